@@ -59,7 +59,7 @@ pub struct MinecraftDecoder {
 }
 
 impl MinecraftDecoder {
-    pub fn new() -> Self {
+    fn new() -> Self {
         Self {
             state: DecodeState::Length(0, 0),
             decompression: None,
@@ -10,7 +10,7 @@ pub struct ProtocolRegistryH {
 }
 
 impl ProtocolRegistryH {
-    pub fn new() -> Self {
+    fn new() -> Self {
         Self {
             id_to_packet: HashMap::new(),
         }
@@ -30,7 +30,7 @@ pub struct ProtocolRegistry {
 }
 
 impl ProtocolRegistry {
-    pub fn new() -> Self {
+    fn new() -> Self {
         Self {
             id_to_packeta: [None; 128]
         }
@@ -0,0 +1,124 @@
+use std::{sync::Arc, thread::available_parallelism};
+
+use bytes::{BufMut, BytesMut};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::{SinkExt, StreamExt};
+use rower::protocol::{
+    codec::{decoder::MinecraftDecoder, encoder::MinecraftEncoder},
+    packet::RawPacket,
+};
+use tokio::{
+    io::DuplexStream,
+    runtime::{self, Runtime},
+    task::JoinSet,
+};
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+// Synthetic load: every backend streams chunk-sized packets to its player through the proxy's
+// codecs. The proxy side decompresses each one and compresses it again for the client, with the
+// thread_local zlib state of MinecraftEncoder/MinecraftDecoder spread over the workers.
+const PLAYERS: usize = 64;
+const PACKETS: usize = 16;
+const PACKET_SIZE: usize = 16 * 1024;
+const THRESHOLD: u32 = 256;
+
+fn payload() -> Arc<[u8]> {
+    // mostly repetitive like chunk sections, with some noise so it does not collapse to nothing
+    let mut seed = 0x2545F491u32;
+    (0..PACKET_SIZE)
+        .map(|i| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            if i % 8 == 0 { seed as u8 } else { (i / 64) as u8 }
+        })
+        .collect()
+}
+
+fn encoder(stream: DuplexStream) -> FramedWrite<DuplexStream, MinecraftEncoder> {
+    let mut encoder = MinecraftEncoder::new();
+    encoder.enable_compression(THRESHOLD);
+    FramedWrite::new(stream, encoder)
+}
+
+fn decoder(stream: DuplexStream) -> FramedRead<DuplexStream, MinecraftDecoder> {
+    let mut decoder = MinecraftDecoder::new();
+    decoder.enable_compression(THRESHOLD, false);
+    FramedRead::new(stream, decoder)
+}
+
+async fn backend(stream: DuplexStream, payload: Arc<[u8]>) {
+    let mut backend = encoder(stream);
+
+    for _ in 0..PACKETS {
+        let mut buffer = BytesMut::with_capacity(PACKET_SIZE + 1);
+        // chunk data
+        buffer.put_u8(0x25);
+        buffer.put_slice(&payload);
+        backend.feed(RawPacket::from_buffer(buffer)).await.unwrap();
+    }
+    backend.flush().await.unwrap();
+}
+
+async fn proxy(from_backend: DuplexStream, to_client: DuplexStream) {
+    let mut backend = decoder(from_backend);
+    let mut client = encoder(to_client);
+
+    while let Some(packet) = backend.next().await {
+        client.send(packet.unwrap()).await.unwrap();
+    }
+}
+
+async fn player(stream: DuplexStream) {
+    let mut client = decoder(stream);
+
+    for _ in 0..PACKETS {
+        let packet = client.next().await.unwrap().unwrap();
+        assert_eq!(packet.buffer.len(), PACKET_SIZE + 1);
+    }
+}
+
+async fn load(payload: Arc<[u8]>) {
+    let mut tasks = JoinSet::new();
+
+    for _ in 0..PLAYERS {
+        let (server, proxy_server) = tokio::io::duplex(64 * 1024);
+        let (proxy_client, client) = tokio::io::duplex(64 * 1024);
+        tasks.spawn(backend(server, payload.clone()));
+        tasks.spawn(proxy(proxy_server, proxy_client));
+        tasks.spawn(player(client));
+    }
+
+    while let Some(result) = tasks.join_next().await {
+        result.unwrap();
+    }
+}
+
+fn runtime(worker_threads: usize) -> Runtime {
+    runtime::Builder::new_multi_thread()
+        .worker_threads(worker_threads)
+        .build()
+        .unwrap()
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("throughput");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes((PLAYERS * PACKETS * PACKET_SIZE) as u64));
+
+    let payload = payload();
+    let cores = available_parallelism().map_or(1, |n| n.get());
+    let thread_counts = (0..).map(|i| 1 << i).take_while(|&n| n < cores).chain([cores]);
+
+    for threads in thread_counts {
+        let runtime = runtime(threads);
+        group.bench_with_input(BenchmarkId::new("workers", threads), &threads, |b, _| {
+            b.iter(|| runtime.block_on(load(payload.clone())))
+        });
+    }
+
+    group.finish()
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,95 @@
+//! Rower plugin that puts "Rower | " in front of the server brand.
+//!
+//! The plugin sees the brand before the proxy's hooks, so with the default hooks a backend
+//! reporting `vanilla` shows up as `Rower | vanilla inside a bike`.
+//!
+//! ```bash
+//! cargo build --release --target wasm32-unknown-unknown
+//! cp target/wasm32-unknown-unknown/release/brand_plugin.wasm ../../plugins/
+//! ```
+
+const PLUGIN_MESSAGE: i32 = 6;
+const NORMAL: i32 = 2;
+
+#[link(wasm_import_module = "rower")]
+extern "C" {
+    fn subscribe(event: i32, priority: i32);
+    fn get_field(name_ptr: *const u8, name_len: usize) -> i64;
+    fn set_field(name_ptr: *const u8, name_len: usize, value_ptr: *const u8, value_len: usize) -> i32;
+}
+
+#[no_mangle]
+pub extern "C" fn rower_abi_version() -> i32 {
+    1
+}
+
+#[no_mangle]
+pub extern "C" fn rower_alloc(len: usize) -> *mut u8 {
+    let mut buffer = Vec::<u8>::with_capacity(len);
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+#[no_mangle]
+pub extern "C" fn rower_init() {
+    unsafe { subscribe(PLUGIN_MESSAGE, NORMAL) }
+}
+
+#[no_mangle]
+pub extern "C" fn rower_on_event(event: i32) {
+    if event != PLUGIN_MESSAGE || get("channel").as_deref() != Some(b"minecraft:brand") {
+        return;
+    }
+    let Some(data) = get("data") else {
+        return;
+    };
+    let Some(brand) = read_string(&data) else {
+        return;
+    };
+
+    let brand = format!("Rower | {}", brand);
+    set("data", &write_string(&brand));
+}
+
+fn get(name: &str) -> Option<Vec<u8>> {
+    let packed = unsafe { get_field(name.as_ptr(), name.len()) };
+    if packed == -1 {
+        return None;
+    }
+    let (ptr, len) = ((packed >> 32) as usize, packed as u32 as usize);
+    // allocated by rower_alloc with exactly this capacity
+    Some(unsafe { Vec::from_raw_parts(ptr as *mut u8, len, len) })
+}
+
+fn set(name: &str, value: &[u8]) -> bool {
+    unsafe { set_field(name.as_ptr(), name.len(), value.as_ptr(), value.len()) == 0 }
+}
+
+/// Minecraft strings are prefixed with their length as a varint.
+fn read_string(data: &[u8]) -> Option<&str> {
+    let mut len = 0usize;
+    for (index, byte) in data.iter().enumerate().take(5) {
+        len |= ((byte & 0x7f) as usize) << (7 * index);
+        if byte & 0x80 == 0 {
+            let rest = &data[index + 1..];
+            return std::str::from_utf8(rest.get(..len)?).ok();
+        }
+    }
+    None
+}
+
+fn write_string(string: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity(string.len() + 5);
+    let mut len = string.len();
+    loop {
+        if len < 0x80 {
+            data.push(len as u8);
+            break;
+        }
+        data.push((len as u8 & 0x7f) | 0x80);
+        len >>= 7;
+    }
+    data.extend_from_slice(string.as_bytes());
+    data
+}
@@ -0,0 +1,25 @@
+#![no_main]
+
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+use rower::protocol::buffer::BufExt;
+
+// first byte picks the reader, the rest is its input
+fuzz_target!(|data: &[u8]| {
+    let Some((&reader, data)) = data.split_first() else {
+        return;
+    };
+    let mut buf = Bytes::copy_from_slice(data);
+
+    let _ = match reader % 9 {
+        0 => buf.get_varint().map(drop),
+        1 => buf.get_bool().map(drop),
+        2 => buf.get_string(32767).map(drop),
+        3 => buf.get_component().map(drop),
+        4 => buf.get_uuid().map(drop),
+        5 => buf.get_bitset().map(drop),
+        6 => buf.get_bytes().map(drop),
+        7 => buf.get_byte_array::<4>().map(drop),
+        _ => buf.get_option(|b| b.get_identifier()).map(drop),
+    };
+});
@@ -0,0 +1,25 @@
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use rower::protocol::codec::decoder::MinecraftDecoder;
+use tokio_util::codec::Decoder;
+
+// first byte picks the mode: 0 uncompressed, otherwise compressed with
+// threshold `byte * 2` and the lowest bit toggling passthrough
+fuzz_target!(|data: &[u8]| {
+    let Some((&mode, data)) = data.split_first() else {
+        return;
+    };
+
+    let mut decoder = MinecraftDecoder::new();
+    if mode != 0 {
+        decoder.enable_compression(mode as u32 * 2, mode & 1 == 1);
+    }
+
+    let mut src = BytesMut::from(data);
+    while let Ok(Some(mut packet)) = decoder.decode(&mut src) {
+        let _ = packet.id();
+        let _ = packet.data();
+    }
+});
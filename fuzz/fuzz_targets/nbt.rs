@@ -0,0 +1,12 @@
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use rower::protocol::nbt::Compound;
+
+fuzz_target!(|data: &[u8]| {
+    let mut buf = data;
+    if let Ok(compound) = Compound::read(&mut buf) {
+        compound.write(&mut BytesMut::new());
+    }
+});
@@ -0,0 +1,55 @@
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use rower::protocol::packet::{
+    handshake::Handshake,
+    login::{
+        Disconnect, EncryptionRequest, EncryptionResponse, LoginPluginRequest,
+        LoginPluginResponse, LoginStart, LoginSuccess, SetCompression,
+    },
+    play::{
+        BossBar, ChatCommand, ChatMessage, JoinGame, KeepAlive, PluginMessage, Respawn, SystemChat,
+    },
+    status::Ping,
+    Packet,
+};
+use rower::protocol::ProtocolVersion;
+use strum::IntoEnumIterator;
+
+fn decode<T: Packet>(mut data: &[u8], version: ProtocolVersion) {
+    if let Ok(packet) = T::from_bytes(&mut data, version) {
+        packet.put_buf(&mut BytesMut::new(), version);
+    }
+}
+
+// first byte picks the packet, second one the protocol version
+fuzz_target!(|data: &[u8]| {
+    let [packet, version, data @ ..] = data else {
+        return;
+    };
+    let Some(version) = ProtocolVersion::iter().nth(*version as usize) else {
+        return;
+    };
+
+    match packet % 18 {
+        0 => decode::<Handshake>(data, version),
+        1 => decode::<LoginStart>(data, version),
+        2 => decode::<LoginSuccess>(data, version),
+        3 => decode::<Disconnect>(data, version),
+        4 => decode::<SetCompression>(data, version),
+        5 => decode::<EncryptionRequest>(data, version),
+        6 => decode::<EncryptionResponse>(data, version),
+        7 => decode::<LoginPluginRequest>(data, version),
+        8 => decode::<LoginPluginResponse>(data, version),
+        9 => decode::<PluginMessage>(data, version),
+        10 => decode::<JoinGame>(data, version),
+        11 => decode::<Respawn>(data, version),
+        12 => decode::<BossBar>(data, version),
+        13 => decode::<ChatCommand>(data, version),
+        14 => decode::<ChatMessage>(data, version),
+        15 => decode::<SystemChat>(data, version),
+        16 => decode::<KeepAlive>(data, version),
+        _ => decode::<Ping>(data, version),
+    }
+});
@@ -0,0 +1,356 @@
+//! Bans and the whitelist, checked when a player logs in.
+//!
+//! They are kept in a TOML file that is reloaded when it changes on disk and written back when a
+//! command changes them. Without a file they only live in memory.
+
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, ensure, Result};
+use tracing::{info, warn};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::component::Component;
+use crate::protocol::wrappers::Player;
+
+/// How often the file is checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Contents of the access list file.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct AccessList {
+    /// Only players on `whitelisted` can join.
+    pub whitelist: bool,
+    /// Usernames or UUIDs.
+    pub whitelisted: Vec<String>,
+    pub bans: Vec<Ban>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Ban {
+    #[serde(flatten)]
+    pub target: BanTarget,
+    pub reason: Component,
+    /// Unix time in seconds the ban ends at, never when missing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+    /// Who issued the ban.
+    pub source: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BanTarget {
+    Uuid(Uuid),
+    Username(String),
+    Ip(Cidr),
+}
+
+/// An IP address, or a network of them like `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.address, address.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address.parse::<IpAddr>()?, Some(prefix.parse::<u8>()?)),
+            None => (s.parse::<IpAddr>()?, None),
+        };
+        let address = address.to_canonical();
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        ensure!(prefix <= max, "prefix of {} is longer than the address", s);
+        Ok(Self { address, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.address, self.prefix) {
+            (IpAddr::V4(address), 32) => write!(f, "{}", address),
+            (IpAddr::V6(address), 128) => write!(f, "{}", address),
+            (address, prefix) => write!(f, "{}/{}", address, prefix),
+        }
+    }
+}
+
+impl Serialize for Cidr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cidr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl BanTarget {
+    /// An IP or CIDR, a UUID or else a username.
+    pub fn parse(target: &str) -> Self {
+        if let Ok(cidr) = target.parse() {
+            BanTarget::Ip(cidr)
+        } else if let Ok(uuid) = Uuid::parse_str(target) {
+            BanTarget::Uuid(uuid)
+        } else {
+            BanTarget::Username(target.to_owned())
+        }
+    }
+
+    /// Whether both target the same players, usernames ignore case.
+    pub fn same(&self, other: &BanTarget) -> bool {
+        match (self, other) {
+            (BanTarget::Username(a), BanTarget::Username(b)) => a.eq_ignore_ascii_case(b),
+            (a, b) => a == b,
+        }
+    }
+
+    pub fn matches(&self, player: &Player) -> bool {
+        match self {
+            BanTarget::Uuid(uuid) => *uuid == player.uuid,
+            BanTarget::Username(username) => username.eq_ignore_ascii_case(&player.username),
+            BanTarget::Ip(cidr) => cidr.contains(player.address.ip()),
+        }
+    }
+}
+
+impl fmt::Display for BanTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BanTarget::Uuid(uuid) => write!(f, "{}", uuid),
+            BanTarget::Username(username) => write!(f, "{}", username),
+            BanTarget::Ip(cidr) => write!(f, "{}", cidr),
+        }
+    }
+}
+
+impl Ban {
+    pub fn is_active(&self) -> bool {
+        self.expires.is_none_or(|expires| expires > now())
+    }
+
+    /// What a banned player sees when they are kicked or try to join.
+    pub fn message(&self) -> Component {
+        match self.expires {
+            Some(expires) => {
+                let left = Duration::from_secs(expires.saturating_sub(now()));
+                let expiry = format!("\n\nExpires in {}", format_duration(left));
+                self.reason.clone().push(Component::text(&expiry))
+            }
+            None => self.reason.clone(),
+        }
+    }
+}
+
+struct State {
+    list: AccessList,
+    /// Modification time of the file when it was last read or written.
+    modified: Option<SystemTime>,
+}
+
+pub struct Access {
+    path: Option<PathBuf>,
+    state: Mutex<State>,
+}
+
+impl Access {
+    /// An access list that is not backed by a file.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            state: Mutex::new(State {
+                list: AccessList::default(),
+                modified: None,
+            }),
+        }
+    }
+
+    /// Loads the file at `path`, a missing one is created when something is added.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let (list, modified) = read(&path)?;
+        Ok(Self {
+            path: Some(path),
+            state: Mutex::new(State { list, modified }),
+        })
+    }
+
+    pub fn list(&self) -> AccessList {
+        self.state.lock().unwrap().list.clone()
+    }
+
+    /// Why `player` can't join, if they can't.
+    pub fn check(&self, player: &Player) -> Option<Component> {
+        let state = self.state.lock().unwrap();
+        let list = &state.list;
+        if let Some(ban) = list.bans.iter().find(|ban| ban.is_active() && ban.target.matches(player)) {
+            return Some(ban.message());
+        }
+        if list.whitelist && !is_whitelisted(list, player) {
+            return Some(Component::text("You are not whitelisted on this server"));
+        }
+        None
+    }
+
+    /// Adds a ban, replacing an earlier one of the same target.
+    pub fn ban(&self, ban: Ban) -> Result<()> {
+        self.update(|list| {
+            list.bans.retain(|existing| !existing.target.same(&ban.target));
+            list.bans.push(ban);
+        })
+    }
+
+    /// Lifts the bans of `target`, false when there were none.
+    pub fn unban(&self, target: &BanTarget) -> Result<bool> {
+        self.update(|list| {
+            let before = list.bans.len();
+            list.bans.retain(|ban| !ban.target.same(target));
+            list.bans.len() != before
+        })
+    }
+
+    pub fn set_whitelist(&self, enabled: bool) -> Result<()> {
+        self.update(|list| list.whitelist = enabled)
+    }
+
+    /// Adds a username or UUID to the whitelist, false when it was on it already.
+    pub fn whitelist_add(&self, entry: &str) -> Result<bool> {
+        self.update(|list| {
+            if list.whitelisted.iter().any(|existing| existing.eq_ignore_ascii_case(entry)) {
+                return false;
+            }
+            list.whitelisted.push(entry.to_owned());
+            true
+        })
+    }
+
+    /// Removes a username or UUID from the whitelist, false when it was not on it.
+    pub fn whitelist_remove(&self, entry: &str) -> Result<bool> {
+        self.update(|list| {
+            let before = list.whitelisted.len();
+            list.whitelisted.retain(|existing| !existing.eq_ignore_ascii_case(entry));
+            list.whitelisted.len() != before
+        })
+    }
+
+    /// Reads the file again if it changed since it was last read or written.
+    pub fn reload(&self) -> Result<bool> {
+        let Some(path) = &self.path else {
+            return Ok(false);
+        };
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        let mut state = self.state.lock().unwrap();
+        if modified == state.modified {
+            return Ok(false);
+        }
+        let (list, modified) = read(path)?;
+        *state = State { list, modified };
+        Ok(true)
+    }
+
+    /// Reloads the file whenever it changes, until `shutdown`.
+    pub(crate) async fn watch(self: Arc<Self>, shutdown: CancellationToken) {
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.cancelled() => return,
+            }
+            match self.reload() {
+                Ok(true) => info!("Reloaded the access list"),
+                Ok(false) => {}
+                Err(err) => warn!("Failed to reload the access list: {:#}", err),
+            }
+        }
+    }
+
+    /// Changes the list, dropping expired bans, and writes it to the file.
+    fn update<T>(&self, change: impl FnOnce(&mut AccessList) -> T) -> Result<T> {
+        let mut state = self.state.lock().unwrap();
+        let result = change(&mut state.list);
+        state.list.bans.retain(Ban::is_active);
+
+        if let Some(path) = &self.path {
+            fs::write(path, toml::to_string(&state.list)?)?;
+            state.modified = fs::metadata(path)?.modified().ok();
+        }
+        Ok(result)
+    }
+}
+
+fn read(path: &PathBuf) -> Result<(AccessList, Option<SystemTime>)> {
+    let Ok(metadata) = fs::metadata(path) else {
+        return Ok((AccessList::default(), None));
+    };
+    let list = toml::from_str(&fs::read_to_string(path)?)
+        .map_err(|err| anyhow!("{}: {}", path.display(), err))?;
+    Ok((list, metadata.modified().ok()))
+}
+
+fn is_whitelisted(list: &AccessList, player: &Player) -> bool {
+    list.whitelisted.iter().any(|entry| {
+        entry.eq_ignore_ascii_case(&player.username)
+            || Uuid::parse_str(entry).is_ok_and(|uuid| uuid == player.uuid)
+    })
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Parses durations like `30s`, `15m`, `12h`, `7d` or `2w`.
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let unit = duration.chars().last()?;
+    let amount: u64 = duration[..duration.len() - unit.len_utf8()].parse().ok()?;
+    let seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(amount.checked_mul(seconds)?))
+}
+
+/// The duration in its largest whole unit, rounded up.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs().max(1);
+    for (unit, size) in [("day", 24 * 60 * 60), ("hour", 60 * 60), ("minute", 60)] {
+        if seconds >= size {
+            let amount = seconds.div_ceil(size);
+            return format!("{} {}{}", amount, unit, if amount == 1 { "" } else { "s" });
+        }
+    }
+    format!("{} second{}", seconds, if seconds == 1 { "" } else { "s" })
+}
@@ -0,0 +1,294 @@
+//! The admin API, JSON over HTTP on a local address for dashboards and bots.
+//!
+//! Every request needs an `Authorization: Bearer <token>` header. Messages are [`Component`]s in
+//! their JSON form, errors come back as `{"error": "..."}`.
+//!
+//! - `GET /players`, the players online and their servers
+//! - `POST /players/{username}/kick` with `{"reason": component}`, the reason is optional
+//! - `POST /players/{username}/send` with `{"server": "name or address"}`
+//! - `GET /servers`, the named servers with their player counts, pinged to see if they are up
+//! - `POST /broadcast` with `{"message": component}`
+//! - `GET /bans`, `POST /bans` with `{"target": "...", "reason": component, "duration": "7d"}`
+//!   (reason and duration are optional) and `DELETE /bans/{target}`
+//! - `POST /reload`, reads the config and the access list again, `{"config": bool, "access_list": bool}`
+//!   tell which of them changed
+//! - `GET /metrics`, the [`crate::metrics`] in the Prometheus text format
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use axum::extract::{Path, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::access::{now, parse_duration, Access, Ban, BanTarget};
+use crate::commands::{ban_and_kick, broadcast};
+use crate::component::Component;
+use crate::config::LiveConfig;
+use crate::metrics::METRICS;
+use crate::players::{OnlinePlayer, Players};
+use crate::protocol::codec::connection::Connection;
+use crate::protocol::packet::handshake::{Handshake, NextState};
+use crate::protocol::packet::status::{Ping, StatusRequest, StatusResponse};
+use crate::protocol::{Direction, ProtocolVersion, State as ProtocolState};
+
+/// How long a server has to answer the status ping of `GET /servers`.
+const PING_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Clone)]
+pub(crate) struct Api {
+    pub(crate) config: Arc<LiveConfig>,
+    pub(crate) players: Arc<Players>,
+    pub(crate) access: Arc<Access>,
+    pub(crate) token: Arc<str>,
+}
+
+/// Serves the API on `listener` until `shutdown`.
+pub(crate) async fn serve(listener: TcpListener, api: Api, shutdown: CancellationToken) -> Result<()> {
+    let router = Router::new()
+        .route("/players", get(players))
+        .route("/players/{username}/kick", post(kick))
+        .route("/players/{username}/send", post(send))
+        .route("/servers", get(servers))
+        .route("/broadcast", post(broadcast_message))
+        .route("/bans", get(bans).post(ban))
+        .route("/bans/{target}", delete(unban))
+        .route("/reload", post(reload))
+        .route("/metrics", get(metrics))
+        .layer(middleware::from_fn_with_state(api.clone(), authorize))
+        .with_state(api);
+
+    axum::serve(listener, router)
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await?;
+    Ok(())
+}
+
+struct Error(StatusCode, String);
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({ "error": self.1 });
+        (self.0, Json(body)).into_response()
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        Error(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", err))
+    }
+}
+
+async fn authorize(State(api): State<Api>, request: Request, next: Next) -> Result<Response, Error> {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match token {
+        Some(token) if same(token.as_bytes(), api.token.as_bytes()) => Ok(next.run(request).await),
+        _ => Err(Error(StatusCode::UNAUTHORIZED, "Missing or wrong token".to_owned())),
+    }
+}
+
+/// Compares without stopping at the first difference, so timing doesn't give the token away.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[derive(Serialize)]
+struct PlayerInfo {
+    username: String,
+    uuid: Uuid,
+    address: SocketAddr,
+    server: String,
+}
+
+async fn players(State(api): State<Api>) -> Json<Vec<PlayerInfo>> {
+    let config = api.config.get();
+    let mut players = api
+        .players
+        .all()
+        .into_iter()
+        .map(|online| PlayerInfo {
+            server: config.server_name(online.server),
+            username: online.player.username,
+            uuid: online.player.uuid,
+            address: online.player.address,
+        })
+        .collect::<Vec<_>>();
+    players.sort_unstable_by_key(|player| player.username.to_lowercase());
+    Json(players)
+}
+
+fn find(api: &Api, username: &str) -> Result<OnlinePlayer, Error> {
+    api.players
+        .find(username)
+        .ok_or_else(|| Error(StatusCode::NOT_FOUND, format!("{} is not online", username)))
+}
+
+#[derive(Deserialize)]
+struct Kick {
+    reason: Option<Component>,
+}
+
+async fn kick(
+    State(api): State<Api>,
+    Path(username): Path<String>,
+    Json(kick): Json<Kick>,
+) -> Result<StatusCode, Error> {
+    let reason = kick.reason.unwrap_or_else(|| Component::text("You were kicked from the server"));
+    find(&api, &username)?.disconnect(reason)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+struct Send {
+    server: String,
+}
+
+async fn send(
+    State(api): State<Api>,
+    Path(username): Path<String>,
+    Json(send): Json<Send>,
+) -> Result<StatusCode, Error> {
+    let address = api
+        .config
+        .get()
+        .server(&send.server)
+        .or_else(|| send.server.parse().ok())
+        .ok_or_else(|| Error(StatusCode::NOT_FOUND, format!("There is no server called {}", send.server)))?;
+    find(&api, &username)?.connect(address)?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Serialize)]
+struct ServerInfo {
+    name: String,
+    address: SocketAddr,
+    players: usize,
+    online: bool,
+    /// Round trip of the status ping, when the server answered it.
+    latency_ms: Option<u64>,
+}
+
+async fn servers(State(api): State<Api>) -> Json<Vec<ServerInfo>> {
+    let players = api.players.all();
+    let pings = api.config.get().named_servers().into_iter().map(|(name, address)| {
+        let players = players.iter().filter(|online| online.server == address).count();
+        async move {
+            let latency = tokio::time::timeout(PING_TIMEOUT, ping(address)).await;
+            let latency = latency.ok().and_then(Result::ok);
+            ServerInfo {
+                name,
+                address,
+                players,
+                online: latency.is_some(),
+                latency_ms: latency.map(|latency| latency.as_millis() as u64),
+            }
+        }
+    });
+    Json(futures::future::join_all(pings).await)
+}
+
+/// Pings `address` like the server list does and returns the round trip.
+async fn ping(address: SocketAddr) -> Result<Duration> {
+    let version = ProtocolVersion::V1_20_3;
+    let mut server = Connection::connect_to(address, version, Direction::Serverbound, None).await?;
+    server
+        .queue_packet(Handshake {
+            protocol: version.into(),
+            server_address: address.ip().to_string(),
+            port: address.port(),
+            state: NextState::Status,
+        })
+        .await?;
+    server.change_state(ProtocolState::Status);
+    server.send_packet(StatusRequest).await?;
+    server.recv_packet::<StatusResponse>().await?;
+
+    let started = Instant::now();
+    server.send_packet(Ping(0)).await?;
+    server.recv_packet::<Ping>().await?;
+    Ok(started.elapsed())
+}
+
+#[derive(Deserialize)]
+struct Broadcast {
+    message: Component,
+}
+
+#[derive(Serialize)]
+struct Sent {
+    players: usize,
+}
+
+async fn broadcast_message(State(api): State<Api>, Json(body): Json<Broadcast>) -> Json<Sent> {
+    Json(Sent {
+        players: broadcast(&api.players, &body.message),
+    })
+}
+
+async fn bans(State(api): State<Api>) -> Json<Vec<Ban>> {
+    Json(api.access.list().bans)
+}
+
+#[derive(Deserialize)]
+struct NewBan {
+    target: String,
+    reason: Option<Component>,
+    /// Like `30m`, `12h` or `7d`, permanent without one.
+    duration: Option<String>,
+}
+
+async fn ban(State(api): State<Api>, Json(body): Json<NewBan>) -> Result<(StatusCode, Json<Ban>), Error> {
+    let duration = match &body.duration {
+        Some(duration) => Some(parse_duration(duration).ok_or_else(|| {
+            Error(StatusCode::BAD_REQUEST, format!("{} is not a duration like 12h or 7d", duration))
+        })?),
+        None => None,
+    };
+    let ban = Ban {
+        target: BanTarget::parse(&body.target),
+        reason: body.reason.unwrap_or_else(|| Component::text("You are banned from this server")),
+        expires: duration.map(|duration| now() + duration.as_secs()),
+        source: "API".to_owned(),
+    };
+    ban_and_kick(&api.access, &api.players, ban.clone())?;
+    Ok((StatusCode::CREATED, Json(ban)))
+}
+
+async fn unban(State(api): State<Api>, Path(target): Path<String>) -> Result<StatusCode, Error> {
+    let target = BanTarget::parse(&target);
+    match api.access.unban(&target)? {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err(Error(StatusCode::NOT_FOUND, format!("{} is not banned", target))),
+    }
+}
+
+#[derive(Serialize)]
+struct Reloaded {
+    config: bool,
+    access_list: bool,
+}
+
+async fn reload(State(api): State<Api>) -> Result<Json<Reloaded>, Error> {
+    Ok(Json(Reloaded {
+        config: api.config.reload()?,
+        access_list: api.access.reload()?,
+    }))
+}
+
+async fn metrics(State(api): State<Api>) -> impl IntoResponse {
+    let content_type = "text/plain; version=0.0.4; charset=utf-8";
+    ([(header::CONTENT_TYPE, content_type)], METRICS.render(&api.players, &api.config.get()))
+}
@@ -0,0 +1,72 @@
+//! Prints the packets of captures with their names and decoded fields, see `rower::inspect`.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::{Parser, ValueEnum};
+
+use rower::capture::Records;
+use rower::inspect::{self, Filter};
+use rower::protocol::{Direction, State};
+
+#[derive(Parser)]
+#[command(about = "Prints the packets of packet captures in a readable form")]
+struct Args {
+    /// Capture files, `.rwcap`
+    #[arg(required = true)]
+    captures: Vec<PathBuf>,
+    /// Only show packets going this way
+    #[arg(long, value_enum)]
+    direction: Option<DirectionArg>,
+    /// Only show packets sent in this state
+    #[arg(long, value_enum)]
+    state: Option<StateArg>,
+    /// Only show packets with this name, like `JoinGame`, can be given more than once
+    #[arg(long = "packet")]
+    packets: Vec<String>,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum DirectionArg {
+    Serverbound,
+    Clientbound,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum StateArg {
+    Handshake,
+    Status,
+    Login,
+    Play,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let filter = Filter {
+        direction: args.direction.map(|direction| match direction {
+            DirectionArg::Serverbound => Direction::Serverbound,
+            DirectionArg::Clientbound => Direction::Clientbound,
+        }),
+        state: args.state.map(|state| match state {
+            StateArg::Handshake => State::Handshake,
+            StateArg::Status => State::Status,
+            StateArg::Login => State::Login,
+            StateArg::Play => State::Play,
+        }),
+        packets: args.packets,
+    };
+
+    for path in &args.captures {
+        println!("{}:", path.display());
+        for (index, record) in Records::open(path)?.enumerate() {
+            match record {
+                Ok(record) if filter.matches(&record) => {
+                    println!("{}", inspect::describe(index, &record))
+                }
+                Ok(_) => {}
+                Err(err) => println!("stopped after packet #{}: {:#}", index, err),
+            }
+        }
+    }
+    Ok(())
+}
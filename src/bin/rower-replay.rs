@@ -0,0 +1,83 @@
+//! Feeds packet captures back through the codecs and registries and reports the packets that
+//! fail to decode, see `rower::capture`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use anyhow::Result;
+use bytes::BytesMut;
+use clap::Parser;
+use tokio_util::codec::Encoder;
+
+use rower::capture::{self, Record, Records};
+use rower::protocol::codec::encoder::MinecraftEncoder;
+use rower::protocol::packet::RawPacket;
+
+#[derive(Parser)]
+#[command(about = "Replays packet captures offline and reports the packets that fail to decode")]
+struct Args {
+    /// Capture files, `.rwcap`
+    #[arg(required = true)]
+    captures: Vec<PathBuf>,
+    /// Also write every packet as a seed of the `decoder` fuzz target to this directory,
+    /// e.g. `fuzz/corpus/decoder`
+    #[arg(long)]
+    seeds: Option<PathBuf>,
+}
+
+fn main() -> Result<ExitCode> {
+    let args = Args::parse();
+    let mut failed = false;
+
+    for path in &args.captures {
+        let mut records = Vec::new();
+        let mut cut_short = None;
+        for record in Records::open(path)? {
+            match record {
+                Ok(record) => records.push(record),
+                Err(err) => cut_short = Some(err),
+            }
+        }
+        let count = records.len();
+        if let Some(dir) = &args.seeds {
+            write_seeds(dir, path, &records)?;
+        }
+        let failures = capture::replay(records);
+
+        println!("{}: {} packet(s), {} failed", path.display(), count, failures.len());
+        for failure in &failures {
+            let record = &failure.record;
+            println!(
+                "  #{} at {:.3}s, {:?} {:?} {:?} packet {:#04x} ({} bytes): {:#}",
+                failure.index,
+                record.time.as_secs_f64(),
+                record.direction,
+                record.state,
+                record.protocol,
+                record.id(),
+                record.packet.len(),
+                failure.error
+            );
+        }
+        if let Some(err) = cut_short {
+            println!("  stopped after packet #{}: {:#}", count, err);
+        }
+        failed |= !failures.is_empty();
+    }
+
+    Ok(if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS })
+}
+
+/// Writes each record as one uncompressed frame, behind the mode byte the `decoder` target reads.
+fn write_seeds(dir: &Path, capture: &Path, records: &[Record]) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let name = capture.file_stem().unwrap_or_default().to_string_lossy();
+    for (index, record) in records.iter().enumerate() {
+        let mut seed = BytesMut::from(&[0][..]);
+        let packet = RawPacket::from_buffer(BytesMut::from(&record.packet[..]));
+        MinecraftEncoder::new().encode(packet, &mut seed)?;
+        fs::write(dir.join(format!("{}-{}", name, index)), seed)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,259 @@
+//! The BungeeCord plugin messaging channel, `bungeecord:main`.
+//!
+//! Backend plugins write a subchannel name followed by its arguments with Java's `DataOutput`
+//! (`writeUTF` strings and big-endian ints), answers go back through the same player's server
+//! in the same format. Messages sent by clients are dropped.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tracing::warn;
+
+use crate::channels::{ChannelMessage, Channels};
+use crate::component::Component;
+use crate::config::{Config, LiveConfig};
+use crate::players::{OnlinePlayer, Players};
+use crate::protocol::packet::play::{PluginMessage, SystemChat};
+use crate::protocol::Direction;
+
+pub const CHANNEL: &str = "bungeecord:main";
+
+pub(crate) fn subscribe(channels: &mut Channels, players: Arc<Players>, config: Arc<LiveConfig>) {
+    channels.subscribe(CHANNEL, move |message: ChannelMessage| {
+        let (players, config) = (players.clone(), config.clone());
+        async move {
+            if message.direction == Direction::Serverbound {
+                return;
+            }
+            let username = message.player.player.username.clone();
+            if let Err(err) = handle(&message.player, message.data, &players, &config.get()) {
+                warn!("Invalid BungeeCord message from the server of {}: {}", username, err);
+            }
+        }
+    });
+}
+
+fn handle(sender: &OnlinePlayer, mut data: Bytes, players: &Players, config: &Config) -> Result<()> {
+    let subchannel = get_utf(&mut data)?;
+    match subchannel.as_str() {
+        "Connect" => {
+            let server = get_utf(&mut data)?;
+            sender.connect(server_address(config, &server)?)?;
+        }
+        "ConnectOther" => {
+            let username = get_utf(&mut data)?;
+            let server = get_utf(&mut data)?;
+            if let Some(player) = players.find(&username) {
+                player.connect(server_address(config, &server)?)?;
+            }
+        }
+        "IP" => {
+            let mut reply = reply(&subchannel)?;
+            put_utf(&mut reply, &sender.player.address.ip().to_string())?;
+            reply.put_i32(sender.player.address.port().into());
+            respond(sender, reply)?;
+        }
+        "PlayerCount" | "PlayerList" => {
+            let server = get_utf(&mut data)?;
+            let online = on_server(players, config, &server)?;
+            let mut reply = reply(&subchannel)?;
+            put_utf(&mut reply, &server)?;
+            if subchannel == "PlayerCount" {
+                reply.put_i32(online.len() as i32);
+            } else {
+                let names = online.iter().map(|online| online.player.username.as_str());
+                put_utf(&mut reply, &names.collect::<Vec<_>>().join(", "))?;
+            }
+            respond(sender, reply)?;
+        }
+        "GetServers" => {
+            let servers = config.named_servers().into_keys().collect::<Vec<_>>();
+            let mut reply = reply(&subchannel)?;
+            put_utf(&mut reply, &servers.join(", "))?;
+            respond(sender, reply)?;
+        }
+        "GetServer" => {
+            let mut reply = reply(&subchannel)?;
+            put_utf(&mut reply, &config.server_name(sender.server))?;
+            respond(sender, reply)?;
+        }
+        "Message" | "MessageRaw" => {
+            let username = get_utf(&mut data)?;
+            let text = get_utf(&mut data)?;
+            let content = if subchannel == "Message" {
+                Component::legacy(&text)
+            } else {
+                serde_json::from_str(&text)?
+            };
+            let targets = match username.as_str() {
+                "ALL" => players.all(),
+                _ => players.find(&username).into_iter().collect(),
+            };
+            for target in targets {
+                let _ = target.send_to_client(SystemChat {
+                    content: content.clone(),
+                    overlay: false,
+                });
+            }
+        }
+        "UUID" => {
+            let mut reply = reply(&subchannel)?;
+            put_utf(&mut reply, &sender.player.uuid.simple().to_string())?;
+            respond(sender, reply)?;
+        }
+        "UUIDOther" => {
+            let username = get_utf(&mut data)?;
+            if let Some(player) = players.find(&username) {
+                let mut reply = reply(&subchannel)?;
+                put_utf(&mut reply, &player.player.username)?;
+                put_utf(&mut reply, &player.player.uuid.simple().to_string())?;
+                respond(sender, reply)?;
+            }
+        }
+        "Forward" => {
+            let target = get_utf(&mut data)?;
+            let forward = forwarded(&mut data)?;
+            let servers = match target.as_str() {
+                "ALL" | "ONLINE" => config
+                    .named_servers()
+                    .into_values()
+                    .filter(|server| *server != sender.server)
+                    .collect(),
+                server => vec![server_address(config, server)?],
+            };
+            // a server can only be reached through one of its players
+            for server in servers {
+                if let Some(player) = players.all().into_iter().find(|online| online.server == server) {
+                    respond(&player, forward.clone())?;
+                }
+            }
+        }
+        "ForwardToPlayer" => {
+            let username = get_utf(&mut data)?;
+            let forward = forwarded(&mut data)?;
+            if let Some(player) = players.find(&username) {
+                respond(&player, forward)?;
+            }
+        }
+        "KickPlayer" => {
+            let username = get_utf(&mut data)?;
+            let reason = get_utf(&mut data)?;
+            if let Some(player) = players.find(&username) {
+                player.disconnect(Component::legacy(&reason))?;
+            }
+        }
+        _ => bail!("unknown subchannel {}", subchannel),
+    }
+    Ok(())
+}
+
+fn server_address(config: &Config, name: &str) -> Result<SocketAddr> {
+    match config.server(name) {
+        Some(address) => Ok(address),
+        None => bail!("unknown server {}", name),
+    }
+}
+
+/// Players on the server called `name`, or everyone for `ALL`.
+fn on_server(players: &Players, config: &Config, name: &str) -> Result<Vec<OnlinePlayer>> {
+    let mut online = players.all();
+    if name != "ALL" {
+        let server = server_address(config, name)?;
+        online.retain(|online| online.server == server);
+    }
+    online.sort_by(|a, b| a.player.username.cmp(&b.player.username));
+    Ok(online)
+}
+
+/// The subchannel and payload of a forward, which reach the other server as they are.
+fn forwarded(data: &mut Bytes) -> Result<BytesMut> {
+    let subchannel = get_utf(data)?;
+    ensure!(data.remaining() >= 2, "missing forward length");
+    let len = data.get_u16() as usize;
+    ensure!(data.remaining() >= len, "forward payload is shorter than its length");
+
+    let mut forward = reply(&subchannel)?;
+    forward.put_u16(len as u16);
+    forward.put(data.split_to(len));
+    Ok(forward)
+}
+
+fn reply(subchannel: &str) -> Result<BytesMut> {
+    let mut reply = BytesMut::new();
+    put_utf(&mut reply, subchannel)?;
+    Ok(reply)
+}
+
+fn respond(player: &OnlinePlayer, data: BytesMut) -> Result<()> {
+    player.send_to_server(PluginMessage {
+        channel: CHANNEL.to_owned(),
+        data: data.freeze(),
+    })
+}
+
+/// Reads a string written by `DataOutput.writeUTF`: an unsigned short length and the string in
+/// Java's modified UTF-8, where NUL takes two bytes and other characters outside the BMP are
+/// surrogate pairs of three bytes each.
+fn get_utf(data: &mut Bytes) -> Result<String> {
+    ensure!(data.remaining() >= 2, "missing string length");
+    let len = data.get_u16() as usize;
+    ensure!(data.remaining() >= len, "string is shorter than its length");
+    let bytes = data.split_to(len);
+
+    let mut units = Vec::with_capacity(len);
+    let mut bytes = bytes.iter().copied();
+    while let Some(byte) = bytes.next() {
+        let unit = match byte {
+            0x01..=0x7f => byte as u16,
+            0xc0..=0xdf => ((byte & 0x1f) as u16) << 6 | continuation(&mut bytes)?,
+            0xe0..=0xef => {
+                let high = continuation(&mut bytes)?;
+                ((byte & 0x0f) as u16) << 12 | high << 6 | continuation(&mut bytes)?
+            }
+            _ => bail!("malformed modified UTF-8"),
+        };
+        units.push(unit);
+    }
+    Ok(String::from_utf16(&units)?)
+}
+
+/// The six bits of the next byte of a multi-byte character.
+fn continuation(bytes: &mut impl Iterator<Item = u8>) -> Result<u16> {
+    match bytes.next() {
+        Some(byte) if byte & 0xc0 == 0x80 => Ok((byte & 0x3f) as u16),
+        _ => bail!("malformed modified UTF-8"),
+    }
+}
+
+/// Writes `value` like `DataOutput.writeUTF`, failing like it for more than 65535 encoded bytes.
+fn put_utf(buf: &mut BytesMut, value: &str) -> Result<()> {
+    let len = value
+        .encode_utf16()
+        .map(|unit| match unit {
+            0x01..=0x7f => 1,
+            0x00 | 0x80..=0x7ff => 2,
+            _ => 3,
+        })
+        .sum::<usize>();
+    ensure!(len <= u16::MAX as usize, "string of {} bytes is too long", len);
+
+    buf.reserve(2 + len);
+    buf.put_u16(len as u16);
+    for unit in value.encode_utf16() {
+        match unit {
+            0x01..=0x7f => buf.put_u8(unit as u8),
+            0x00 | 0x80..=0x7ff => {
+                buf.put_u8(0xc0 | (unit >> 6) as u8);
+                buf.put_u8(0x80 | (unit & 0x3f) as u8);
+            }
+            _ => {
+                buf.put_u8(0xe0 | (unit >> 12) as u8);
+                buf.put_u8(0x80 | (unit >> 6 & 0x3f) as u8);
+                buf.put_u8(0x80 | (unit & 0x3f) as u8);
+            }
+        }
+    }
+    Ok(())
+}
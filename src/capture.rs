@@ -0,0 +1,326 @@
+//! Packet captures, to see what a player and the proxy actually sent each other.
+//!
+//! A capture holds every packet a client connection reads or writes once its login got past the
+//! access list and the anti-bot verification, with the time, direction, state and protocol
+//! version it was sent in. Each captured connection gets a file of its own,
+//! `<username>-<unix millis>.rwcap`, or `<uuid>-<unix millis>.rwcap` when the username is not a
+//! valid Minecraft one. Packets are stored uncompressed and decrypted, so they can be [`replay`]ed
+//! through the codecs and registries without the proxy.
+//!
+//! The file starts with [`MAGIC`], a format version byte and the start time in unix microseconds,
+//! followed by records:
+//!
+//! | field     | encoding                                                        |
+//! |-----------|-----------------------------------------------------------------|
+//! | time      | `u64`, microseconds since the start                             |
+//! | flags     | `u8`, bit 0 set for clientbound, bits 1-2 the state             |
+//! | protocol  | VarInt protocol number                                          |
+//! | length    | VarInt length of the packet                                     |
+//! | packet    | the id and data                                                 |
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, ensure, Context, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+use tracing::warn;
+
+use crate::protocol::buffer::{BufExt, BufMutExt};
+use crate::protocol::codec::connection::decode_packet;
+use crate::protocol::codec::decoder::MinecraftDecoder;
+use crate::protocol::codec::encoder::MinecraftEncoder;
+use crate::protocol::codec::registry::{get_protocol_registry, ProtocolRegistry};
+use crate::protocol::packet::login::SetCompression;
+use crate::protocol::packet::{PacketType, RawPacket};
+use crate::protocol::wrappers::Player;
+use crate::protocol::{codec, Direction, ProtocolVersion, State};
+
+pub const MAGIC: &[u8; 5] = b"RWCAP";
+const FORMAT_VERSION: u8 = 1;
+pub const EXTENSION: &str = "rwcap";
+
+/// Which players are captured and where their files go.
+#[derive(Debug, Clone)]
+pub struct CaptureSettings {
+    pub dir: PathBuf,
+    /// Usernames (ignoring case) to capture, everyone when empty.
+    pub players: Vec<String>,
+}
+
+impl CaptureSettings {
+    pub(crate) fn wants(&self, username: &str) -> bool {
+        self.players.is_empty()
+            || self
+                .players
+                .iter()
+                .any(|player| player.eq_ignore_ascii_case(username))
+    }
+
+    /// Starts the capture file of `player`.
+    pub(crate) fn start(&self, player: &Player) -> Result<Capture> {
+        fs::create_dir_all(&self.dir)?;
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        // the username comes straight from the client, only a valid one makes it into the path
+        let name = match valid_username(&player.username) {
+            true => player.username.clone(),
+            false => player.uuid.to_string(),
+        };
+        let path = self.dir.join(format!("{}-{}.{}", name, millis, EXTENSION));
+        ensure!(
+            path.parent() == Some(self.dir.as_path()),
+            "{} is outside of {}",
+            path.display(),
+            self.dir.display()
+        );
+        Capture::create(path)
+    }
+}
+
+fn valid_username(username: &str) -> bool {
+    (1..=16).contains(&username.len())
+        && username
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+}
+
+/// A capture file being written, shared by both halves of a connection.
+pub struct Capture {
+    path: PathBuf,
+    started: Instant,
+    file: Mutex<Option<BufWriter<File>>>,
+}
+
+impl Capture {
+    pub fn create(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = File::create(&path).with_context(|| format!("{}", path.display()))?;
+        let mut file = BufWriter::new(file);
+        let started = SystemTime::now().duration_since(UNIX_EPOCH)?;
+
+        file.write_all(MAGIC)?;
+        file.write_all(&[FORMAT_VERSION])?;
+        file.write_all(&(started.as_micros() as u64).to_be_bytes())?;
+        Ok(Self {
+            path,
+            started: Instant::now(),
+            file: Mutex::new(Some(file)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `packet`, going in `direction`. A capture that fails to write stops, the
+    /// connection goes on without it.
+    pub fn record(
+        &self,
+        direction: Direction,
+        state: State,
+        protocol: ProtocolVersion,
+        packet: &RawPacket,
+    ) {
+        let mut file = self.file.lock().unwrap();
+        let Some(writer) = file.as_mut() else {
+            return;
+        };
+        let time = self.started.elapsed();
+        if let Err(err) = write_record(writer, time, direction, state, protocol, packet) {
+            warn!("Stopped capturing to {}: {:#}", self.path.display(), err);
+            *file = None;
+        }
+    }
+}
+
+fn write_record(
+    writer: &mut impl Write,
+    time: Duration,
+    direction: Direction,
+    state: State,
+    protocol: ProtocolVersion,
+    packet: &RawPacket,
+) -> Result<()> {
+    let decompressed;
+    let packet = match packet.data_length() {
+        Some(data_length) => {
+            decompressed = codec::decompress(&packet.buffer, data_length)?;
+            &decompressed[..]
+        }
+        None => &packet.buffer[..],
+    };
+
+    let mut header = BytesMut::with_capacity(16);
+    header.put_u64(time.as_micros() as u64);
+    header.put_u8(flags(direction, state));
+    header.put_varint(protocol.into());
+    header.put_uvarint(packet.len() as u32);
+    writer.write_all(&header)?;
+    writer.write_all(packet)?;
+    Ok(())
+}
+
+fn flags(direction: Direction, state: State) -> u8 {
+    let direction = match direction {
+        Direction::Serverbound => 0,
+        Direction::Clientbound => 1,
+    };
+    let state = match state {
+        State::Handshake => State::HANDSHAKE,
+        State::Status => State::STATUS,
+        State::Login => State::LOGIN,
+        State::Play => State::PLAY,
+    };
+    direction | state << 1
+}
+
+/// A packet read back from a capture.
+#[derive(Debug, Clone)]
+pub struct Record {
+    /// Since the capture started.
+    pub time: Duration,
+    pub direction: Direction,
+    pub state: State,
+    pub protocol: ProtocolVersion,
+    /// The id and data.
+    pub packet: Bytes,
+}
+
+impl Record {
+    pub fn id(&self) -> u8 {
+        self.packet[0]
+    }
+
+    /// The registry this record's packet is looked up in.
+    pub fn registry(&self) -> &'static ProtocolRegistry {
+        // a connection sending in the opposite direction is the one that receives it
+        get_protocol_registry(self.state, self.protocol, self.direction.opposite()).0
+    }
+}
+
+/// The records of a capture file, in the order they were written.
+pub struct Records {
+    pub started: SystemTime,
+    data: Bytes,
+    failed: bool,
+}
+
+impl Records {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read(path).with_context(|| format!("{}", path.display()))?;
+        Self::parse(data.into())
+    }
+
+    pub fn parse(mut data: Bytes) -> Result<Self> {
+        ensure!(data.starts_with(MAGIC), "Not a capture file");
+        data.advance(MAGIC.len());
+        ensure!(data.len() >= 9, "Capture file is cut short");
+        let version = data.get_u8();
+        ensure!(
+            version == FORMAT_VERSION,
+            "Unsupported capture format version {}",
+            version
+        );
+        let started = UNIX_EPOCH + Duration::from_micros(data.get_u64());
+        Ok(Self {
+            started,
+            data,
+            failed: false,
+        })
+    }
+
+    fn read(&mut self) -> Result<Record> {
+        ensure!(self.data.len() >= 9, "Record is cut short");
+        let time = Duration::from_micros(self.data.get_u64());
+        let flags = self.data.get_u8();
+        let protocol = ProtocolVersion::from(self.data.get_varint()?);
+        let length = self.data.get_varint()? as usize;
+        ensure!(
+            length > 0 && self.data.len() >= length,
+            "Record is cut short"
+        );
+        let state = match flags >> 1 {
+            state @ State::HANDSHAKE..=State::PLAY => State::from_id(state),
+            state => bail!("Invalid state {} in a record", state),
+        };
+        Ok(Record {
+            time,
+            direction: match flags & 1 {
+                0 => Direction::Serverbound,
+                _ => Direction::Clientbound,
+            },
+            state,
+            protocol,
+            packet: self.data.split_to(length),
+        })
+    }
+}
+
+impl Iterator for Records {
+    type Item = Result<Record>;
+
+    /// Ends after the first error, a capture cut short by a crash still gives what it has.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.data.is_empty() {
+            return None;
+        }
+        let record = self.read();
+        self.failed = record.is_err();
+        Some(record)
+    }
+}
+
+/// A record that did not make it through the codecs or its registry.
+#[derive(Debug)]
+pub struct Failure {
+    /// Position of the record in the capture, from 0.
+    pub index: usize,
+    pub record: Record,
+    pub error: anyhow::Error,
+}
+
+/// Feeds each record through an encoder and decoder like the ones of its connection and decodes
+/// it with its registry, the way the proxy would have.
+///
+/// Compression is turned on once the capture shows a `SetCompression`, so the codecs go through
+/// the same paths as they did live.
+pub fn replay(records: impl IntoIterator<Item = Record>) -> Vec<Failure> {
+    let mut threshold = None;
+    let mut failures = Vec::new();
+    for (index, record) in records.into_iter().enumerate() {
+        match replay_record(&record, threshold) {
+            Ok(PacketType::SetCompression(SetCompression { threshold: new })) => {
+                threshold = u32::try_from(new).ok();
+            }
+            Ok(_) => {}
+            Err(error) => failures.push(Failure {
+                index,
+                record,
+                error,
+            }),
+        }
+    }
+    failures
+}
+
+fn replay_record(record: &Record, threshold: Option<u32>) -> Result<PacketType> {
+    let mut encoder = MinecraftEncoder::new();
+    let mut decoder = MinecraftDecoder::new();
+    if let Some(threshold) = threshold {
+        encoder.enable_compression(threshold);
+        decoder.enable_compression(threshold, false);
+    }
+
+    let mut frame = BytesMut::new();
+    let packet = RawPacket::from_buffer(BytesMut::from(&record.packet[..]));
+    encoder.encode(packet, &mut frame)?;
+    let packet = decoder
+        .decode(&mut frame)?
+        .context("Frame was not decoded")?;
+    ensure!(frame.is_empty(), "Frame was not fully decoded");
+    decode_packet(record.registry(), packet, record.protocol)
+}
@@ -0,0 +1,149 @@
+//! Plugin message channels: what each side registered and the channels the proxy owns.
+//!
+//! Messages on a channel the proxy subscribed to go to its handlers and are never forwarded, and
+//! owned channels are left out of the registrations passed between the client and the backend.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+
+use crate::players::{OnlinePlayer, Players};
+use crate::protocol::packet::play::PluginMessage;
+use crate::protocol::wrappers::Player;
+use crate::protocol::Direction;
+
+pub const REGISTER: &str = "minecraft:register";
+pub const UNREGISTER: &str = "minecraft:unregister";
+
+/// A plugin message on a channel owned by the proxy.
+pub struct ChannelMessage {
+    pub player: OnlinePlayer,
+    pub channel: String,
+    pub data: Bytes,
+    /// `Serverbound` when the client sent it, `Clientbound` when the backend did.
+    pub direction: Direction,
+}
+
+type Handler = Box<dyn Fn(ChannelMessage) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Channels registered by both ends of one player's connection.
+#[derive(Default, Debug)]
+pub struct Registered {
+    pub client: HashSet<String>,
+    pub server: HashSet<String>,
+}
+
+#[derive(Default)]
+pub struct Channels {
+    handlers: HashMap<String, Vec<Handler>>,
+}
+
+impl Channels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes `channel` over for the proxy, its messages from either side go to `handler`.
+    pub fn subscribe<F, Fut>(&mut self, channel: impl Into<String>, handler: F)
+    where
+        F: Fn(ChannelMessage) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handler: Handler = Box::new(move |message| Box::pin(handler(message)));
+        self.handlers.entry(channel.into()).or_default().push(handler);
+    }
+
+    pub fn is_owned(&self, channel: &str) -> bool {
+        self.handlers.contains_key(channel)
+    }
+
+    /// Tracks registrations and hands messages on owned channels to their handlers,
+    /// returning what is left to forward.
+    pub(crate) async fn route(
+        &self,
+        mut message: PluginMessage,
+        direction: Direction,
+        player: &Player,
+        players: &Players,
+        registered: &Mutex<Registered>,
+    ) -> Option<PluginMessage> {
+        if message.channel == REGISTER || message.channel == UNREGISTER {
+            let channels = parse(&message.data);
+            {
+                let mut registered = registered.lock().unwrap();
+                let side = match direction {
+                    Direction::Serverbound => &mut registered.client,
+                    Direction::Clientbound => &mut registered.server,
+                };
+                for channel in &channels {
+                    if message.channel == REGISTER {
+                        side.insert(channel.clone());
+                    } else {
+                        side.remove(channel);
+                    }
+                }
+            }
+
+            let forwarded = channels
+                .iter()
+                .filter(|channel| !self.is_owned(channel))
+                .collect::<Vec<_>>();
+            if forwarded.is_empty() {
+                return None;
+            }
+            message.data = join(forwarded);
+            return Some(message);
+        }
+
+        let Some(handlers) = self.handlers.get(&message.channel) else {
+            return Some(message);
+        };
+        let online = players.get(&player.uuid)?;
+        for handler in handlers {
+            handler(ChannelMessage {
+                player: online.clone(),
+                channel: message.channel.clone(),
+                data: message.data.clone(),
+                direction,
+            })
+            .await;
+        }
+        None
+    }
+
+    /// The registration a new backend gets: the client's channels and the ones the proxy owns.
+    pub(crate) fn registration(&self, registered: &Mutex<Registered>) -> Option<PluginMessage> {
+        let registered = registered.lock().unwrap();
+        let mut channels = registered
+            .client
+            .iter()
+            .filter(|channel| !self.is_owned(channel))
+            .chain(self.handlers.keys())
+            .collect::<Vec<_>>();
+        if channels.is_empty() {
+            return None;
+        }
+        channels.sort();
+
+        Some(PluginMessage {
+            channel: REGISTER.to_owned(),
+            data: join(channels),
+        })
+    }
+}
+
+/// Register payloads are channel names separated by a null byte.
+fn parse(data: &[u8]) -> Vec<String> {
+    data.split(|&byte| byte == 0)
+        .filter(|channel| !channel.is_empty())
+        .map(|channel| String::from_utf8_lossy(channel).into_owned())
+        .collect()
+}
+
+fn join<'a>(channels: impl IntoIterator<Item = &'a String>) -> Bytes {
+    let channels = channels.into_iter().map(String::as_str).collect::<Vec<_>>();
+    Bytes::from(channels.join("\0"))
+}
@@ -0,0 +1,308 @@
+//! Commands the proxy runs itself instead of forwarding them to the backend.
+//!
+//! Players need the `rower.command.<name>` permission (see [`Hooks::has_permission`]), without
+//! it their command goes on to the backend like any other.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use tokio_util::sync::CancellationToken;
+
+use crate::access::{format_duration, now, parse_duration, Access, Ban, BanTarget};
+use crate::component::{Color, Component};
+use crate::config::{Config, LiveConfig};
+use crate::hooks::{self, Hooks};
+use crate::players::{OnlinePlayer, Players};
+use crate::protocol::packet::play::SystemChat;
+
+/// Names of the commands with their usage, in the order `help` lists them.
+const USAGES: &[(&str, &str)] = &[
+    ("help", "/help"),
+    ("list", "/list"),
+    ("servers", "/servers"),
+    ("send", "/send <player|all> <server>"),
+    ("kick", "/kick <player> [reason]"),
+    ("alert", "/alert <message>"),
+    ("ban", "/ban <player|uuid|ip[/prefix]> [duration] [reason]"),
+    ("unban", "/unban <player|uuid|ip[/prefix]>"),
+    ("whitelist", "/whitelist <on|off|add|remove|list> [player|uuid]"),
+    ("reload", "/reload"),
+    ("shutdown", "/shutdown"),
+];
+
+/// Who runs a command.
+pub enum Source {
+    Console,
+    Player(OnlinePlayer),
+}
+
+impl Source {
+    pub fn name(&self) -> String {
+        match self {
+            Source::Console => "Console".to_owned(),
+            Source::Player(online) => online.player.username.clone(),
+        }
+    }
+
+    fn has_permission(&self, hooks: &dyn Hooks, config: &Config, permission: &str) -> bool {
+        match self {
+            Source::Console => true,
+            Source::Player(online) => hooks::permitted(hooks, config, &online.player, permission),
+        }
+    }
+}
+
+pub(crate) struct Commands {
+    config: Arc<LiveConfig>,
+    access: Arc<Access>,
+    players: Arc<Players>,
+    shutdown: CancellationToken,
+}
+
+impl Commands {
+    pub(crate) fn new(
+        config: Arc<LiveConfig>,
+        access: Arc<Access>,
+        players: Arc<Players>,
+        shutdown: CancellationToken,
+    ) -> Self {
+        Self { config, access, players, shutdown }
+    }
+
+    /// Runs `line` (without the leading slash) and returns the feedback for `source`, or `None`
+    /// when it is not a proxy command `source` may run.
+    pub(crate) fn execute(&self, source: &Source, hooks: &dyn Hooks, line: &str) -> Option<Component> {
+        let mut arguments = line.split_whitespace();
+        let name = arguments.next()?.to_ascii_lowercase();
+        let arguments = arguments.collect::<Vec<_>>();
+
+        if name == "help" {
+            return source
+                .has_permission(hooks, &self.config.get(), &permission(&name))
+                .then(|| help(source, hooks, &self.config.get()));
+        }
+        let command: fn(&Self, &Source, &[&str]) -> Result<Component> = match name.as_str() {
+            "list" => Self::list,
+            "servers" => Self::servers,
+            "send" => Self::send,
+            "kick" => Self::kick,
+            "alert" => Self::alert,
+            "ban" => Self::ban,
+            "unban" => Self::unban,
+            "whitelist" => Self::whitelist,
+            "reload" => Self::reload,
+            "shutdown" => Self::shutdown,
+            _ => return None,
+        };
+        if !source.has_permission(hooks, &self.config.get(), &permission(&name)) {
+            return None;
+        }
+        Some(command(self, source, &arguments).unwrap_or_else(|err| error(&format!("{:#}", err))))
+    }
+
+    fn list(&self, _: &Source, _: &[&str]) -> Result<Component> {
+        let players = self.players.all();
+        let mut by_server = BTreeMap::<String, Vec<String>>::new();
+        for online in &players {
+            let server = self.config.get().server_name(online.server);
+            by_server.entry(server).or_default().push(online.player.username.clone());
+        }
+
+        let mut list = format!("{} player(s) online", players.len());
+        for (server, mut usernames) in by_server {
+            usernames.sort_unstable_by_key(|username| username.to_lowercase());
+            list.push_str(&format!("\n{} ({}): {}", server, usernames.len(), usernames.join(", ")));
+        }
+        Ok(success(&list))
+    }
+
+    fn servers(&self, _: &Source, _: &[&str]) -> Result<Component> {
+        let players = self.players.all();
+        let mut servers = String::from("Servers:");
+        for (name, address) in self.config.get().named_servers() {
+            let count = players.iter().filter(|online| online.server == address).count();
+            servers.push_str(&format!("\n{} ({}): {} player(s)", name, address, count));
+        }
+        Ok(success(&servers))
+    }
+
+    fn send(&self, _: &Source, arguments: &[&str]) -> Result<Component> {
+        let [target, server] = arguments else {
+            return Ok(error("Usage: /send <player|all> <server>"));
+        };
+        let config = self.config.get();
+        let Some(address) = config.server(server).or_else(|| server.parse::<SocketAddr>().ok())
+        else {
+            return Ok(error(&format!("There is no server called {}", server)));
+        };
+
+        let players = match *target {
+            "all" => self.players.all(),
+            username => vec![self.find(username)?],
+        };
+        for online in &players {
+            online.connect(address)?;
+        }
+        Ok(success(&format!(
+            "Sending {} player(s) to {}",
+            players.len(),
+            config.server_name(address)
+        )))
+    }
+
+    fn kick(&self, _: &Source, arguments: &[&str]) -> Result<Component> {
+        let Some((username, reason)) = arguments.split_first() else {
+            return Ok(error("Usage: /kick <player> [reason]"));
+        };
+        let online = self.find(username)?;
+        let reason = match reason {
+            [] => Component::text("You were kicked from the server"),
+            reason => Component::legacy(&reason.join(" ")),
+        };
+        online.disconnect(reason)?;
+        Ok(success(&format!("Kicked {}", online.player.username)))
+    }
+
+    fn alert(&self, _: &Source, arguments: &[&str]) -> Result<Component> {
+        if arguments.is_empty() {
+            return Ok(error("Usage: /alert <message>"));
+        }
+        let sent = broadcast(&self.players, &Component::legacy(&arguments.join(" ")));
+        Ok(success(&format!("Sent the alert to {} player(s)", sent)))
+    }
+
+    fn reload(&self, _: &Source, _: &[&str]) -> Result<Component> {
+        let config = self.config.reload()?;
+        let access = self.access.reload()?;
+        Ok(success(match (config, access) {
+            (true, true) => "Reloaded the config and the access list",
+            (true, false) => "Reloaded the config",
+            (false, true) => "Reloaded the access list",
+            (false, false) => "The config and the access list did not change",
+        }))
+    }
+
+    fn shutdown(&self, source: &Source, _: &[&str]) -> Result<Component> {
+        tracing::info!("{} shut the proxy down", source.name());
+        self.shutdown.cancel();
+        Ok(success("Shutting down"))
+    }
+
+    fn find(&self, username: &str) -> Result<OnlinePlayer> {
+        self.players.find(username).ok_or_else(|| anyhow!("{} is not online", username))
+    }
+
+    fn ban(&self, source: &Source, arguments: &[&str]) -> Result<Component> {
+        let Some((target, mut rest)) = arguments.split_first() else {
+            return Ok(error("Usage: /ban <player|uuid|ip[/prefix]> [duration] [reason]"));
+        };
+        let duration = rest.first().and_then(|duration| parse_duration(duration));
+        if duration.is_some() {
+            rest = &rest[1..];
+        }
+        let reason = match rest {
+            [] => Component::text("You are banned from this server"),
+            reason => Component::legacy(&reason.join(" ")),
+        };
+
+        let ban = Ban {
+            target: BanTarget::parse(target),
+            reason,
+            expires: duration.map(|duration| now() + duration.as_secs()),
+            source: source.name(),
+        };
+        let feedback = match duration {
+            Some(duration) => format!("Banned {} for {}", ban.target, format_duration(duration)),
+            None => format!("Banned {}", ban.target),
+        };
+        ban_and_kick(&self.access, &self.players, ban)?;
+        Ok(success(&feedback))
+    }
+
+    fn unban(&self, _: &Source, arguments: &[&str]) -> Result<Component> {
+        let [target] = arguments else {
+            return Ok(error("Usage: /unban <player|uuid|ip[/prefix]>"));
+        };
+        let target = BanTarget::parse(target);
+        Ok(match self.access.unban(&target)? {
+            true => success(&format!("Unbanned {}", target)),
+            false => error(&format!("{} is not banned", target)),
+        })
+    }
+
+    fn whitelist(&self, _: &Source, arguments: &[&str]) -> Result<Component> {
+        Ok(match arguments {
+            ["on"] => {
+                self.access.set_whitelist(true)?;
+                success("The whitelist is on")
+            }
+            ["off"] => {
+                self.access.set_whitelist(false)?;
+                success("The whitelist is off")
+            }
+            ["add", entry] => match self.access.whitelist_add(entry)? {
+                true => success(&format!("Added {} to the whitelist", entry)),
+                false => error(&format!("{} is already whitelisted", entry)),
+            },
+            ["remove", entry] => match self.access.whitelist_remove(entry)? {
+                true => success(&format!("Removed {} from the whitelist", entry)),
+                false => error(&format!("{} is not whitelisted", entry)),
+            },
+            ["list"] => {
+                let list = self.access.list();
+                let state = if list.whitelist { "on" } else { "off" };
+                success(&format!(
+                    "The whitelist is {}, {} entries: {}",
+                    state,
+                    list.whitelisted.len(),
+                    list.whitelisted.join(", ")
+                ))
+            }
+            _ => error("Usage: /whitelist <on|off|add|remove|list> [player|uuid]"),
+        })
+    }
+}
+
+/// Adds `ban` and kicks the players online it applies to.
+pub(crate) fn ban_and_kick(access: &Access, players: &Players, ban: Ban) -> Result<()> {
+    for online in players.all() {
+        if ban.target.matches(&online.player) {
+            let _ = online.disconnect(ban.message());
+        }
+    }
+    access.ban(ban)
+}
+
+/// Sends `content` to the chat of every player, returns how many there are.
+pub(crate) fn broadcast(players: &Players, content: &Component) -> usize {
+    let players = players.all();
+    for online in &players {
+        // players that are leaving right now just miss it
+        let _ = online.send_to_client(SystemChat { content: content.clone(), overlay: false });
+    }
+    players.len()
+}
+
+fn permission(command: &str) -> String {
+    format!("rower.command.{}", command)
+}
+
+/// Usage of the commands `source` may run.
+fn help(source: &Source, hooks: &dyn Hooks, config: &Config) -> Component {
+    let usages = USAGES
+        .iter()
+        .filter(|(name, _)| source.has_permission(hooks, config, &permission(name)))
+        .map(|(_, usage)| *usage)
+        .collect::<Vec<_>>();
+    success(&format!("Commands:\n{}", usages.join("\n")))
+}
+
+fn success(text: &str) -> Component {
+    Component::text(text).color(Color::Green)
+}
+
+fn error(text: &str) -> Component {
+    Component::text(text).color(Color::Red)
+}
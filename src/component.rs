@@ -4,7 +4,7 @@ use serde::{
     Deserialize, Deserializer, Serialize, Serializer,
 };
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum Color {
     Black,
@@ -79,7 +79,7 @@ impl<'de> Visitor<'de> for ColorVisitor {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum Type {
     Text(String),
@@ -87,12 +87,12 @@ pub enum Type {
     #[serde(untagged)]
     Translation {
         translate: String,
-        #[serde(skip_serializing_if = "Vec::is_empty")]
+        #[serde(skip_serializing_if = "Vec::is_empty", default = "Vec::new")]
         with: Vec<Component>,
     },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Component {
     #[serde(skip_serializing_if = "Option::is_none")]
     bold: Option<bool>,
@@ -173,4 +173,187 @@ impl Component {
         self.obfuscated = Some(b);
         self
     }
+
+    pub fn italic(mut self, b: bool) -> Self {
+        self.italic = Some(b);
+        self
+    }
+
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// Builds a component from text with legacy `&` formatting codes, e.g. `&cBanned &lforever`.
+    /// A color code resets the formatting before it, like in the game.
+    pub fn legacy(text: &str) -> Self {
+        let mut parts = Vec::new();
+        let mut style = Component::text("");
+        let mut current = String::new();
+
+        let mut chars = text.chars();
+        while let Some(char) = chars.next() {
+            if char != '&' && char != '§' {
+                current.push(char);
+                continue;
+            }
+            let Some(code) = chars.next() else {
+                current.push(char);
+                break;
+            };
+            let code = code.to_ascii_lowercase();
+            if !matches!(code, '0'..='9' | 'a'..='f' | 'k'..='o' | 'r') {
+                current.push(char);
+                current.push(code);
+                continue;
+            }
+
+            if !current.is_empty() {
+                let mut part = style.clone();
+                part.content = Some(Type::Text(std::mem::take(&mut current)));
+                parts.push(part);
+            }
+            style = match code {
+                'k' => style.obfuscated(true),
+                'l' => style.bold(true),
+                'm' => style.strikethrough(true),
+                'n' => style.underlined(true),
+                'o' => style.italic(true),
+                'r' => Component::text(""),
+                color => Component::text("").color(legacy_color(color)),
+            };
+        }
+        if !current.is_empty() {
+            style.content = Some(Type::Text(current));
+            parts.push(style);
+        }
+
+        Component::text("").append(parts)
+    }
+
+    /// Renders the component for a terminal, with ANSI escape codes for colors and formatting.
+    /// Translations show their key, there are no translations to look them up in.
+    pub fn to_ansi(&self) -> String {
+        let mut out = String::new();
+        self.write_ansi(&Component::text(""), &mut out);
+        out.push_str("\x1b[0m");
+        out
+    }
+
+    /// The text of the component and its children without any formatting. Translations show their
+    /// key followed by their arguments in brackets.
+    pub fn to_plain(&self) -> String {
+        let mut out = String::new();
+        self.write_plain(&mut out);
+        out
+    }
+
+    fn write_plain(&self, out: &mut String) {
+        match &self.content {
+            Some(Type::Text(text) | Type::Keybind(text)) => out.push_str(text),
+            Some(Type::Translation { translate, with }) => {
+                out.push_str(translate);
+                if !with.is_empty() {
+                    out.push('[');
+                    for (i, argument) in with.iter().enumerate() {
+                        if i > 0 {
+                            out.push_str(", ");
+                        }
+                        argument.write_plain(out);
+                    }
+                    out.push(']');
+                }
+            }
+            None => {}
+        }
+        for child in &self.extra {
+            child.write_plain(out);
+        }
+    }
+
+    /// Writes the component with the style it inherits from `parent`, then its children.
+    fn write_ansi(&self, parent: &Component, out: &mut String) {
+        let style = Component {
+            bold: self.bold.or(parent.bold),
+            italic: self.italic.or(parent.italic),
+            underlined: self.underlined.or(parent.underlined),
+            strikethrough: self.strikethrough.or(parent.strikethrough),
+            obfuscated: self.obfuscated.or(parent.obfuscated),
+            color: self.color.clone().or_else(|| parent.color.clone()),
+            ..Component::text("")
+        };
+
+        let text = match &self.content {
+            Some(Type::Text(text) | Type::Keybind(text)) => text.as_str(),
+            Some(Type::Translation { translate, .. }) => translate.as_str(),
+            None => "",
+        };
+        if !text.is_empty() {
+            out.push_str("\x1b[0");
+            for (enabled, code) in [
+                (style.bold, ";1"),
+                (style.italic, ";3"),
+                (style.underlined, ";4"),
+                (style.obfuscated, ";8"),
+                (style.strikethrough, ";9"),
+            ] {
+                if enabled == Some(true) {
+                    out.push_str(code);
+                }
+            }
+            if let Some(color) = &style.color {
+                out.push_str(&ansi_color(color));
+            }
+            out.push('m');
+            out.push_str(text);
+        }
+
+        for child in &self.extra {
+            child.write_ansi(&style, out);
+        }
+    }
+}
+
+fn ansi_color(color: &Color) -> String {
+    let code = match color {
+        Color::Black => 30,
+        Color::DarkBlue => 34,
+        Color::DarkGreen => 32,
+        Color::DarkAqua => 36,
+        Color::DarkRed => 31,
+        Color::DarkPurple => 35,
+        Color::Gold => 33,
+        Color::Gray => 37,
+        Color::DarkGray => 90,
+        Color::Blue => 94,
+        Color::Green => 92,
+        Color::Aqua => 96,
+        Color::Red => 91,
+        Color::LightPurple => 95,
+        Color::Yellow => 93,
+        Color::White => 97,
+        Color::Rgb(red, green, blue) => return format!(";38;2;{};{};{}", red, green, blue),
+    };
+    format!(";{}", code)
+}
+
+fn legacy_color(code: char) -> Color {
+    match code {
+        '0' => Color::Black,
+        '1' => Color::DarkBlue,
+        '2' => Color::DarkGreen,
+        '3' => Color::DarkAqua,
+        '4' => Color::DarkRed,
+        '5' => Color::DarkPurple,
+        '6' => Color::Gold,
+        '7' => Color::Gray,
+        '8' => Color::DarkGray,
+        '9' => Color::Blue,
+        'a' => Color::Green,
+        'b' => Color::Aqua,
+        'c' => Color::Red,
+        'd' => Color::LightPurple,
+        'e' => Color::Yellow,
+        _ => Color::White,
+    }
 }
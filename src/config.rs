@@ -1,36 +1,157 @@
 use std::{
-    fs, net::{IpAddr, Ipv4Addr, SocketAddr}, path::Path, sync::OnceLock
+    collections::BTreeMap, fs, net::{IpAddr, Ipv4Addr, SocketAddr}, path::{Path, PathBuf}, sync::Arc
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use clap::Parser;
 use libdeflater::CompressionLvl;
 use serde::{Deserialize, Serialize, Serializer};
+use toml::{de::ValueDeserializer, Table, Value};
 
-pub fn config() -> &'static Config {
-    static CONFIG: OnceLock<Config> = OnceLock::new();
-    CONFIG.get_or_init(|| {
-        load_config().inspect_err(|err| log::error!("{}", err)).unwrap_or_default()
-    })
+use crate::logging::LogFormat;
+use crate::throttle::Verification;
+
+const ENV_PREFIX: &str = "ROWER_";
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Args {
+    /// Path to the config file, created with defaults if missing
+    #[arg(long, env = "ROWER_CONFIG", default_value = "config.toml")]
+    pub config: PathBuf,
+    /// Address to listen on
+    #[arg(long)]
+    pub bind: Option<SocketAddr>,
+    /// Packet compression threshold (-1 to disable)
+    #[arg(long, allow_negative_numbers = true)]
+    pub compression_threshold: Option<i32>,
+    /// Compression level (1-12)
+    #[arg(long)]
+    pub compression_level: Option<i32>,
+    /// Enable Mojang authentication
+    #[arg(long)]
+    pub online: Option<bool>,
+    /// Primary backend server
+    #[arg(long)]
+    pub backend_server: Option<SocketAddr>,
+    /// Fallback server for disconnects
+    #[arg(long)]
+    pub fallback_server: Option<SocketAddr>,
+    /// Runtime worker threads (0 for one per core, 1 for a single-threaded runtime)
+    #[arg(long)]
+    pub worker_threads: Option<usize>,
+    /// Log output, `json` writes one object per line
+    #[arg(long, env = "ROWER_LOG_FORMAT", value_enum, default_value = "text")]
+    pub log_format: LogFormat,
+}
+
+impl Args {
+    fn overrides(&self) -> Result<Table> {
+        let mut table = Table::new();
+        insert(&mut table, "bind", &self.bind)?;
+        insert(&mut table, "compression_threshold", &self.compression_threshold)?;
+        insert(&mut table, "compression_level", &self.compression_level)?;
+        insert(&mut table, "online", &self.online)?;
+        insert(&mut table, "backend_server", &self.backend_server)?;
+        insert(&mut table, "fallback_server", &self.fallback_server)?;
+        insert(&mut table, "worker_threads", &self.worker_threads)?;
+        Ok(table)
+    }
+}
+
+fn insert<T: Serialize>(table: &mut Table, key: &str, value: &Option<T>) -> Result<()> {
+    if let Some(value) = value {
+        table.insert(key.to_owned(), Value::try_from(value)?);
+    }
+    Ok(())
 }
 
-fn load_config() -> Result<Config> {
-    let path = Path::new("config.toml");
+/// Layers the config file, the `ROWER_*` variables among `vars` and the cli args over the defaults.
+pub fn load(args: &Args, vars: impl IntoIterator<Item = (String, String)>) -> Result<Config> {
+    let mut table = read_file(&args.config)?;
+    merge(&mut table, env_overrides(vars)?);
+    merge(&mut table, args.overrides()?);
+
+    table
+        .try_into()
+        .with_context(|| format!("Invalid config in {}", args.config.display()))
+}
 
+fn read_file(path: &Path) -> Result<Table> {
     if path.exists() {
         let content = fs::read_to_string(path)?;
-        let config = toml::from_str(&content)?;
+        return Ok(content.parse()?);
+    }
+
+    let toml = toml::to_string(&Config::default()).unwrap();
+    fs::write(path, toml)?;
+    Ok(Table::new())
+}
+
+/// Maps `ROWER_COMPRESSION_LEVEL=6` to `compression_level = 6`,
+/// a double underscore (`ROWER_A__B`) descends into the `a` table.
+fn env_overrides(vars: impl IntoIterator<Item = (String, String)>) -> Result<Table> {
+    let defaults = Value::try_from(Config::default())?;
+    let mut table = Table::new();
+
+    for (key, value) in vars {
+        let Some(key) = key.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        if key == "CONFIG" {
+            continue;
+        }
+
+        let mut path = key.split("__").map(str::to_lowercase).collect::<Vec<_>>();
+        let last = path.pop().unwrap();
 
-        return Ok(config);
+        let mut default = defaults.as_table();
+        let mut entry = &mut table;
+        for part in path {
+            default = default.and_then(|table| table.get(&part)).and_then(Value::as_table);
+            let value = entry.entry(part).or_insert_with(|| Value::Table(Table::new()));
+            if !value.is_table() {
+                *value = Value::Table(Table::new());
+            }
+            entry = value.as_table_mut().unwrap();
+        }
+        let default = default.and_then(|table| table.get(&last));
+        entry.insert(last, parse_value(&value, default));
     }
+    Ok(table)
+}
 
-    let config = Config::default();
-    let toml = toml::to_string(&config).unwrap();
+/// Reads the value the way the field it overrides is typed, going by its default: strings stay
+/// strings (`ROWER_API_TOKEN=123456`), numbers, booleans and arrays are read as toml literals
+/// (`256`, `true`, `["a", "b"]`). Fields without a default are strings unless they look like an
+/// array or a table. A literal that does not parse is kept as a string, so the error names the field.
+fn parse_value(value: &str, default: Option<&Value>) -> Value {
+    let literal = match default {
+        Some(Value::String(_)) => false,
+        Some(_) => true,
+        None => value.starts_with(['[', '{']),
+    };
+    if literal {
+        if let Ok(parsed) = Value::deserialize(ValueDeserializer::new(value)) {
+            return parsed;
+        }
+    }
+    Value::String(value.to_owned())
+}
 
-    fs::write(path, toml)?;
-    Ok(config)
+fn merge(base: &mut Table, overrides: Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(table)) => merge(base, table),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(default)]
 pub struct Config {
     #[serde(rename = "bind")]
@@ -38,9 +159,73 @@ pub struct Config {
     pub compression_threshold: i32,
     #[serde(serialize_with = "ser", deserialize_with = "de")]
     pub compression_level: CompressionLvl,
+    pub compression_passthrough: bool,
     pub online: bool,
     pub backend_server: SocketAddr,
     pub fallback_server: SocketAddr,
+    pub worker_threads: usize,
+    /// Directory the `.wasm` plugins are loaded from.
+    pub plugins: PathBuf,
+    /// Memory a plugin can use, in MiB.
+    pub plugin_memory_limit: usize,
+    /// Fuel a plugin gets for every call into it.
+    pub plugin_fuel: u64,
+    /// Answer the BungeeCord plugin message channel (`bungeecord:main`) of backend plugins.
+    pub bungee_plugin_channel: bool,
+    /// File the bans and the whitelist are kept in.
+    pub access_list: PathBuf,
+    /// Usernames or UUIDs of the players allowed to run every proxy command.
+    pub admins: Vec<String>,
+    /// Seconds the connection and login limits count over.
+    pub throttle_window: u64,
+    /// Connections one IP may open per window, 0 for no limit.
+    pub connections_per_ip: u32,
+    /// Logins accepted per window from all IPs together, 0 for no limit.
+    pub logins_per_window: u32,
+    /// Only let IPs that pinged the server list recently log in.
+    pub require_ping: bool,
+    /// Hold logins in a limbo before connecting them to a backend: off, on, or auto while
+    /// the login limit is being hit.
+    pub verification: Verification,
+    /// Milliseconds a login is held in verification.
+    pub verification_delay: u64,
+    /// Milliseconds a new connection has to send its handshake in, 0 waits forever.
+    pub handshake_timeout: u64,
+    /// Milliseconds a status ping may wait for each packet, 0 waits forever.
+    pub status_timeout: u64,
+    /// Milliseconds a login may wait for each packet of the client or the backend, 0 waits forever.
+    pub login_timeout: u64,
+    /// Milliseconds a player in game has to answer a keepalive in, and the backend to send
+    /// anything in, 0 to not watch them.
+    pub keepalive_timeout: u64,
+    /// Clients connect through a load balancer that starts every connection with a PROXY
+    /// protocol header, connections without one are closed.
+    pub proxy_protocol: bool,
+    /// Start backend connections with a PROXY protocol header naming the player's address.
+    pub backend_proxy_protocol: bool,
+    /// What players are disconnected with when the proxy shuts down, `&` color codes work.
+    pub shutdown_message: String,
+    /// Milliseconds a shutdown waits for connections to close, 0 waits for all of them.
+    pub shutdown_timeout: u64,
+    /// Read proxy commands from stdin.
+    pub console: bool,
+    /// Address of the admin HTTP API, off without one.
+    pub api_bind: Option<SocketAddr>,
+    /// Token callers of the admin API have to send.
+    pub api_token: String,
+    /// Directory packet captures are written to, off without one.
+    pub capture_dir: Option<PathBuf>,
+    /// Usernames whose packets are captured, everyone's when empty.
+    pub capture_players: Vec<String>,
+    /// Keep players a full or unreachable server refuses, at login or when they switch or are
+    /// kicked to it, in its queue.
+    pub queue: bool,
+    /// Permissions giving a better place in the queues, the first one the best.
+    pub queue_priorities: Vec<String>,
+    /// Milliseconds between the tries of the player at the front of a queue.
+    pub queue_retry: u64,
+    /// Backend servers by name, for commands and the BungeeCord channel.
+    pub servers: BTreeMap<String, SocketAddr>,
 }
 
 impl Default for Config {
@@ -49,10 +234,68 @@ impl Default for Config {
             address: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 25565),
             compression_threshold: 256,
             compression_level: CompressionLvl::default(),
+            compression_passthrough: true,
             online: true,
             backend_server: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 25566),
             fallback_server: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 25567),
+            worker_threads: 0,
+            plugins: PathBuf::from("plugins"),
+            plugin_memory_limit: 16,
+            plugin_fuel: 10_000_000,
+            bungee_plugin_channel: true,
+            access_list: PathBuf::from("access.toml"),
+            admins: Vec::new(),
+            throttle_window: 10,
+            connections_per_ip: 8,
+            logins_per_window: 40,
+            require_ping: false,
+            verification: Verification::Auto,
+            verification_delay: 3000,
+            handshake_timeout: 5000,
+            status_timeout: 5000,
+            login_timeout: 30_000,
+            keepalive_timeout: 30_000,
+            proxy_protocol: false,
+            backend_proxy_protocol: false,
+            shutdown_message: "Proxy is shutting down".to_owned(),
+            shutdown_timeout: 10_000,
+            console: true,
+            api_bind: None,
+            api_token: String::new(),
+            capture_dir: None,
+            capture_players: Vec::new(),
+            queue: false,
+            queue_priorities: Vec::new(),
+            queue_retry: 5000,
+            servers: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// The named servers, plus the backend and fallback servers as `backend` and `fallback`
+    /// when no name was given to them.
+    pub fn named_servers(&self) -> BTreeMap<String, SocketAddr> {
+        let mut servers = self.servers.clone();
+        for (name, address) in [("backend", self.backend_server), ("fallback", self.fallback_server)] {
+            if !servers.values().any(|server| *server == address) {
+                servers.entry(name.to_owned()).or_insert(address);
+            }
         }
+        servers
+    }
+
+    pub fn server(&self, name: &str) -> Option<SocketAddr> {
+        self.named_servers().get(name).copied()
+    }
+
+    /// Name of the server at `address`, the address itself for unnamed servers.
+    pub fn server_name(&self, address: SocketAddr) -> String {
+        self.named_servers()
+            .into_iter()
+            .find(|(_, server)| *server == address)
+            .map(|(name, _)| name)
+            .unwrap_or_else(|| address.to_string())
     }
 }
 
@@ -67,9 +310,9 @@ fn de<'de, D>(deserializer: D) -> Result<CompressionLvl, D::Error>
 where
     D: serde::Deserializer<'de>
 {
-    let level = match i32::deserialize(deserializer) {
-        Ok(-1) | Err(_) => return Ok(CompressionLvl::default()),
-        Ok(level) => level,
+    let level = match i32::deserialize(deserializer)? {
+        -1 => return Ok(CompressionLvl::default()),
+        level => level,
     };
 
     match CompressionLvl::new(level) {
@@ -77,3 +320,40 @@ where
         _ => Err(serde::de::Error::custom("invalid compression level (accepted range 1-12)"))
     }
 }
+
+/// Reads the config again, from wherever it came from the first time.
+pub type ConfigLoader = Box<dyn Fn() -> Result<Config> + Send + Sync>;
+
+/// The config of a running proxy, swapped as a whole when it is reloaded.
+pub(crate) struct LiveConfig {
+    current: ArcSwap<Config>,
+    loader: Option<ConfigLoader>,
+}
+
+impl LiveConfig {
+    pub(crate) fn new(config: Config, loader: Option<ConfigLoader>) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(config),
+            loader,
+        }
+    }
+
+    /// The config as it is now, a later reload does not change what was returned.
+    pub(crate) fn get(&self) -> Arc<Config> {
+        self.current.load_full()
+    }
+
+    /// Loads the config again, `false` without a loader or when nothing changed.
+    /// A config that fails to load leaves the current one in place.
+    pub(crate) fn reload(&self) -> Result<bool> {
+        let Some(loader) = &self.loader else {
+            return Ok(false);
+        };
+        let config = loader()?;
+        if config == *self.get() {
+            return Ok(false);
+        }
+        self.current.store(Arc::new(config));
+        Ok(true)
+    }
+}
@@ -0,0 +1,54 @@
+//! The admin console, proxy commands typed on stdin with line editing and history.
+//!
+//! Commands are the ones players run in game (see [`crate::commands`]), the console may run all of
+//! them. Their feedback is printed with ANSI colors.
+
+use std::thread;
+
+use anyhow::Result;
+use tracing::{info, warn};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use tokio_util::sync::CancellationToken;
+
+use crate::component::{Color, Component};
+
+const PROMPT: &str = "> ";
+
+/// Reads lines on a thread of its own until stdin closes or `shutdown`, running each with
+/// `execute`. Ctrl-C shuts the proxy down.
+pub(crate) fn start(
+    execute: impl Fn(&str) -> Option<Component> + Send + 'static,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    thread::Builder::new().name("console".to_owned()).spawn(move || {
+        while !shutdown.is_cancelled() {
+            let line = match editor.readline(PROMPT) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => {
+                    info!("Shutting down");
+                    shutdown.cancel();
+                    return;
+                }
+                // stdin is not a terminal or was closed, the proxy keeps running without a console
+                Err(ReadlineError::Eof) => return,
+                Err(err) => {
+                    warn!("Console stopped: {}", err);
+                    return;
+                }
+            };
+            let line = line.trim().trim_start_matches('/');
+            if line.is_empty() {
+                continue;
+            }
+            let _ = editor.add_history_entry(line);
+
+            let feedback = execute(line).unwrap_or_else(|| {
+                Component::text("Unknown command, type help for a list").color(Color::Red)
+            });
+            println!("{}", feedback.to_ansi());
+        }
+    })?;
+    Ok(())
+}
@@ -0,0 +1,154 @@
+//! Typed events fired by the proxy.
+//!
+//! Handlers are async, take the event by value and hand it back, possibly changed.
+//! They run one after another in [`Priority`] order, so later handlers see what earlier ones did.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+
+use futures::future::BoxFuture;
+
+use crate::component::Component;
+use crate::protocol::wrappers::Player;
+use crate::protocol::ProtocolVersion;
+
+pub trait Event: Send + 'static {}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    First,
+    Early,
+    #[default]
+    Normal,
+    Late,
+    /// Sees the final result, meant for handlers that only observe.
+    Last,
+}
+
+type Handler<E> = Box<dyn Fn(E) -> BoxFuture<'static, E> + Send + Sync>;
+
+/// A `Handler<E>` with the event type erased.
+type AnyHandler = Box<dyn Any + Send + Sync>;
+
+#[derive(Default)]
+pub struct EventBus {
+    handlers: HashMap<TypeId, Vec<(Priority, AnyHandler)>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<E, F, Fut>(&mut self, priority: Priority, handler: F)
+    where
+        E: Event,
+        F: Fn(E) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = E> + Send + 'static,
+    {
+        let handler: Handler<E> = Box::new(move |event| Box::pin(handler(event)));
+        let handlers = self.handlers.entry(TypeId::of::<E>()).or_default();
+
+        // behind the ones with the same priority, so they keep the registration order
+        let index = handlers.partition_point(|(p, _)| *p <= priority);
+        handlers.insert(index, (priority, Box::new(handler)));
+    }
+
+    pub async fn fire<E: Event>(&self, mut event: E) -> E {
+        let Some(handlers) = self.handlers.get(&TypeId::of::<E>()) else {
+            return event;
+        };
+
+        for (_, handler) in handlers {
+            let handler = handler
+                .downcast_ref::<Handler<E>>()
+                .expect("handler registered under the wrong event");
+            event = handler(event).await;
+        }
+        event
+    }
+}
+
+/// Fired after the login start, before authentication. A denied player is disconnected with the reason.
+pub struct PreLoginEvent {
+    pub username: String,
+    pub address: SocketAddr,
+    pub protocol: ProtocolVersion,
+    pub denied: Option<Component>,
+}
+
+impl PreLoginEvent {
+    pub fn deny(&mut self, reason: Component) {
+        self.denied = Some(reason);
+    }
+}
+
+/// Fired once the player finished logging in.
+pub struct PostLoginEvent {
+    pub player: Player,
+}
+
+/// Chooses the first server of a player, the configured backend by default.
+pub struct InitialServerEvent {
+    pub player: Player,
+    pub server: SocketAddr,
+}
+
+/// Fired before connecting the player to any server, changing `server` redirects them.
+pub struct ServerPreConnectEvent {
+    pub player: Player,
+    pub server: SocketAddr,
+}
+
+pub struct ServerConnectedEvent {
+    pub player: Player,
+    pub server: SocketAddr,
+    pub previous: Option<SocketAddr>,
+}
+
+pub enum KickResult {
+    /// Move the player to this server.
+    Fallback(SocketAddr),
+    Disconnect(Component),
+}
+
+/// Fired when a server kicks the player during play. Defaults to the configured fallback server,
+/// or to disconnecting when the player is kicked from the fallback itself.
+pub struct KickedFromServerEvent {
+    pub player: Player,
+    pub server: SocketAddr,
+    pub reason: Component,
+    pub result: KickResult,
+}
+
+/// A chat message sent by the player. Cancelled messages are not forwarded. A signed message that
+/// gets changed fails validation on servers enforcing secure chat.
+pub struct ChatEvent {
+    pub player: Player,
+    pub message: String,
+    pub cancelled: bool,
+}
+
+/// A command (without the leading `/`) sent by the player, same rules as [`ChatEvent`].
+pub struct CommandEvent {
+    pub player: Player,
+    pub command: String,
+    pub cancelled: bool,
+}
+
+/// Fired when a player that finished logging in leaves the proxy.
+pub struct DisconnectEvent {
+    pub player: Player,
+}
+
+impl Event for PreLoginEvent {}
+impl Event for PostLoginEvent {}
+impl Event for InitialServerEvent {}
+impl Event for ServerPreConnectEvent {}
+impl Event for ServerConnectedEvent {}
+impl Event for KickedFromServerEvent {}
+impl Event for ChatEvent {}
+impl Event for CommandEvent {}
+impl Event for DisconnectEvent {}
@@ -1,5 +1,4 @@
 use std::io::Cursor;
-use std::net::SocketAddr;
 use std::sync::OnceLock;
 
 use anyhow::Result;
@@ -9,15 +8,14 @@ use image::{
     error::{LimitError, LimitErrorKind},
     image_dimensions, ImageError, ImageFormat,
 };
-use log::error;
+use tracing::error;
 
-use crate::config::config;
 use crate::{
     component::Component,
     protocol::packet::status::{Motd, Players, Status, Version},
 };
 
-pub fn status() -> &'static Vec<u8> {
+pub fn status() -> &'static [u8] {
     static STATUS: OnceLock<Vec<u8>> = OnceLock::new();
     STATUS.get_or_init(create_status)
 }
@@ -59,7 +57,3 @@ fn read_favicon() -> Result<String> {
 
     Ok(format!("data:image/png;base64,{}", favicon))
 }
-
-pub fn get_initial_server() -> SocketAddr {
-    config().backend_server
-}
@@ -0,0 +1,130 @@
+//! The HAProxy PROXY protocol, versions 1 and 2.
+//!
+//! A TCP load balancer in front of the proxy sends a header naming the client before anything
+//! else on the stream, so players keep their own address instead of the balancer's. The same
+//! header can be sent to backends that sit behind the proxy.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use anyhow::{bail, ensure, Result};
+use bytes::{BufMut, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+/// A version 1 header is at most this long, `\r\n` included.
+const V1_MAX_LENGTH: usize = 107;
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Version 2 and the PROXY command.
+const V2_PROXY: u8 = 0x21;
+const V2_LOCAL: u8 = 0x20;
+const V2_TCP4: u8 = 0x11;
+const V2_TCP6: u8 = 0x21;
+
+/// Reads the header at the start of `stream` and returns the client it names, `None` when the
+/// balancer connected on its own behalf (like for a health check).
+///
+/// Reads nothing past the header, so the stream can be handed on as it is.
+pub async fn read_header<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Option<SocketAddr>> {
+    // even the shortest header, `PROXY UNKNOWN\r\n`, is longer than the version 2 signature
+    let mut start = [0; 12];
+    stream.read_exact(&mut start).await?;
+
+    if start == V2_SIGNATURE {
+        read_v2(stream).await
+    } else if start.starts_with(V1_PREFIX) {
+        read_v1(stream, &start).await
+    } else {
+        bail!("Connection did not start with a PROXY protocol header")
+    }
+}
+
+async fn read_v1<R: AsyncRead + Unpin>(stream: &mut R, start: &[u8]) -> Result<Option<SocketAddr>> {
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        ensure!(line.len() < V1_MAX_LENGTH, "PROXY protocol header is too long");
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[V1_PREFIX.len()..line.len() - 2])?;
+    let parts = line.split(' ').collect::<Vec<_>>();
+    match parts.as_slice() {
+        ["UNKNOWN", ..] => Ok(None),
+        [protocol @ ("TCP4" | "TCP6"), source, _, port, _] => {
+            let source: IpAddr = source.parse()?;
+            ensure!(
+                source.is_ipv4() == (*protocol == "TCP4"),
+                "{} does not belong to {}",
+                source,
+                protocol
+            );
+            Ok(Some(SocketAddr::new(source, port.parse()?)))
+        }
+        _ => bail!("Malformed PROXY protocol header: {}", line),
+    }
+}
+
+async fn read_v2<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Option<SocketAddr>> {
+    let command = stream.read_u8().await?;
+    let family = stream.read_u8().await?;
+    let mut addresses = vec![0; stream.read_u16().await? as usize];
+    stream.read_exact(&mut addresses).await?;
+
+    match command {
+        V2_LOCAL => return Ok(None),
+        V2_PROXY => {}
+        _ => bail!("Unsupported PROXY protocol version or command {:#04x}", command),
+    }
+    // the addresses are followed by TLVs nobody here needs
+    let source = match family >> 4 {
+        1 if addresses.len() >= 12 => {
+            let ip = <[u8; 4]>::try_from(&addresses[..4]).unwrap();
+            SocketAddr::new(Ipv4Addr::from(ip).into(), port(&addresses[8..10]))
+        }
+        2 if addresses.len() >= 36 => {
+            let ip = <[u8; 16]>::try_from(&addresses[..16]).unwrap();
+            SocketAddr::new(Ipv6Addr::from(ip).into(), port(&addresses[32..34]))
+        }
+        1 | 2 => bail!("PROXY protocol addresses are cut short"),
+        // unix sockets or an unspecified family, there is no address to take
+        _ => return Ok(None),
+    };
+    Ok(Some(source))
+}
+
+fn port(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+/// A version 2 header for a connection from `source` to `destination`.
+pub fn header(source: SocketAddr, destination: SocketAddr) -> BytesMut {
+    let mut buf = BytesMut::with_capacity(16 + 36);
+    buf.put_slice(&V2_SIGNATURE);
+    buf.put_u8(V2_PROXY);
+
+    match (source.ip(), destination.ip()) {
+        (IpAddr::V4(source_ip), IpAddr::V4(destination_ip)) => {
+            buf.put_u8(V2_TCP4);
+            buf.put_u16(12);
+            buf.put_slice(&source_ip.octets());
+            buf.put_slice(&destination_ip.octets());
+        }
+        // both have to be of one family, IPv4 addresses are mapped when they are mixed
+        (source_ip, destination_ip) => {
+            buf.put_u8(V2_TCP6);
+            buf.put_u16(36);
+            buf.put_slice(&to_ipv6(source_ip).octets());
+            buf.put_slice(&to_ipv6(destination_ip).octets());
+        }
+    }
+    buf.put_u16(source.port());
+    buf.put_u16(destination.port());
+    buf
+}
+
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
@@ -0,0 +1,38 @@
+use bytes::Bytes;
+
+use crate::config::Config;
+use crate::handlers::status;
+use crate::protocol::wrappers::Player;
+
+/// Extension points of the proxy, every method defaults to the stock behaviour.
+pub trait Hooks: Send + Sync + 'static {
+    /// Serialized status json sent in the server list.
+    fn status(&self) -> Bytes {
+        Bytes::from_static(status())
+    }
+
+    /// Rewrites the `minecraft:brand` a backend reports to the client.
+    fn server_brand(&self, mut brand: String) -> String {
+        brand.push_str(" inside a bike");
+        brand
+    }
+
+    /// Whether `player` may do what `permission` stands for, e.g. `rower.command.ban`.
+    /// The `admins` of the config may do everything without asking, by default nobody else may.
+    fn has_permission(&self, player: &Player, permission: &str) -> bool {
+        let _ = (player, permission);
+        false
+    }
+}
+
+/// Whether `player` is one of the `admins` of `config` or `hooks` gives them `permission`.
+pub(crate) fn permitted(hooks: &dyn Hooks, config: &Config, player: &Player, permission: &str) -> bool {
+    let admin = config.admins.iter().any(|admin| {
+        admin.eq_ignore_ascii_case(&player.username) || *admin == player.uuid.to_string()
+    });
+    admin || hooks.has_permission(player, permission)
+}
+
+pub struct DefaultHooks;
+
+impl Hooks for DefaultHooks {}
@@ -0,0 +1,397 @@
+//! Capture records in a form people can read, for the `rower-inspect` binary.
+//!
+//! A record is shown with the name its registry has for its id in that protocol version and,
+//! when the proxy has a [`Packet`] impl for it, its decoded fields. NBT is shown as SNBT and
+//! components as plain text. Packets the proxy only passes through just show their length.
+
+use std::fmt::Write;
+
+use anyhow::Result;
+use bytes::BytesMut;
+
+use crate::capture::Record;
+use crate::component::Component;
+use crate::protocol::packet::handshake::Handshake;
+use crate::protocol::packet::login::{
+    Disconnect, EncryptionRequest, EncryptionResponse, LoginAcknowledged, LoginPluginRequest,
+    LoginPluginResponse, LoginStart, LoginSuccess, SetCompression,
+};
+use crate::protocol::packet::play::{
+    BossBar, BossBarAction, ChatCommand, ChatMessage, Death, JoinGame, KeepAlive, PlayerPosition,
+    PluginMessage, Respawn, SystemChat,
+};
+use crate::protocol::packet::status::{Ping, StatusRequest};
+use crate::protocol::packet::{Packet, RawPacket};
+use crate::protocol::{Direction, State};
+
+/// A field name and its value, formatted.
+pub type Field = (&'static str, String);
+
+/// Which records to show, everything by default.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub direction: Option<Direction>,
+    pub state: Option<State>,
+    /// Packet names (ignoring case), like `JoinGame`, all packets when empty.
+    pub packets: Vec<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, record: &Record) -> bool {
+        self.direction.is_none_or(|direction| direction == record.direction)
+            && self.state.is_none_or(|state| state == record.state)
+            && (self.packets.is_empty()
+                || name(record).is_some_and(|name| {
+                    self.packets
+                        .iter()
+                        .any(|packet| packet.eq_ignore_ascii_case(name))
+                }))
+    }
+}
+
+/// Name of the record's packet in its registry, `None` for packets the proxy does not know.
+pub fn name(record: &Record) -> Option<&'static str> {
+    record.registry().get_name(record.id())
+}
+
+/// Decoded fields of the record's packet, `None` when there is no [`Packet`] impl to decode it.
+pub fn fields(record: &Record) -> Option<Result<Vec<Field>>> {
+    macro_rules! first_of {
+        ($($packet:ty),* $(,)?) => {
+            $(
+                if let Some(fields) = decode::<$packet>(record) {
+                    return Some(fields);
+                }
+            )*
+        };
+    }
+    first_of!(
+        Handshake,
+        StatusRequest,
+        Ping,
+        LoginStart,
+        EncryptionRequest,
+        EncryptionResponse,
+        SetCompression,
+        LoginSuccess,
+        LoginPluginRequest,
+        LoginPluginResponse,
+        LoginAcknowledged,
+        Disconnect,
+        PluginMessage,
+        JoinGame,
+        Respawn,
+        PlayerPosition,
+        BossBar,
+        ChatCommand,
+        ChatMessage,
+        SystemChat,
+        KeepAlive,
+    );
+    None
+}
+
+fn decode<T: Inspect + 'static>(record: &Record) -> Option<Result<Vec<Field>>> {
+    let id = *record.registry().get_id::<T>().ok()?;
+    if id != record.id() {
+        return None;
+    }
+    let packet = RawPacket::from_buffer(BytesMut::from(&record.packet[..]));
+    Some(packet.decode::<T>(record.protocol).map(|packet| packet.fields()))
+}
+
+/// The record as a header line, `#<index> <time> <direction> <state> <id> <name>`, followed by a
+/// line for each field.
+pub fn describe(index: usize, record: &Record) -> String {
+    let mut out = format!(
+        "#{} {:.3}s {:?} {:?} {:#04x} {}",
+        index,
+        record.time.as_secs_f64(),
+        record.direction,
+        record.state,
+        record.id(),
+        name(record).unwrap_or("unknown"),
+    );
+    match fields(record) {
+        Some(Ok(fields)) => {
+            for (name, value) in fields {
+                let _ = write!(out, "\n    {}: {}", name, value);
+            }
+        }
+        Some(Err(err)) => {
+            let _ = write!(out, "\n    could not decode: {:#}", err);
+        }
+        None => {
+            let _ = write!(out, " ({} bytes)", record.packet.len() - 1);
+        }
+    }
+    out
+}
+
+/// Fields of a packet, in the order they are sent.
+trait Inspect: Packet {
+    fn fields(self) -> Vec<Field>;
+}
+
+fn text(component: &Component) -> String {
+    format!("{:?}", component.to_plain())
+}
+
+fn bytes(bytes: &[u8]) -> String {
+    format!("{} bytes", bytes.len())
+}
+
+fn death(death: &Option<Death>) -> String {
+    match death {
+        Some(death) => format!("{:?} at {}", death.dimension_name, death.position),
+        None => "none".to_owned(),
+    }
+}
+
+impl Inspect for Handshake {
+    fn fields(self) -> Vec<Field> {
+        vec![
+            ("protocol", self.protocol.to_string()),
+            ("server_address", format!("{:?}", self.server_address)),
+            ("port", self.port.to_string()),
+            ("state", format!("{:?}", self.state)),
+        ]
+    }
+}
+
+impl Inspect for StatusRequest {
+    fn fields(self) -> Vec<Field> {
+        Vec::new()
+    }
+}
+
+impl Inspect for Ping {
+    fn fields(self) -> Vec<Field> {
+        vec![("payload", self.0.to_string())]
+    }
+}
+
+impl Inspect for LoginStart {
+    fn fields(self) -> Vec<Field> {
+        let uuid = self.uuid.map_or("none".to_owned(), |uuid| uuid.to_string());
+        vec![
+            ("username", format!("{:?}", self.username)),
+            ("uuid", uuid),
+        ]
+    }
+}
+
+impl Inspect for EncryptionRequest {
+    fn fields(self) -> Vec<Field> {
+        vec![
+            ("server_id", format!("{:?}", self.server_id)),
+            ("public_key", bytes(&self.public_key)),
+            ("verify_token", bytes(&self.verify_token)),
+        ]
+    }
+}
+
+impl Inspect for EncryptionResponse {
+    fn fields(self) -> Vec<Field> {
+        vec![
+            ("shared_secret", bytes(&self.shared_secret)),
+            ("verify_token", bytes(&self.verify_token)),
+        ]
+    }
+}
+
+impl Inspect for SetCompression {
+    fn fields(self) -> Vec<Field> {
+        vec![("threshold", self.threshold.to_string())]
+    }
+}
+
+impl Inspect for LoginSuccess {
+    fn fields(self) -> Vec<Field> {
+        let properties = self.properties.iter().map(|property| &property.name);
+        vec![
+            ("uuid", self.uuid.to_string()),
+            ("username", format!("{:?}", self.username)),
+            ("properties", format!("{:?}", properties.collect::<Vec<_>>())),
+        ]
+    }
+}
+
+impl Inspect for LoginPluginRequest {
+    fn fields(self) -> Vec<Field> {
+        vec![
+            ("message_id", self.message_id.to_string()),
+            ("channel", format!("{:?}", self.channel)),
+            ("data", bytes(&self.data)),
+        ]
+    }
+}
+
+impl Inspect for LoginPluginResponse {
+    fn fields(self) -> Vec<Field> {
+        vec![
+            ("message_id", self.message_id.to_string()),
+            ("successful", self.successful.to_string()),
+            ("data", self.data.as_deref().map_or("none".to_owned(), bytes)),
+        ]
+    }
+}
+
+impl Inspect for LoginAcknowledged {
+    fn fields(self) -> Vec<Field> {
+        Vec::new()
+    }
+}
+
+impl Inspect for Disconnect {
+    fn fields(self) -> Vec<Field> {
+        vec![("reason", text(&self.reason))]
+    }
+}
+
+impl Inspect for PluginMessage {
+    fn fields(self) -> Vec<Field> {
+        vec![
+            ("channel", format!("{:?}", self.channel)),
+            ("data", bytes(&self.data)),
+        ]
+    }
+}
+
+impl Inspect for JoinGame {
+    fn fields(self) -> Vec<Field> {
+        vec![
+            ("entity_id", self.entity_id.to_string()),
+            ("is_hardcore", self.is_hardcore.to_string()),
+            ("gamemode", self.gamemode.to_string()),
+            ("previous_gamemode", self.previous_gamemode.to_string()),
+            ("dimensions_names", format!("{:?}", self.dimensions_names)),
+            ("registry", self.registry.to_string()),
+            ("dimension_type", format!("{:?}", self.dimension_type)),
+            ("dimension_name", format!("{:?}", self.dimension_name)),
+            ("hashed_seed", self.hashed_seed.to_string()),
+            ("max_players", self.max_players.to_string()),
+            ("view_distance", self.view_distance.to_string()),
+            ("simulation_distance", self.simulation_distance.to_string()),
+            ("reduced_debug_info", self.reduced_debug_info.to_string()),
+            ("respawn_screen", self.respawn_screen.to_string()),
+            ("is_debug", self.is_debug.to_string()),
+            ("is_flat", self.is_flat.to_string()),
+            ("last_death", death(&self.last_death)),
+        ]
+    }
+}
+
+impl Inspect for Respawn {
+    fn fields(self) -> Vec<Field> {
+        vec![
+            ("dimension_type", format!("{:?}", self.dimension_type)),
+            ("dimension_name", format!("{:?}", self.dimension_name)),
+            ("hashed_seed", self.hashed_seed.to_string()),
+            ("gamemode", self.gamemode.to_string()),
+            ("previous_gamemode", self.previous_gamemode.to_string()),
+            ("is_debug", self.is_debug.to_string()),
+            ("is_flat", self.is_flat.to_string()),
+            ("data_kept", self.data_kept.to_string()),
+            ("last_death", death(&self.last_death)),
+        ]
+    }
+}
+
+impl Inspect for PlayerPosition {
+    fn fields(self) -> Vec<Field> {
+        vec![
+            ("x", self.x.to_string()),
+            ("y", self.y.to_string()),
+            ("z", self.z.to_string()),
+            ("yaw", self.yaw.to_string()),
+            ("pitch", self.pitch.to_string()),
+            ("flags", format!("{:#04x}", self.flags)),
+            ("teleport_id", self.teleport_id.to_string()),
+            ("dismount_vehicle", self.dismount_vehicle.to_string()),
+        ]
+    }
+}
+
+impl Inspect for BossBar {
+    fn fields(self) -> Vec<Field> {
+        let mut fields = vec![("uuid", self.uuid.to_string())];
+        match self.action {
+            BossBarAction::Add {
+                title,
+                health,
+                color,
+                division,
+                flags,
+            } => fields.extend([
+                ("action", "Add".to_owned()),
+                ("title", text(&title)),
+                ("health", health.to_string()),
+                ("color", format!("{:?}", color)),
+                ("division", format!("{:?}", division)),
+                ("flags", format!("{:#04x}", flags)),
+            ]),
+            BossBarAction::Remove => fields.push(("action", "Remove".to_owned())),
+            BossBarAction::UpdateHealth(health) => fields.extend([
+                ("action", "UpdateHealth".to_owned()),
+                ("health", health.to_string()),
+            ]),
+            BossBarAction::UpdateTitle(title) => fields.extend([
+                ("action", "UpdateTitle".to_owned()),
+                ("title", text(&title)),
+            ]),
+            BossBarAction::UpdateStyle(color, division) => fields.extend([
+                ("action", "UpdateStyle".to_owned()),
+                ("color", format!("{:?}", color)),
+                ("division", format!("{:?}", division)),
+            ]),
+            BossBarAction::UpdateFlags(flags) => fields.extend([
+                ("action", "UpdateFlags".to_owned()),
+                ("flags", format!("{:#04x}", flags)),
+            ]),
+        }
+        fields
+    }
+}
+
+impl Inspect for ChatCommand {
+    fn fields(self) -> Vec<Field> {
+        let arguments = self.arguments.iter().map(|(name, _)| name);
+        vec![
+            ("command", format!("{:?}", self.command)),
+            ("timestamp", self.timestamp.to_string()),
+            ("salt", self.salt.to_string()),
+            ("signed_arguments", format!("{:?}", arguments.collect::<Vec<_>>())),
+            ("message_count", self.message_count.to_string()),
+            ("acknowledged", bytes(&self.acknowledged)),
+        ]
+    }
+}
+
+impl Inspect for ChatMessage {
+    fn fields(self) -> Vec<Field> {
+        vec![
+            ("message", format!("{:?}", self.message)),
+            ("timestamp", self.timestamp.to_string()),
+            ("salt", self.salt.to_string()),
+            ("signature", self.signature.as_deref().map_or("none".to_owned(), bytes)),
+            ("message_count", self.message_count.to_string()),
+            ("acknowledged", bytes(&self.acknowledged)),
+        ]
+    }
+}
+
+impl Inspect for SystemChat {
+    fn fields(self) -> Vec<Field> {
+        vec![
+            ("content", text(&self.content)),
+            ("overlay", self.overlay.to_string()),
+        ]
+    }
+}
+
+impl Inspect for KeepAlive {
+    fn fields(self) -> Vec<Field> {
+        vec![("id", self.0.to_string())]
+    }
+}
@@ -0,0 +1,180 @@
+//! Interception of play packets by id, including packet types the proxy itself does not know.
+//!
+//! A handler is registered for a [`Packet`] type together with the direction it travels in and its
+//! id in each protocol version. Matching packets are decoded into that type before the proxy looks
+//! at them, and the handler decides what happens next: forward the packet (changed or not), drop it,
+//! inject more packets towards the same side or reply to the side it came from.
+
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use strum::IntoEnumIterator;
+
+use crate::protocol::codec::registry::PLAY_REG;
+use crate::protocol::packet::{Packet, RawPacket};
+use crate::protocol::wrappers::Player;
+use crate::protocol::{Direction, ProtocolVersion};
+
+/// An intercepted packet, handed to the handler and back.
+pub struct Intercepted<T> {
+    /// Forwarded once the handler returns, `None` drops the packet.
+    pub packet: Option<T>,
+    pub player: Player,
+    pub direction: Direction,
+    interceptors: Arc<Interceptors>,
+    injected: Vec<RawPacket>,
+    replies: Vec<RawPacket>,
+}
+
+impl<T> Intercepted<T> {
+    pub fn cancel(&mut self) {
+        self.packet = None;
+    }
+
+    /// Sends `packet` the same way as the intercepted one, right after it.
+    /// `P` can be a core packet or one registered for interception in that direction.
+    pub fn inject<P: Packet + 'static>(&mut self, packet: P) -> Result<()> {
+        let packet = self.interceptors.encode(packet, self.direction, self.player.protocol)?;
+        self.injected.push(packet);
+        Ok(())
+    }
+
+    /// Sends `packet` back to the side the intercepted one came from.
+    pub fn reply<P: Packet + 'static>(&mut self, packet: P) -> Result<()> {
+        let direction = self.direction.opposite();
+        let packet = self.interceptors.encode(packet, direction, self.player.protocol)?;
+        self.replies.push(packet);
+        Ok(())
+    }
+}
+
+/// What is left of a packet after the handlers ran.
+pub(crate) struct Outcome {
+    pub packet: Option<RawPacket>,
+    pub injected: Vec<RawPacket>,
+    pub replies: Vec<RawPacket>,
+}
+
+impl Outcome {
+    fn forward(packet: RawPacket) -> Self {
+        Self {
+            packet: Some(packet),
+            injected: Vec::new(),
+            replies: Vec::new(),
+        }
+    }
+}
+
+type Handler = Box<
+    dyn Fn(RawPacket, Player, Arc<Interceptors>) -> Result<BoxFuture<'static, Outcome>>
+        + Send
+        + Sync,
+>;
+
+#[derive(Default)]
+pub struct Interceptors {
+    /// Handlers by direction, version and packet id, in registration order.
+    routes: HashMap<(Direction, ProtocolVersion, u8), Vec<usize>>,
+    handlers: Vec<Handler>,
+    /// Ids of the registered types, so they can be injected too.
+    ids: HashMap<(TypeId, Direction, ProtocolVersion), u8>,
+}
+
+impl Interceptors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Intercepts `T` packets going in `direction` during play. Each `(version, id)` pair maps the
+    /// id from that version up to the next listed one, versions before the first are not intercepted.
+    ///
+    /// Handlers of the same packet run in registration order, until one of them drops it.
+    pub fn register<T, F, Fut>(&mut self, direction: Direction, ids: &[(ProtocolVersion, u8)], handler: F)
+    where
+        T: Packet + Send + 'static,
+        F: Fn(Intercepted<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Intercepted<T>> + Send + 'static,
+    {
+        let index = self.handlers.len();
+        let mut ids = ids.to_vec();
+        ids.sort();
+
+        for version in ProtocolVersion::iter() {
+            let Some(&(_, id)) = ids.iter().rev().find(|(first, _)| *first <= version) else {
+                continue;
+            };
+            self.routes.entry((direction, version, id)).or_default().push(index);
+            self.ids.insert((TypeId::of::<T>(), direction, version), id);
+        }
+
+        self.handlers.push(Box::new(move |packet, player, interceptors| {
+            let id = packet.id();
+            let protocol = player.protocol;
+            let intercepted = Intercepted {
+                packet: Some(packet.decode::<T>(protocol)?),
+                player,
+                direction,
+                interceptors,
+                injected: Vec::new(),
+                replies: Vec::new(),
+            };
+            let future = handler(intercepted);
+
+            Ok(Box::pin(async move {
+                let intercepted = future.await;
+                Outcome {
+                    packet: intercepted
+                        .packet
+                        .map(|packet| RawPacket::encode(packet, id, protocol)),
+                    injected: intercepted.injected,
+                    replies: intercepted.replies,
+                }
+            }))
+        }));
+    }
+
+    /// Runs the handlers registered for `packet`, forwarding it untouched when there are none.
+    pub(crate) async fn intercept(
+        self: &Arc<Self>,
+        direction: Direction,
+        player: &Player,
+        packet: RawPacket,
+    ) -> Result<Outcome> {
+        if self.routes.is_empty() {
+            return Ok(Outcome::forward(packet));
+        }
+        let Some(handlers) = self.routes.get(&(direction, player.protocol, packet.id())) else {
+            return Ok(Outcome::forward(packet));
+        };
+
+        let mut outcome = Outcome::forward(packet);
+        for &index in handlers {
+            let Some(packet) = outcome.packet.take() else {
+                break;
+            };
+            let next = self.handlers[index](packet, player.clone(), self.clone())?.await;
+
+            outcome.packet = next.packet;
+            outcome.injected.extend(next.injected);
+            outcome.replies.extend(next.replies);
+        }
+        Ok(outcome)
+    }
+
+    fn encode<P: Packet + 'static>(
+        &self,
+        packet: P,
+        direction: Direction,
+        version: ProtocolVersion,
+    ) -> Result<RawPacket> {
+        let id = match self.ids.get(&(TypeId::of::<P>(), direction, version)) {
+            Some(id) => *id,
+            None => *PLAY_REG.get_registry(direction, version).get_id::<P>()?,
+        };
+        Ok(RawPacket::encode(packet, id, version))
+    }
+}
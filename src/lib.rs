@@ -0,0 +1,32 @@
+pub mod access;
+#[cfg(feature = "api")]
+pub mod api;
+pub mod bungeecord;
+pub mod capture;
+pub mod channels;
+pub mod commands;
+pub mod component;
+pub mod config;
+#[cfg(feature = "console")]
+pub mod console;
+pub mod event;
+pub mod haproxy;
+pub mod hooks;
+pub mod inspect;
+pub mod intercept;
+pub mod logging;
+pub mod metrics;
+pub mod online;
+pub mod players;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod protocol;
+pub mod proxy;
+pub mod queue;
+pub mod throttle;
+
+mod error;
+mod handlers;
+
+pub use hooks::Hooks;
+pub use proxy::{Proxy, ProxyBuilder};
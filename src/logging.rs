@@ -0,0 +1,43 @@
+//! Log output, as text for people or as JSON lines for log pipelines.
+//!
+//! Every connection runs in a `connection` span carrying the remote `address` and, once they are
+//! known, the `protocol`, `username`, `uuid` and current `server`, so anything logged while
+//! handling it can be traced back to the player. The level is set with `RUST_LOG`, like
+//! `RUST_LOG=rower=debug`, and defaults to `info`.
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the connection span under `span`.
+    Json,
+}
+
+/// Logs to stdout in `format`, records of the `log` crate (used by dependencies) included.
+pub fn init(format: LogFormat) -> Result<()> {
+    init_with_writer(format, std::io::stdout)
+}
+
+/// Like [`init`], but writes wherever `writer` says.
+pub fn init_with_writer<W>(format: LogFormat, writer: W) -> Result<()>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(writer);
+    let result = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .try_init(),
+    };
+    result.map_err(|err| anyhow!(err))
+}
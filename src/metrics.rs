@@ -0,0 +1,430 @@
+//! Counters and gauges in the Prometheus text format, served as `/metrics` by the admin API.
+//!
+//! Everything is counted in one process wide [`METRICS`] with relaxed atomics, so recording on
+//! the hot path costs about as much as an add. Player counts are taken from [`Players`] when
+//! the metrics are rendered.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::players::Players;
+use crate::protocol::Direction;
+
+pub static METRICS: Metrics = Metrics::new();
+
+/// Upper bounds of the backend connect latency buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+/// How a login ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Login {
+    Success,
+    /// The client's version is too old.
+    Unsupported,
+    /// Turned away by the connection throttle.
+    Throttled,
+    /// Denied by a `PreLoginEvent` handler.
+    Denied,
+    /// Banned, not on the whitelist or otherwise not let in by the proxy.
+    Refused,
+    Unverified,
+    /// The backend disconnected the player during its login.
+    Kicked,
+    /// The login failed with an error, timeouts included.
+    Failed,
+    /// The client left, or the proxy shut down, while it waited in a server queue.
+    Abandoned,
+}
+
+impl Login {
+    const ALL: [Login; 9] = [
+        Login::Success,
+        Login::Unsupported,
+        Login::Throttled,
+        Login::Denied,
+        Login::Refused,
+        Login::Unverified,
+        Login::Kicked,
+        Login::Failed,
+        Login::Abandoned,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Login::Success => "success",
+            Login::Unsupported => "unsupported",
+            Login::Throttled => "throttled",
+            Login::Denied => "denied",
+            Login::Refused => "refused",
+            Login::Unverified => "unverified",
+            Login::Kicked => "kicked",
+            Login::Failed => "failed",
+            Login::Abandoned => "abandoned",
+        }
+    }
+}
+
+/// A counter per [`Direction`].
+struct PerDirection([AtomicU64; 2]);
+
+impl PerDirection {
+    const fn new() -> Self {
+        Self([AtomicU64::new(0), AtomicU64::new(0)])
+    }
+
+    fn add(&self, direction: Direction, value: u64) {
+        let index = match direction {
+            Direction::Serverbound => 0,
+            Direction::Clientbound => 1,
+        };
+        self.0[index].fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn get(&self, direction: Direction) -> u64 {
+        match direction {
+            Direction::Serverbound => load(&self.0[0]),
+            Direction::Clientbound => load(&self.0[1]),
+        }
+    }
+}
+
+/// Time spent in a codec and the bytes that went in and came out.
+struct Codec {
+    nanos: AtomicU64,
+    compressed: AtomicU64,
+    uncompressed: AtomicU64,
+}
+
+impl Codec {
+    const fn new() -> Self {
+        Self {
+            nanos: AtomicU64::new(0),
+            compressed: AtomicU64::new(0),
+            uncompressed: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, elapsed: Duration, compressed: usize, uncompressed: usize) {
+        self.nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        self.compressed
+            .fetch_add(compressed as u64, Ordering::Relaxed);
+        self.uncompressed
+            .fetch_add(uncompressed as u64, Ordering::Relaxed);
+    }
+}
+
+struct Histogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; LATENCY_BUCKETS.len()],
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, value: Duration) {
+        let seconds = value.as_secs_f64();
+        // buckets only count their own range, rendering adds them up
+        if let Some(index) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(value.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+pub struct Metrics {
+    connections: AtomicU64,
+    logins: [AtomicU64; Login::ALL.len()],
+    packets: PerDirection,
+    bytes: PerDirection,
+    compressor: Codec,
+    decompressor: Codec,
+    switches: AtomicU64,
+    backend_connect: Histogram,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            connections: AtomicU64::new(0),
+            logins: [const { AtomicU64::new(0) }; Login::ALL.len()],
+            packets: PerDirection::new(),
+            bytes: PerDirection::new(),
+            compressor: Codec::new(),
+            decompressor: Codec::new(),
+            switches: AtomicU64::new(0),
+            backend_connect: Histogram::new(),
+        }
+    }
+
+    pub(crate) fn connection(&self) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn login(&self, outcome: Login) {
+        self.logins[outcome as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A packet read from the wire, `direction` is the one it travels in.
+    pub(crate) fn packet(&self, direction: Direction) {
+        self.packets.add(direction, 1);
+    }
+
+    /// A frame of `length` bytes read from the wire, length prefix included.
+    pub(crate) fn bytes(&self, direction: Direction, length: usize) {
+        self.bytes.add(direction, length as u64);
+    }
+
+    pub(crate) fn compressed(&self, elapsed: Duration, uncompressed: usize, compressed: usize) {
+        self.compressor.record(elapsed, compressed, uncompressed);
+    }
+
+    pub(crate) fn decompressed(&self, elapsed: Duration, compressed: usize, uncompressed: usize) {
+        self.decompressor.record(elapsed, compressed, uncompressed);
+    }
+
+    pub(crate) fn switch(&self) {
+        self.switches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn backend_connected(&self, latency: Duration) {
+        self.backend_connect.observe(latency);
+    }
+
+    /// The metrics in the Prometheus text format, with the player counts of `players` on the
+    /// servers of `config`.
+    pub fn render(&self, players: &Players, config: &Config) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "rower_connections_total",
+            "counter",
+            "Connections accepted",
+        );
+        sample(
+            &mut out,
+            "rower_connections_total",
+            "",
+            load(&self.connections),
+        );
+
+        header(
+            &mut out,
+            "rower_logins_total",
+            "counter",
+            "Logins by how they ended",
+        );
+        for outcome in Login::ALL {
+            let labels = format!("outcome=\"{}\"", outcome.label());
+            sample(
+                &mut out,
+                "rower_logins_total",
+                &labels,
+                load(&self.logins[outcome as usize]),
+            );
+        }
+
+        // named servers are listed even when nobody is on them
+        let mut online = config
+            .named_servers()
+            .into_keys()
+            .map(|name| (name, 0))
+            .collect::<BTreeMap<_, _>>();
+        for player in players.all() {
+            *online
+                .entry(config.server_name(player.server))
+                .or_default() += 1;
+        }
+        header(
+            &mut out,
+            "rower_players",
+            "gauge",
+            "Players in game per backend server",
+        );
+        for (server, count) in online {
+            sample(
+                &mut out,
+                "rower_players",
+                &format!("server=\"{}\"", escape(&server)),
+                count,
+            );
+        }
+
+        header(
+            &mut out,
+            "rower_packets_total",
+            "counter",
+            "Packets read, by the direction they travel in",
+        );
+        per_direction(&mut out, "rower_packets_total", &self.packets);
+        header(
+            &mut out,
+            "rower_bytes_total",
+            "counter",
+            "Bytes read, by the direction they travel in",
+        );
+        per_direction(&mut out, "rower_bytes_total", &self.bytes);
+
+        let codecs = [
+            ("compressor", &self.compressor),
+            ("decompressor", &self.decompressor),
+        ];
+        header(
+            &mut out,
+            "rower_codec_seconds_total",
+            "counter",
+            "Time spent compressing and decompressing packets",
+        );
+        for (name, codec) in codecs {
+            let seconds = load(&codec.nanos) as f64 / 1e9;
+            sample(
+                &mut out,
+                "rower_codec_seconds_total",
+                &codec_label(name),
+                seconds,
+            );
+        }
+        header(
+            &mut out,
+            "rower_codec_compressed_bytes_total",
+            "counter",
+            "Compressed bytes that went through a codec",
+        );
+        for (name, codec) in codecs {
+            let compressed = load(&codec.compressed);
+            sample(
+                &mut out,
+                "rower_codec_compressed_bytes_total",
+                &codec_label(name),
+                compressed,
+            );
+        }
+        header(
+            &mut out,
+            "rower_codec_uncompressed_bytes_total",
+            "counter",
+            "Uncompressed bytes that went through a codec",
+        );
+        for (name, codec) in codecs {
+            let uncompressed = load(&codec.uncompressed);
+            sample(
+                &mut out,
+                "rower_codec_uncompressed_bytes_total",
+                &codec_label(name),
+                uncompressed,
+            );
+        }
+        header(
+            &mut out,
+            "rower_compression_ratio",
+            "gauge",
+            "Uncompressed bytes per compressed byte so far",
+        );
+        for (name, codec) in codecs {
+            let (compressed, uncompressed) = (load(&codec.compressed), load(&codec.uncompressed));
+            if compressed > 0 {
+                let ratio = uncompressed as f64 / compressed as f64;
+                sample(
+                    &mut out,
+                    "rower_compression_ratio",
+                    &codec_label(name),
+                    ratio,
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "rower_server_switches_total",
+            "counter",
+            "Players moved from one backend server to another",
+        );
+        sample(
+            &mut out,
+            "rower_server_switches_total",
+            "",
+            load(&self.switches),
+        );
+
+        let name = "rower_backend_connect_seconds";
+        header(
+            &mut out,
+            name,
+            "histogram",
+            "Time from connecting to a backend server to its login success",
+        );
+        let histogram = &self.backend_connect;
+        let mut cumulative = 0;
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&histogram.buckets) {
+            cumulative += load(bucket);
+            sample(
+                &mut out,
+                &format!("{}_bucket", name),
+                &format!("le=\"{}\"", bound),
+                cumulative,
+            );
+        }
+        let count = load(&histogram.count);
+        sample(&mut out, &format!("{}_bucket", name), "le=\"+Inf\"", count);
+        let sum = load(&histogram.sum_micros) as f64 / 1e6;
+        sample(&mut out, &format!("{}_sum", name), "", sum);
+        sample(&mut out, &format!("{}_count", name), "", count);
+
+        out
+    }
+}
+
+fn load(counter: &AtomicU64) -> u64 {
+    counter.load(Ordering::Relaxed)
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn per_direction(out: &mut String, name: &str, counter: &PerDirection) {
+    for (label, direction) in [
+        ("serverbound", Direction::Serverbound),
+        ("clientbound", Direction::Clientbound),
+    ] {
+        sample(
+            out,
+            name,
+            &format!("direction=\"{}\"", label),
+            counter.get(direction),
+        );
+    }
+}
+
+fn codec_label(name: &str) -> String {
+    format!("codec=\"{}\"", name)
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: impl std::fmt::Display) {
+    let _ = match labels {
+        "" => writeln!(out, "{} {}", name, value),
+        labels => writeln!(out, "{}{{{}}} {}", name, labels, value),
+    };
+}
+
+/// Escapes a label value, server names come from the config.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -23,6 +23,7 @@ pub static RSA_KEYS: LazyLock<Keys> = LazyLock::new(|| {
     }
 });
 
+#[allow(dead_code)]
 #[derive(Deserialize)]
 pub struct GameProfile {
     pub id: Uuid,
@@ -30,7 +31,7 @@ pub struct GameProfile {
     pub properties: Vec<Property>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct Property {
     pub name: String,
     pub value: String,
@@ -0,0 +1,141 @@
+//! Players that are currently in game, with a way to send packets to them and their server.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::RwLock;
+
+use anyhow::{anyhow, Result};
+use tokio::sync::mpsc::UnboundedSender;
+use uuid::Uuid;
+
+use crate::component::Component;
+use crate::protocol::codec::registry::PLAY_REG;
+use crate::protocol::packet::{Packet, RawPacket};
+use crate::protocol::wrappers::Player;
+use crate::protocol::Direction;
+
+#[derive(Clone)]
+pub struct OnlinePlayer {
+    pub player: Player,
+    /// Server the player is currently connected to.
+    pub server: SocketAddr,
+    to_client: UnboundedSender<RawPacket>,
+    to_server: UnboundedSender<RawPacket>,
+    requests: UnboundedSender<Request>,
+}
+
+/// What the connection of a player is asked to do.
+pub(crate) enum Request {
+    Connect(SocketAddr),
+    /// Sends the client the reason and closes both sides.
+    Disconnect(Component),
+}
+
+impl OnlinePlayer {
+    pub(crate) fn new(
+        player: Player,
+        server: SocketAddr,
+        to_client: UnboundedSender<RawPacket>,
+        to_server: UnboundedSender<RawPacket>,
+        requests: UnboundedSender<Request>,
+    ) -> Self {
+        Self { player, server, to_client, to_server, requests }
+    }
+
+    /// Sends a play packet to the player's client.
+    pub fn send_to_client<P: Packet + 'static>(&self, packet: P) -> Result<()> {
+        self.send(&self.to_client, packet, Direction::Clientbound)
+    }
+
+    /// Sends a play packet to the server the player is on.
+    pub fn send_to_server<P: Packet + 'static>(&self, packet: P) -> Result<()> {
+        self.send(&self.to_server, packet, Direction::Serverbound)
+    }
+
+    /// Moves the player to another server, they stay where they are if it can't be reached, and
+    /// wait in its queue there when queues are enabled.
+    pub fn connect(&self, server: SocketAddr) -> Result<()> {
+        self.request(Request::Connect(server))
+    }
+
+    /// Sends the client a disconnect, then closes its connection and the one to its server, a
+    /// client that ignores the packet is not left connected.
+    pub fn disconnect(&self, reason: Component) -> Result<()> {
+        self.request(Request::Disconnect(reason))
+    }
+
+    fn request(&self, request: Request) -> Result<()> {
+        self.requests
+            .send(request)
+            .map_err(|_| anyhow!("{} is not connected anymore", self.player.username))
+    }
+
+    fn send<P: Packet + 'static>(
+        &self,
+        sender: &UnboundedSender<RawPacket>,
+        packet: P,
+        direction: Direction,
+    ) -> Result<()> {
+        let version = self.player.protocol;
+        let id = PLAY_REG.get_registry(direction, version).get_id::<P>()?;
+        sender
+            .send(RawPacket::encode(packet, *id, version))
+            .map_err(|_| anyhow!("{} is not connected anymore", self.player.username))
+    }
+}
+
+#[derive(Default)]
+pub struct Players {
+    players: RwLock<HashMap<Uuid, OnlinePlayer>>,
+}
+
+impl Players {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, uuid: &Uuid) -> Option<OnlinePlayer> {
+        self.players.read().unwrap().get(uuid).cloned()
+    }
+
+    /// Looks a player up by name, ignoring case.
+    pub fn find(&self, username: &str) -> Option<OnlinePlayer> {
+        self.players
+            .read()
+            .unwrap()
+            .values()
+            .find(|online| online.player.username.eq_ignore_ascii_case(username))
+            .cloned()
+    }
+
+    pub fn all(&self) -> Vec<OnlinePlayer> {
+        self.players.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn count(&self) -> usize {
+        self.players.read().unwrap().len()
+    }
+
+    pub(crate) fn insert(&self, online: OnlinePlayer) {
+        self.players.write().unwrap().insert(online.player.uuid, online);
+    }
+
+    /// Removes the player, unless the entry already belongs to a newer session of the same player.
+    /// Returns the removed entry, with the server the player was on last.
+    pub(crate) fn remove(&self, online: &OnlinePlayer) -> Option<OnlinePlayer> {
+        let mut players = self.players.write().unwrap();
+        if players
+            .get(&online.player.uuid)
+            .is_some_and(|current| current.to_client.same_channel(&online.to_client))
+        {
+            return players.remove(&online.player.uuid);
+        }
+        None
+    }
+
+    pub(crate) fn set_server(&self, uuid: &Uuid, server: SocketAddr) {
+        if let Some(online) = self.players.write().unwrap().get_mut(uuid) {
+            online.server = server;
+        }
+    }
+}
@@ -0,0 +1,608 @@
+//! WebAssembly plugins, loaded from the `.wasm` modules in the plugins directory.
+//!
+//! Every plugin runs in its own store, limited to `plugin_memory_limit` MiB of memory and
+//! `plugin_fuel` units of fuel per call into it. A plugin that traps or runs out of fuel only loses
+//! that call, the event goes on with whatever the plugin changed before. Calls run on the runtime's
+//! blocking threads, one at a time per plugin.
+//!
+//! # ABI (version 1)
+//!
+//! A plugin exports its `memory` and
+//! - `rower_abi_version() -> i32`, returning [`ABI_VERSION`]
+//! - `rower_alloc(len: i32) -> i32`, memory for values the host hands over, owned by the plugin after
+//! - `rower_init()` (optional), called once after loading, where the plugin subscribes to events
+//! - `rower_on_event(event: i32)`, called for every event it subscribed to
+//!
+//! The host functions are imported from the `rower` module. Values returned to the plugin are
+//! allocated with `rower_alloc` and packed as `(ptr << 32) | len`, or -1 when there is none.
+//! - `log(level: i32, ptr: i32, len: i32)`, level 0 (error) to 4 (trace)
+//! - `subscribe(event: i32, priority: i32)`, priority 0 ([`Priority::First`]) to 4 ([`Priority::Last`])
+//! - `get_field(name_ptr: i32, name_len: i32) -> i64`, a field of the event being handled
+//! - `set_field(name_ptr: i32, name_len: i32, value_ptr: i32, value_len: i32) -> i32`, 0 or -1 when
+//!   the field is read-only or the value is invalid
+//! - `players() -> i64`, players in game, one `uuid username server` line each
+//! - `component(ptr: i32, len: i32) -> i64`, JSON of a component built from text with legacy `&` codes
+//! - `send_plugin_message(uuid_ptr: i32, uuid_len: i32, channel_ptr: i32, channel_len: i32,
+//!   data_ptr: i32, data_len: i32) -> i32`, sends a plugin message to a player's client, 0 or -1
+//!   when the player is not online
+//!
+//! Fields are UTF-8 text except plugin message data, booleans are `true` or `false` and components
+//! JSON. Player events have the `username`, `uuid`, `address` and `protocol` fields of the player.
+//!
+//! | id | event                                  | fields, writable ones in bold            |
+//! |----|----------------------------------------|------------------------------------------|
+//! | 0  | [`PreLoginEvent`]                      | username, address, protocol, **denied**  |
+//! | 1  | [`PostLoginEvent`]                     | player                                   |
+//! | 2  | [`ServerConnectedEvent`]               | player, server, previous                 |
+//! | 3  | [`ChatEvent`]                          | player, **message**, **cancelled**       |
+//! | 4  | [`CommandEvent`]                       | player, **command**, **cancelled**       |
+//! | 5  | [`DisconnectEvent`]                    | player                                   |
+//! | 6  | plugin message from server to player   | player, channel, **data**, **cancelled** |
+
+use std::any::Any;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, ensure, Result};
+use bytes::Bytes;
+use tokio::task;
+use tracing::{debug, error, info, trace, warn};
+use strum::IntoEnumIterator;
+use uuid::Uuid;
+use wasmtime::{Caller, Engine, Extern, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc};
+
+use crate::component::Component;
+use crate::config::Config;
+use crate::event::{
+    ChatEvent, CommandEvent, DisconnectEvent, Event, EventBus, PostLoginEvent, PreLoginEvent,
+    Priority, ServerConnectedEvent,
+};
+use crate::intercept::{Intercepted, Interceptors};
+use crate::players::Players;
+use crate::protocol::codec::registry::PLAY_REG;
+use crate::protocol::packet::play::PluginMessage;
+use crate::protocol::wrappers::Player;
+use crate::protocol::{Direction, ProtocolVersion};
+
+pub const ABI_VERSION: i32 = 1;
+
+/// Loads every plugin in `dir` and registers the events they subscribed to.
+/// A missing directory means no plugins, a plugin that fails to load is skipped.
+pub(crate) fn load(
+    dir: &Path,
+    config: &Config,
+    events: &mut EventBus,
+    interceptors: &mut Interceptors,
+    players: Arc<Players>,
+) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+
+    let mut wasm_config = wasmtime::Config::new();
+    wasm_config.consume_fuel(true);
+    let engine = Engine::new(&wasm_config)?;
+    let linker = linker(&engine)?;
+
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| path.extension().is_some_and(|extension| extension == "wasm"));
+    paths.sort();
+
+    let mut subscriptions = Vec::new();
+    for path in paths {
+        match Plugin::load(&engine, &linker, &path, config, players.clone()) {
+            Ok((plugin, subscribed)) => {
+                info!("Loaded plugin {}", plugin.name);
+                let plugin = Arc::new(plugin);
+                for (event, priority) in subscribed {
+                    subscriptions.push((priority, event, plugin.clone()));
+                }
+            }
+            Err(err) => error!("Failed to load plugin {}: {:#}", path.display(), err),
+        }
+    }
+
+    // interceptors run in registration order, so plugin messages need them sorted here
+    subscriptions.sort_by_key(|(priority, ..)| *priority);
+    for (priority, event, plugin) in subscriptions {
+        subscribe(event, priority, plugin, events, interceptors);
+    }
+    Ok(())
+}
+
+fn subscribe(
+    event: i32,
+    priority: Priority,
+    plugin: Arc<Plugin>,
+    events: &mut EventBus,
+    interceptors: &mut Interceptors,
+) {
+    match event {
+        PreLoginEvent::ID => on::<PreLoginEvent>(events, priority, plugin),
+        PostLoginEvent::ID => on::<PostLoginEvent>(events, priority, plugin),
+        ServerConnectedEvent::ID => on::<ServerConnectedEvent>(events, priority, plugin),
+        ChatEvent::ID => on::<ChatEvent>(events, priority, plugin),
+        CommandEvent::ID => on::<CommandEvent>(events, priority, plugin),
+        DisconnectEvent::ID => on::<DisconnectEvent>(events, priority, plugin),
+        PluginMessageEvent::ID => {
+            let ids = ProtocolVersion::iter()
+                .filter_map(|version| {
+                    let registry = PLAY_REG.get_registry(Direction::Clientbound, version);
+                    Some((version, *registry.get_id::<PluginMessage>().ok()?))
+                })
+                .collect::<Vec<_>>();
+
+            interceptors.register(
+                Direction::Clientbound,
+                &ids,
+                move |mut intercepted: Intercepted<PluginMessage>| {
+                    let plugin = plugin.clone();
+                    async move {
+                        if let Some(message) = intercepted.packet.take() {
+                            let event = PluginMessageEvent {
+                                player: intercepted.player.clone(),
+                                message,
+                                cancelled: false,
+                            };
+                            let event = plugin.fire_blocking(event).await;
+                            if !event.cancelled {
+                                intercepted.packet = Some(event.message);
+                            }
+                        }
+                        intercepted
+                    }
+                },
+            );
+        }
+        _ => warn!("Plugin {} subscribed to unknown event {}", plugin.name, event),
+    }
+}
+
+fn on<E: PluginEvent>(events: &mut EventBus, priority: Priority, plugin: Arc<Plugin>) {
+    events.register(priority, move |event: E| {
+        let plugin = plugin.clone();
+        plugin.fire_blocking(event)
+    });
+}
+
+struct Plugin {
+    name: String,
+    /// Fuel for every call into the plugin.
+    fuel: u64,
+    store: Mutex<Store<State>>,
+    on_event: TypedFunc<i32, ()>,
+}
+
+/// Store data of a plugin.
+struct State {
+    name: String,
+    limits: StoreLimits,
+    players: Arc<Players>,
+    /// The event being handled, between the host putting it in and taking it back.
+    event: Option<Box<dyn Fields>>,
+    subscriptions: Vec<(i32, Priority)>,
+}
+
+impl Plugin {
+    fn load(
+        engine: &Engine,
+        linker: &Linker<State>,
+        path: &Path,
+        config: &Config,
+        players: Arc<Players>,
+    ) -> Result<(Self, Vec<(i32, Priority)>)> {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let module = Module::from_file(engine, path)?;
+
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(config.plugin_memory_limit << 20)
+            .build();
+        let mut store = Store::new(
+            engine,
+            State {
+                name: name.clone(),
+                limits,
+                players,
+                event: None,
+                subscriptions: Vec::new(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+
+        store.set_fuel(config.plugin_fuel)?;
+        let instance = linker.instantiate(&mut store, &module)?;
+
+        let version = instance
+            .get_typed_func::<(), i32>(&mut store, "rower_abi_version")?
+            .call(&mut store, ())?;
+        ensure!(
+            version == ABI_VERSION,
+            "Plugin was built for ABI version {}, the proxy supports {}",
+            version,
+            ABI_VERSION
+        );
+        instance.get_typed_func::<i32, i32>(&mut store, "rower_alloc")?;
+        let on_event = instance.get_typed_func::<i32, ()>(&mut store, "rower_on_event")?;
+
+        if let Ok(init) = instance.get_typed_func::<(), ()>(&mut store, "rower_init") {
+            store.set_fuel(config.plugin_fuel)?;
+            init.call(&mut store, ())?;
+        }
+        let subscriptions = std::mem::take(&mut store.data_mut().subscriptions);
+
+        let plugin = Self {
+            name,
+            fuel: config.plugin_fuel,
+            store: Mutex::new(store),
+            on_event,
+        };
+        Ok((plugin, subscriptions))
+    }
+
+    /// [`Plugin::fire`] on a blocking thread, a call can run for as long as its fuel lasts and
+    /// must not hold up the runtime's workers meanwhile.
+    async fn fire_blocking<E: PluginEvent>(self: Arc<Self>, event: E) -> E {
+        task::spawn_blocking(move || self.fire(event))
+            .await
+            .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+    }
+
+    /// Runs the plugin's handler with `event` and hands it back, changed by the plugin or not.
+    fn fire<E: PluginEvent>(&self, event: E) -> E {
+        let mut store = self.store.lock().unwrap();
+        store.data_mut().event = Some(Box::new(event));
+
+        let result = store
+            .set_fuel(self.fuel)
+            .and_then(|_| self.on_event.call(&mut *store, E::ID));
+        if let Err(err) = result {
+            error!("Plugin {} failed handling event {}: {:#}", self.name, E::ID, err);
+        }
+
+        let event: Box<dyn Any> = store.data_mut().event.take().expect("event is gone");
+        *event.downcast().expect("event changed its type")
+    }
+}
+
+fn linker(engine: &Engine) -> Result<Linker<State>> {
+    let mut linker = Linker::new(engine);
+
+    linker.func_wrap(
+        "rower",
+        "log",
+        |mut caller: Caller<'_, State>, level: i32, ptr: i32, len: i32| {
+            let message = read_string(&mut caller, ptr, len)?;
+            let plugin = &caller.data().name;
+            match level {
+                0 => error!(plugin, "{}", message),
+                1 => warn!(plugin, "{}", message),
+                2 => info!(plugin, "{}", message),
+                3 => debug!(plugin, "{}", message),
+                _ => trace!(plugin, "{}", message),
+            }
+            Ok(())
+        },
+    )?;
+
+    linker.func_wrap(
+        "rower",
+        "subscribe",
+        |mut caller: Caller<'_, State>, event: i32, priority: i32| {
+            let priority = match priority {
+                0 => Priority::First,
+                1 => Priority::Early,
+                3 => Priority::Late,
+                4 => Priority::Last,
+                _ => Priority::Normal,
+            };
+            caller.data_mut().subscriptions.push((event, priority));
+        },
+    )?;
+
+    linker.func_wrap(
+        "rower",
+        "get_field",
+        |mut caller: Caller<'_, State>, ptr: i32, len: i32| {
+            let name = read_string(&mut caller, ptr, len)?;
+            let value = caller.data().event.as_ref().and_then(|event| event.get(&name));
+            match value {
+                Some(value) => write(&mut caller, &value),
+                None => Ok(-1),
+            }
+        },
+    )?;
+
+    linker.func_wrap(
+        "rower",
+        "set_field",
+        |mut caller: Caller<'_, State>, name_ptr: i32, name_len: i32, ptr: i32, len: i32| {
+            let name = read_string(&mut caller, name_ptr, name_len)?;
+            let value = read(&mut caller, ptr, len)?;
+            let event = caller.data_mut().event.as_mut();
+            Ok(match event.and_then(|event| event.set(&name, &value)) {
+                Some(()) => 0,
+                None => -1,
+            })
+        },
+    )?;
+
+    linker.func_wrap("rower", "players", |mut caller: Caller<'_, State>| {
+        let players = caller
+            .data()
+            .players
+            .all()
+            .into_iter()
+            .map(|online| format!("{} {} {}\n", online.player.uuid, online.player.username, online.server))
+            .collect::<String>();
+        write(&mut caller, players.as_bytes())
+    })?;
+
+    linker.func_wrap(
+        "rower",
+        "component",
+        |mut caller: Caller<'_, State>, ptr: i32, len: i32| {
+            let text = read_string(&mut caller, ptr, len)?;
+            let json = serde_json::to_vec(&Component::legacy(&text))?;
+            write(&mut caller, &json)
+        },
+    )?;
+
+    linker.func_wrap(
+        "rower",
+        "send_plugin_message",
+        |mut caller: Caller<'_, State>,
+         uuid_ptr: i32,
+         uuid_len: i32,
+         channel_ptr: i32,
+         channel_len: i32,
+         data_ptr: i32,
+         data_len: i32| {
+            let uuid = read_string(&mut caller, uuid_ptr, uuid_len)?;
+            let channel = read_string(&mut caller, channel_ptr, channel_len)?;
+            let data = read(&mut caller, data_ptr, data_len)?;
+
+            let online = Uuid::parse_str(&uuid).ok().and_then(|uuid| caller.data().players.get(&uuid));
+            let sent = online.is_some_and(|online| {
+                online
+                    .send_to_client(PluginMessage {
+                        channel,
+                        data: Bytes::from(data),
+                    })
+                    .is_ok()
+            });
+            Ok(if sent { 0 } else { -1 })
+        },
+    )?;
+
+    Ok(linker)
+}
+
+fn memory(caller: &mut Caller<'_, State>) -> Result<Memory> {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| anyhow!("Plugin does not export its memory"))
+}
+
+fn read(caller: &mut Caller<'_, State>, ptr: i32, len: i32) -> Result<Vec<u8>> {
+    let memory = memory(caller)?;
+    let start = ptr as u32 as usize;
+    let end = start + len as u32 as usize;
+
+    let bytes = memory
+        .data(&caller)
+        .get(start..end)
+        .ok_or_else(|| anyhow!("Plugin passed memory out of bounds"))?;
+    Ok(bytes.to_vec())
+}
+
+fn read_string(caller: &mut Caller<'_, State>, ptr: i32, len: i32) -> Result<String> {
+    Ok(String::from_utf8(read(caller, ptr, len)?)?)
+}
+
+/// Copies `bytes` into memory allocated by the plugin, returning them packed for it.
+fn write(caller: &mut Caller<'_, State>, bytes: &[u8]) -> Result<i64> {
+    let alloc = caller
+        .get_export("rower_alloc")
+        .and_then(Extern::into_func)
+        .ok_or_else(|| anyhow!("Plugin does not export rower_alloc"))?
+        .typed::<i32, i32>(&caller)?;
+    let len = i32::try_from(bytes.len())?;
+    let ptr = alloc.call(&mut *caller, len)?;
+
+    memory(caller)?.write(&mut *caller, ptr as u32 as usize, bytes)?;
+    Ok(((ptr as u32 as i64) << 32) | len as i64)
+}
+
+/// An event as plugins see it, a set of named fields.
+trait Fields: Any + Send {
+    fn get(&self, name: &str) -> Option<Vec<u8>>;
+
+    /// `None` when the field can not be set or the value is invalid.
+    fn set(&mut self, name: &str, value: &[u8]) -> Option<()>;
+}
+
+trait PluginEvent: Event + Fields {
+    const ID: i32;
+}
+
+/// A plugin message the server sends to the player.
+struct PluginMessageEvent {
+    player: Player,
+    message: PluginMessage,
+    cancelled: bool,
+}
+
+impl Event for PluginMessageEvent {}
+
+fn text(value: impl ToString) -> Option<Vec<u8>> {
+    Some(value.to_string().into_bytes())
+}
+
+fn player_field(player: &Player, name: &str) -> Option<Vec<u8>> {
+    match name {
+        "username" => text(&player.username),
+        "uuid" => text(player.uuid),
+        "address" => text(player.address),
+        "protocol" => text(i32::from(player.protocol)),
+        _ => None,
+    }
+}
+
+fn parse_text(value: &[u8]) -> Option<String> {
+    String::from_utf8(value.to_vec()).ok()
+}
+
+fn parse_bool(value: &[u8]) -> Option<bool> {
+    match value {
+        b"true" => Some(true),
+        b"false" => Some(false),
+        _ => None,
+    }
+}
+
+impl Fields for PreLoginEvent {
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        match name {
+            "username" => text(&self.username),
+            "address" => text(self.address),
+            "protocol" => text(i32::from(self.protocol)),
+            "denied" => serde_json::to_vec(self.denied.as_ref()?).ok(),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, name: &str, value: &[u8]) -> Option<()> {
+        match name {
+            "denied" => self.denied = Some(serde_json::from_slice(value).ok()?),
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+impl Fields for PostLoginEvent {
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        player_field(&self.player, name)
+    }
+
+    fn set(&mut self, _: &str, _: &[u8]) -> Option<()> {
+        None
+    }
+}
+
+impl Fields for ServerConnectedEvent {
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        match name {
+            "server" => text(self.server),
+            "previous" => text(self.previous?),
+            _ => player_field(&self.player, name),
+        }
+    }
+
+    fn set(&mut self, _: &str, _: &[u8]) -> Option<()> {
+        None
+    }
+}
+
+impl Fields for ChatEvent {
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        match name {
+            "message" => text(&self.message),
+            "cancelled" => text(self.cancelled),
+            _ => player_field(&self.player, name),
+        }
+    }
+
+    fn set(&mut self, name: &str, value: &[u8]) -> Option<()> {
+        match name {
+            "message" => self.message = parse_text(value)?,
+            "cancelled" => self.cancelled = parse_bool(value)?,
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+impl Fields for CommandEvent {
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        match name {
+            "command" => text(&self.command),
+            "cancelled" => text(self.cancelled),
+            _ => player_field(&self.player, name),
+        }
+    }
+
+    fn set(&mut self, name: &str, value: &[u8]) -> Option<()> {
+        match name {
+            "command" => self.command = parse_text(value)?,
+            "cancelled" => self.cancelled = parse_bool(value)?,
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+impl Fields for DisconnectEvent {
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        player_field(&self.player, name)
+    }
+
+    fn set(&mut self, _: &str, _: &[u8]) -> Option<()> {
+        None
+    }
+}
+
+impl Fields for PluginMessageEvent {
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        match name {
+            "channel" => text(&self.message.channel),
+            "data" => Some(self.message.data.to_vec()),
+            "cancelled" => text(self.cancelled),
+            _ => player_field(&self.player, name),
+        }
+    }
+
+    fn set(&mut self, name: &str, value: &[u8]) -> Option<()> {
+        match name {
+            "data" => self.message.data = Bytes::copy_from_slice(value),
+            "cancelled" => self.cancelled = parse_bool(value)?,
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+impl PluginEvent for PreLoginEvent {
+    const ID: i32 = 0;
+}
+
+impl PluginEvent for PostLoginEvent {
+    const ID: i32 = 1;
+}
+
+impl PluginEvent for ServerConnectedEvent {
+    const ID: i32 = 2;
+}
+
+impl PluginEvent for ChatEvent {
+    const ID: i32 = 3;
+}
+
+impl PluginEvent for CommandEvent {
+    const ID: i32 = 4;
+}
+
+impl PluginEvent for DisconnectEvent {
+    const ID: i32 = 5;
+}
+
+impl PluginEvent for PluginMessageEvent {
+    const ID: i32 = 6;
+}
@@ -8,7 +8,7 @@ pub mod packet;
 pub mod util;
 pub mod wrappers;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum State {
     Handshake,
     Status,
@@ -33,7 +33,7 @@ impl State {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Direction {
     Clientbound,
     Serverbound,
@@ -42,10 +42,17 @@ pub enum Direction {
 impl Direction {
     pub const CLIENTBOUND: bool = true;
     pub const SERVERBOUND: bool = !Self::CLIENTBOUND;
+
+    pub const fn opposite(self) -> Self {
+        match self {
+            Direction::Clientbound => Direction::Serverbound,
+            Direction::Serverbound => Direction::Clientbound,
+        }
+    }
 }
 
 #[repr(usize)]
-#[derive(PartialOrd, Ord, PartialEq, Eq, Copy, Clone, EnumIter, Debug)]
+#[derive(PartialOrd, Ord, PartialEq, Eq, Hash, Copy, Clone, EnumIter, Debug)]
 pub enum ProtocolVersion {
     Unknown,
     V1_7_2,
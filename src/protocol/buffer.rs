@@ -26,7 +26,7 @@ pub trait BufExt: Buf {
     }
 
     fn get_bool(&mut self) -> Result<bool> {
-        match self.get_u8() {
+        match self.try_get_u8()? {
             0x00 => Ok(false),
             0x01 => Ok(true),
             byte => Err(anyhow!("Could not get bool value from byte: {}", byte)),
@@ -38,6 +38,7 @@ pub trait BufExt: Buf {
 
         ensure!(len >= 0, "String lenght is negative");
         ensure!(len <= 3 * cap, "String is too long");
+        ensure!(len as usize <= self.remaining(), "Invalid string lenght");
 
         let bytes = self.copy_to_bytes(len as usize);
         Ok(String::from_utf8(bytes.to_vec())?)
@@ -49,21 +50,23 @@ pub trait BufExt: Buf {
 
     fn get_component(&mut self) -> Result<Component> {
         let len = self.get_varint()? as usize;
+        ensure!(len <= self.remaining(), "Invalid component lenght");
         let reader = self.take(len).reader();
         Ok(serde_json::from_reader(reader)?)
     }
 
-    fn get_uuid(&mut self) -> Uuid {
+    fn get_uuid(&mut self) -> Result<Uuid> {
         let mut bytes = [0; 16];
-        self.copy_to_slice(&mut bytes);
-        Uuid::from_bytes(bytes)
+        self.try_copy_to_slice(&mut bytes)?;
+        Ok(Uuid::from_bytes(bytes))
     }
 
     fn get_bitset(&mut self) -> Result<Vec<i64>> {
-        let len = self.get_varint()?;
-        let mut vec = Vec::with_capacity(len as usize);
+        let len = self.get_varint()? as usize;
+        ensure!(len <= self.remaining() / 8, "Invalid bitset lenght");
+        let mut vec = Vec::with_capacity(len);
         for _ in 0..len {
-            vec.push(self.get_i64())
+            vec.push(self.try_get_i64()?)
         }
         Ok(vec)
     }
@@ -1,6 +1,8 @@
-mod decoder;
-mod encoder;
+pub mod decoder;
+pub mod encoder;
 pub mod connection;
 pub mod registry;
 mod util;
 mod cipher;
+
+pub use decoder::decompress;
@@ -1,8 +1,10 @@
 use anyhow::{anyhow, ensure, Context, Result};
-use std::{any::type_name, net::SocketAddr};
+use std::{any::type_name, net::SocketAddr, sync::Arc, time::Duration};
 
 use bytes::Buf;
+use libdeflater::CompressionLvl;
 use futures::{SinkExt, StreamExt};
+use tokio::io::AsyncWriteExt;
 use tokio::net::{
     tcp::{OwnedReadHalf, OwnedWriteHalf},
     TcpStream,
@@ -10,7 +12,10 @@ use tokio::net::{
 use tokio_util::codec::{FramedRead, FramedWrite};
 
 use crate::{
+    capture::Capture,
     component::Component,
+    haproxy,
+    metrics::METRICS,
     protocol::{
         packet::{login::Disconnect, Packet, PacketType, RawPacket},
         Direction, ProtocolVersion, State,
@@ -23,15 +28,40 @@ use super::{
     registry::{get_protocol_registry, ProtocolRegistry, HANDSHAKE_REG},
 };
 
+/// Decodes `packet` with the producer `registry` has for its id, [`PacketType::Raw`] when it has none.
+pub fn decode_packet(
+    registry: &ProtocolRegistry,
+    mut packet: RawPacket,
+    protocol: ProtocolVersion,
+) -> Result<PacketType> {
+    if let Some(producer) = registry.get_packet(packet.id()) {
+        let mut data = packet.data()?;
+        let result = producer(&mut data, protocol)?;
+        ensure!(data.is_empty(), "Packet was not been fully read");
+        Ok(result)
+    } else {
+        Ok(PacketType::Raw(packet))
+    }
+}
+
 pub struct Connection {
     pub protocol: ProtocolVersion,
     direction: Direction,
+    state: State,
 
     receive_registry: &'static ProtocolRegistry,
     send_registry: &'static ProtocolRegistry,
 
     framed_read: FramedRead<OwnedReadHalf, MinecraftDecoder>,
     framed_write: FramedWrite<OwnedWriteHalf, MinecraftEncoder>,
+    /// How long a read may wait for the next packet, it belongs to the read half.
+    read_timeout: Option<Duration>,
+    /// Where packets read are captured, it belongs to the read half.
+    read_capture: Option<Arc<Capture>>,
+    /// Where packets written are captured with the direction they go in, it belongs to the write half.
+    write_capture: Option<(Arc<Capture>, Direction)>,
+    /// Leave compressed packets read compressed, it belongs to the read half.
+    compression_passthrough: bool,
 }
 
 impl Connection {
@@ -42,12 +72,17 @@ impl Connection {
         Self {
             protocol,
             direction,
+            state: State::Handshake,
 
             receive_registry,
             send_registry,
 
-            framed_read: FramedRead::new(reader, MinecraftDecoder::new()),
+            framed_read: FramedRead::new(reader, MinecraftDecoder::counted(direction.opposite())),
             framed_write: FramedWrite::new(writer, MinecraftEncoder::new()),
+            read_timeout: None,
+            read_capture: None,
+            write_capture: None,
+            compression_passthrough: false,
         }
     }
 
@@ -55,38 +90,56 @@ impl Connection {
         Self::create(stream, ProtocolVersion::Unknown, direction)
     }
 
+    /// Connects to `addr`, with `proxied_client` the stream starts with a PROXY protocol header
+    /// naming it, see [`crate::haproxy`].
     pub async fn connect_to(
         addr: SocketAddr,
         version: ProtocolVersion,
         direction: Direction,
+        proxied_client: Option<SocketAddr>,
     ) -> Result<Self> {
-        let stream = TcpStream::connect(addr).await?;
+        let mut stream = TcpStream::connect(addr).await?;
         stream.set_nodelay(true)?;
+        if let Some(client) = proxied_client {
+            stream.write_all(&haproxy::header(client, addr)).await?;
+        }
         Ok(Self::create(stream, version, direction))
     }
 
+    /// Makes reads fail once no packet arrived for `timeout`, `None` waits forever.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+
+    /// Captures every packet read or written from now on, see [`crate::capture`].
+    pub fn capture(&mut self, capture: Arc<Capture>) {
+        self.read_capture = Some(capture.clone());
+        self.write_capture = Some((capture, self.direction));
+    }
+
     pub fn change_state(&mut self, state: State) {
+        self.state = state;
         (self.receive_registry, self.send_registry) =
             get_protocol_registry(state, self.protocol, self.direction);
     }
 
     pub async fn auto_read(&mut self) -> Result<PacketType> {
-        let mut packet = self.recv_raw_packet().await?;
+        let packet = self.recv_raw_packet().await?;
+        self.decode(packet)
+    }
 
-        if let Some(producer) = self.receive_registry.get_packet(packet.id()) {
-            let mut data = packet.data();
-            let result = producer(&mut data, self.protocol)?;
-            ensure!(data.is_empty(), "Packet was not been fully read");
-            Ok(result)
-        } else {
-            Ok(PacketType::Raw(packet))
-        }
+    /// Decodes a packet read with [`Connection::recv_raw_packet`] the way [`Connection::auto_read`] does.
+    pub fn decode(&self, packet: RawPacket) -> Result<PacketType> {
+        decode_packet(self.receive_registry, packet, self.protocol)
     }
 
     pub async fn recv_packet<T: Packet + 'static>(&mut self) -> Result<T> {
         let expected_id = self.receive_registry.get_id::<T>()?;
 
-        let mut frame = self.recv_raw_packet().await?.buffer;
+        let mut packet = self.recv_raw_packet().await?;
+        packet.decompress()?;
+
+        let mut frame = packet.buffer;
         let id = frame.get_u8();
 
         ensure!(
@@ -106,21 +159,38 @@ impl Connection {
     }
 
     pub async fn recv_raw_packet(&mut self) -> Result<RawPacket> {
-        match self.framed_read.next().await {
-            Some(result) => Ok(RawPacket { buffer: result? }),
+        let next = match self.read_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.framed_read.next())
+                .await
+                .map_err(|_| anyhow!("Timed out after {:?} without a packet", timeout))?,
+            None => self.framed_read.next().await,
+        };
+        match next {
+            Some(Ok(packet)) => {
+                // the read half always receives the opposite of what this side sends
+                METRICS.packet(self.direction.opposite());
+                if let Some(capture) = &self.read_capture {
+                    capture.record(self.direction.opposite(), self.state, self.protocol, &packet);
+                }
+                Ok(packet)
+            }
+            Some(Err(err)) => Err(err),
             None => Err(anyhow!("Connection aborted")),
         }
     }
 
     pub async fn send_raw_packet(&mut self, packet: RawPacket) -> Result<()> {
+        self.captured(&packet);
         self.framed_write.send(packet).await
     }
 
     pub async fn queue_raw_packet(&mut self, packet: RawPacket) -> Result<()> {
+        self.captured(&packet);
         self.framed_write.feed(packet).await
     }
 
     pub async fn auto_send_raw_packet(&mut self, packet: RawPacket) -> Result<()> {
+        self.captured(&packet);
         if self.framed_read.read_buffer().is_empty() {
             self.framed_write.send(packet).await
         } else {
@@ -146,15 +216,14 @@ impl Connection {
         self.auto_send_raw_packet(raw_packet).await
     }
 
-    fn serialize_packet<T: Packet + 'static>(&self, packet: T, id: u8) -> Result<RawPacket> {
-        let mut raw_packet = RawPacket::new();
-        raw_packet.set_id(id);
-
-        let mut data = raw_packet.data();
-        packet.put_buf(&mut data, self.protocol);
-        raw_packet.buffer.unsplit(data);
+    fn captured(&self, packet: &RawPacket) {
+        if let Some((capture, direction)) = &self.write_capture {
+            capture.record(*direction, self.state, self.protocol, packet);
+        }
+    }
 
-        Ok(raw_packet)
+    fn serialize_packet<T: Packet + 'static>(&self, packet: T, id: u8) -> Result<RawPacket> {
+        Ok(RawPacket::encode(packet, id, self.protocol))
     }
 
     pub async fn shutdown(&mut self) -> Result<()> {
@@ -166,8 +235,23 @@ impl Connection {
         self.shutdown().await
     }
 
+    /// Forwards the compressed packets read once compression is on without recompressing them,
+    /// see [`MinecraftDecoder::enable_compression`].
+    pub fn set_compression_passthrough(&mut self, passthrough: bool) {
+        self.compression_passthrough = passthrough;
+    }
+
+    pub fn set_compression_level(&mut self, level: CompressionLvl) {
+        self.framed_write.encoder_mut().set_compression_level(level);
+    }
+
     pub fn enable_compression(&mut self, threshold: u32) {
-        self.framed_read.decoder_mut().enable_compression();
+        // only backend packets are forwarded compressed, client ones always get fully checked
+        let passthrough =
+            self.compression_passthrough && matches!(self.direction, Direction::Serverbound);
+        self.framed_read
+            .decoder_mut()
+            .enable_compression(threshold, passthrough);
         self.framed_write
             .encoder_mut()
             .enable_compression(threshold);
@@ -182,22 +266,32 @@ impl Connection {
             Connection {
                 protocol: self.protocol,
                 direction: self.direction,
+                state: self.state,
 
                 receive_registry: self.receive_registry,
                 send_registry: connection.send_registry,
 
                 framed_read: self.framed_read,
                 framed_write: connection.framed_write,
+                read_timeout: self.read_timeout,
+                read_capture: self.read_capture,
+                write_capture: connection.write_capture,
+                compression_passthrough: self.compression_passthrough,
             },
             Connection {
                 protocol: connection.protocol,
                 direction: connection.direction,
+                state: connection.state,
 
                 receive_registry: connection.receive_registry,
                 send_registry: self.send_registry,
 
                 framed_read: connection.framed_read,
                 framed_write: self.framed_write,
+                read_timeout: connection.read_timeout,
+                read_capture: connection.read_capture,
+                write_capture: self.write_capture,
+                compression_passthrough: connection.compression_passthrough,
             },
         )
     }
@@ -1,15 +1,24 @@
 use std::cell::RefCell;
+use std::time::Instant;
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use bytes::BytesMut;
+use flate2::{Decompress, FlushDecompress};
 use libdeflater::Decompressor;
 use tokio_util::codec::Decoder;
-use crate::protocol::buffer::BufExt;
+use crate::metrics::METRICS;
+use crate::protocol::{buffer::BufExt, packet::RawPacket, Direction};
 
-use super::util::read_varint;
+use super::util::{read_varint, varint_length_usize, MAX_DATA_LENGTH};
+
+/// Most a partial frame grows the read buffer by at once.
+const RESERVE_CHUNK: usize = 8 * 1024;
+/// Deflate can't expand data by more than about 1032 times.
+const MAX_DEFLATE_RATIO: usize = 1032;
 
 thread_local!(
-    static DECOMPRESSOR: RefCell<Decompressor> = RefCell::new(Decompressor::new())
+    static DECOMPRESSOR: RefCell<Decompressor> = RefCell::new(Decompressor::new());
+    static ID_INFLATER: RefCell<Decompress> = RefCell::new(Decompress::new(true))
 );
 
 pub enum DecodeState {
@@ -19,24 +28,46 @@ pub enum DecodeState {
 
 pub struct MinecraftDecoder {
     state: DecodeState,
-    decompression: Option<BytesMut>,
+    threshold: Option<usize>,
+    passthrough: bool,
+    /// Direction of the packets read, for the metrics.
+    direction: Option<Direction>,
+}
+
+impl Default for MinecraftDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MinecraftDecoder {
     pub fn new() -> Self {
         Self {
             state: DecodeState::Length(0, 0),
-            decompression: None,
+            threshold: None,
+            passthrough: false,
+            direction: None,
+        }
+    }
+
+    /// Counts the bytes read in the [`crate::metrics`] as traveling in `direction`.
+    pub fn counted(direction: Direction) -> Self {
+        Self {
+            direction: Some(direction),
+            ..Self::new()
         }
     }
 
-    pub fn enable_compression(&mut self) {
-        self.decompression = Some(BytesMut::with_capacity(1024))
+    /// With `passthrough` compressed packets are left compressed and only their id is inflated,
+    /// the full decompression happens when someone actually needs the data.
+    pub fn enable_compression(&mut self, threshold: u32, passthrough: bool) {
+        self.threshold = Some(threshold as usize);
+        self.passthrough = passthrough;
     }
 }
 
 impl Decoder for MinecraftDecoder {
-    type Item = BytesMut;
+    type Item = RawPacket;
     type Error = anyhow::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
@@ -52,36 +83,80 @@ impl Decoder for MinecraftDecoder {
             DecodeState::Data(length) => length,
         } as usize;
 
-        src.reserve(length.saturating_sub(src.len()));
-
         if src.len() < length {
+            // grows with what arrives instead of trusting the declared length up front
+            src.reserve((length - src.len()).min(RESERVE_CHUNK));
             return Ok(None);
         }
 
         self.state = DecodeState::Length(0, 0);
         let mut data = src.split_to(length);
+        if let Some(direction) = self.direction {
+            METRICS.bytes(direction, length + varint_length_usize(length as u32));
+        }
 
-        if let Some(buf) = &mut self.decompression {
+        if let Some(threshold) = self.threshold {
             let data_length = data.get_varint()?;
 
             if data_length == 0 {
-                return Ok(Some(data));
+                ensure!(!data.is_empty(), "Empty packet");
+                return Ok(Some(RawPacket::from_buffer(data)));
             }
 
             let data_length = data_length as usize;
-            buf.reserve(data_length);
-            unsafe {
-                buf.set_len(data_length);
+            ensure!(
+                (threshold..=MAX_DATA_LENGTH).contains(&data_length),
+                "Invalid uncompressed packet length: {} (threshold {})",
+                data_length,
+                threshold
+            );
+            ensure!(
+                data_length <= data.len() * MAX_DEFLATE_RATIO,
+                "Uncompressed packet length {} is impossible for {} compressed bytes",
+                data_length,
+                data.len()
+            );
+
+            if self.passthrough {
+                let id = inflate_id(&data)?;
+                return Ok(Some(RawPacket::compressed(data, id, data_length)));
             }
-            let mut buf = buf.split_to(data_length);
 
-            let _result = DECOMPRESSOR.with_borrow_mut(|d| {
-                d.zlib_decompress(&data, &mut buf)
-            })?;
-            
-            return Ok(Some(buf));
+            return Ok(Some(RawPacket::from_buffer(decompress(&data, data_length)?)));
         }
 
-        Ok(Some(data))
+        ensure!(!data.is_empty(), "Empty packet");
+        Ok(Some(RawPacket::from_buffer(data)))
     }
 }
+
+pub fn decompress(data: &[u8], data_length: usize) -> Result<BytesMut> {
+    let mut buf = BytesMut::zeroed(data_length);
+    let started = Instant::now();
+    let length = DECOMPRESSOR.with_borrow_mut(|d| d.zlib_decompress(data, &mut buf))?;
+    METRICS.decompressed(started.elapsed(), data.len(), length);
+
+    ensure!(
+        length == data_length,
+        "Decompressed packet length mismatch: declared {}, got {}",
+        data_length,
+        length
+    );
+    Ok(buf)
+}
+
+/// Inflates just the first byte of the zlib stream, which is the packet id.
+fn inflate_id(data: &[u8]) -> Result<u8> {
+    let mut id = [0; 1];
+
+    ID_INFLATER.with_borrow_mut(|inflater| {
+        inflater.reset(true);
+        inflater.decompress(data, &mut id, FlushDecompress::None)
+    })?;
+
+    ensure!(
+        ID_INFLATER.with_borrow(|inflater| inflater.total_out()) == 1,
+        "Compressed packet has no id"
+    );
+    Ok(id[0])
+}
@@ -1,28 +1,51 @@
 use std::cell::RefCell;
+use std::time::Instant;
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use bytes::{BufMut, BytesMut};
-use libdeflater::Compressor;
-use openssl::symm::Crypter;
+use libdeflater::{CompressionLvl, Compressor};
+use openssl::symm::{Cipher, Crypter, Mode};
 use tokio_util::codec::Encoder;
 
-use crate::{config::config, protocol::packet::RawPacket};
+use crate::{metrics::METRICS, protocol::{buffer::BufMutExt, packet::RawPacket}};
 
-use super::util::{varint_length_usize, write_varint};
+use super::util::{varint_length_usize, write_varint, MAX_PACKET_SIZE};
 
 thread_local!(
-    static COMPRESSOR: RefCell<Compressor> = RefCell::new(Compressor::new(config().compression_level))
+    /// A compressor for each level used on this thread.
+    static COMPRESSORS: RefCell<Vec<(CompressionLvl, Compressor)>> = const { RefCell::new(Vec::new()) }
 );
 
+fn with_compressor<R>(level: CompressionLvl, f: impl FnOnce(&mut Compressor) -> R) -> R {
+    COMPRESSORS.with_borrow_mut(|compressors| {
+        let index = match compressors.iter().position(|(used, _)| *used == level) {
+            Some(index) => index,
+            None => {
+                compressors.push((level, Compressor::new(level)));
+                compressors.len() - 1
+            }
+        };
+        f(&mut compressors[index].1)
+    })
+}
+
 pub struct MinecraftEncoder {
     threshold: Option<usize>,
+    level: CompressionLvl,
     cipher: Option<Crypter>,
 }
 
+impl Default for MinecraftEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MinecraftEncoder {
     pub fn new() -> Self {
         Self {
             threshold: None,
+            level: CompressionLvl::default(),
             cipher: None,
         }
     }
@@ -31,10 +54,14 @@ impl MinecraftEncoder {
         self.threshold = Some(threshold as usize)
     }
 
+    pub fn set_compression_level(&mut self, level: CompressionLvl) {
+        self.level = level;
+    }
+
     pub fn enable_encryption(&mut self, key: [u8; 16]) -> Result<()> {
         self.cipher = Some(Crypter::new(
-            openssl::symm::Cipher::aes_128_cfb8(),
-            openssl::symm::Mode::Encrypt,
+            Cipher::aes_128_cfb8(),
+            Mode::Encrypt,
             &key,
             Some(&key),
         )?);
@@ -45,33 +72,56 @@ impl MinecraftEncoder {
 impl Encoder<RawPacket> for MinecraftEncoder {
     type Error = anyhow::Error;
 
-    fn encode(&mut self, item: RawPacket, dst: &mut BytesMut) -> Result<()> {
-        let packet = item.buffer;
-        let uncompressed_length = packet.len() as u32;
-
-        if let Some(threshold) = self.threshold {
-            if packet.len() >= threshold {
-                let buffer = dst.split();
-                dst.reserve(packet.len() + 6);
+    fn encode(&mut self, mut item: RawPacket, dst: &mut BytesMut) -> Result<()> {
+        let start = dst.len();
 
-                let mut data = dst.split_off(3);
+        match (item.data_length(), self.threshold) {
+            // the receiver accepts it as is, no need to recompress
+            (Some(data_length), Some(threshold)) if data_length >= threshold => {
+                write_compressed(&item.buffer, data_length, dst);
+            }
+            _ => {
+                item.decompress()?;
+                self.write_packet(item.buffer, dst)?;
+            }
+        }
 
-                write_varint(&mut data, uncompressed_length);
-                let header = data.len();
-                unsafe {
-                    data.set_len(data.capacity());
-                }
+        // only the new frame, dst may still hold earlier (already encrypted) ones
+        if let Some(cipher) = &mut self.cipher {
+            let frame = dst.split_off(start);
+            dst.resize(start + frame.len() + Cipher::aes_128_cfb8().block_size(), 0);
+            let length = cipher.update(&frame, &mut dst[start..])?;
+            dst.truncate(start + length);
+        }
 
-                let compressed_length = COMPRESSOR
-                    .with_borrow_mut(|c| c.zlib_compress(&packet, &mut data[header..]))?;
-                unsafe {
-                    data.set_len(header + compressed_length);
-                }
+        Ok(())
+    }
+}
 
-                write_21bit_varint(data.len() as u32, dst);
+impl MinecraftEncoder {
+    fn write_packet(&mut self, packet: BytesMut, dst: &mut BytesMut) -> Result<()> {
+        let uncompressed_length = packet.len() as u32;
 
-                dst.unsplit(data);
-                dst.unsplit(buffer);
+        if let Some(threshold) = self.threshold {
+            if packet.len() >= threshold {
+                let start = dst.len();
+                let bound = with_compressor(self.level, |c| c.zlib_compress_bound(packet.len()));
+
+                // room for the 3 byte frame length, filled in once the compressed size is known
+                dst.resize(start + 3, 0);
+                dst.put_uvarint(uncompressed_length);
+                let header = dst.len();
+                dst.resize(header + bound, 0);
+
+                let started = Instant::now();
+                let compressed_length =
+                    with_compressor(self.level, |c| c.zlib_compress(&packet, &mut dst[header..]))?;
+                METRICS.compressed(started.elapsed(), packet.len(), compressed_length);
+                dst.truncate(header + compressed_length);
+
+                let length = dst.len() - start - 3;
+                ensure!(length <= MAX_PACKET_SIZE, "Packet is too big: {} bytes", length);
+                dst[start..start + 3].copy_from_slice(&varint_21bit(length as u32));
             } else {
                 dst.reserve(packet.len() + varint_length_usize(uncompressed_length) + 1);
 
@@ -85,19 +135,24 @@ impl Encoder<RawPacket> for MinecraftEncoder {
             dst.extend_from_slice(&packet);
         }
 
-        if let Some(cipher) = &mut self.cipher {
-            let len = dst.len();
-            let buffer = dst.split();
-            dst.reserve(len);
-            cipher.update(&buffer, dst)?;
-        }
-
         Ok(())
     }
 }
 
-fn write_21bit_varint(value: u32, buf: &mut BytesMut) {
-    let w = (value & 0x7F | 0x80) << 16 | ((value >> 7) & 0x7F | 0x80) << 8 | (value >> 14);
-    buf.put_u16((w >> 8) as u16);
-    buf.put_u8(w as u8);
+fn write_compressed(compressed: &[u8], data_length: usize, dst: &mut BytesMut) {
+    let data_length = data_length as u32;
+    let length = varint_length_usize(data_length) + compressed.len();
+
+    dst.reserve(length + 3);
+    write_varint(dst, length as u32);
+    dst.put_uvarint(data_length);
+    dst.extend_from_slice(compressed);
+}
+
+fn varint_21bit(value: u32) -> [u8; 3] {
+    [
+        (value & 0x7F | 0x80) as u8,
+        ((value >> 7) & 0x7F | 0x80) as u8,
+        (value >> 14) as u8,
+    ]
 }
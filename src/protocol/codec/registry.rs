@@ -12,7 +12,7 @@ use strum::IntoEnumIterator;
 use super::util::produce;
 use crate::protocol::{
     packet::{
-        handshake::Handshake, login::{Disconnect, EncryptionRequest, EncryptionResponse, LoginAcknowledged, LoginStart, LoginSuccess, SetCompression}, play::{BossBar, JoinGame, PluginMessage, Respawn}, status::{Ping, StatusRequest, StatusResponse}, Packet, PacketType
+        handshake::Handshake, login::{Disconnect, EncryptionRequest, EncryptionResponse, LoginAcknowledged, LoginPluginRequest, LoginPluginResponse, LoginStart, LoginSuccess, SetCompression}, play::{BossBar, ChatCommand, ChatMessage, JoinGame, KeepAlive, PlayerPosition, PluginMessage, Respawn, SystemChat}, status::{Ping, StatusRequest, StatusResponse}, Packet, PacketType
     },
     Direction, ProtocolVersion, State,
 };
@@ -64,6 +64,8 @@ pub static LOGIN_REG: LazyLock<StateRegistry> = LazyLock::new(|| {
     reg.insert::<EncryptionResponse>(produce!(EncryptionResponse), Id::Serverbound(Mapping::Single(0x01)));
     reg.insert::<SetCompression>(produce!(SetCompression), Id::Clientbound(Mapping::Single(0x03)));
     reg.insert::<LoginSuccess>(produce!(LoginSuccess), Id::Clientbound(Mapping::Single(0x02)));
+    reg.insert::<LoginPluginRequest>(produce!(LoginPluginRequest), Id::Clientbound(Mapping::Single(0x04)));
+    reg.insert::<LoginPluginResponse>(None, Id::Serverbound(Mapping::Single(0x02)));
     reg.insert::<LoginAcknowledged>(None, Id::Serverbound(Mapping::Single(0x03)));
     reg
 });
@@ -73,21 +75,80 @@ pub static PLAY_REG: LazyLock<StateRegistry> = LazyLock::new(|| {
     reg.insert::<Disconnect>(
         produce!(Disconnect),
         Id::Clientbound(Mapping::List(vec![
-            (0x1a, ProtocolVersion::V1_19_4),
+            (0x19, ProtocolVersion::V1_19_2),
             (0x17, ProtocolVersion::V1_19_3),
+            (0x1a, ProtocolVersion::V1_19_4),
+            (0x1b, ProtocolVersion::V1_20_2),
         ])),
     );
     reg.insert::<PluginMessage>(
         produce!(PluginMessage),
-        Id::Clientbound(Mapping::Single(0x17)),
+        Id::Both(
+            Mapping::List(vec![
+                (0x0d, ProtocolVersion::V1_19_2),
+                (0x0c, ProtocolVersion::V1_19_3),
+                (0x0d, ProtocolVersion::V1_19_4),
+                (0x0f, ProtocolVersion::V1_20_2),
+                (0x10, ProtocolVersion::V1_20_3),
+            ]),
+            Mapping::List(vec![
+                (0x16, ProtocolVersion::V1_19_2),
+                (0x15, ProtocolVersion::V1_19_3),
+                (0x17, ProtocolVersion::V1_19_4),
+                (0x18, ProtocolVersion::V1_20_2),
+            ]),
+        ),
     );
     reg.insert::<JoinGame>(None, Id::Clientbound(Mapping::Single(0x28)));
     reg.insert::<Respawn>(None, Id::Clientbound(Mapping::Single(0x41)));
+    reg.insert::<PlayerPosition>(
+        None,
+        Id::Clientbound(Mapping::List(vec![
+            (0x39, ProtocolVersion::V1_19_2),
+            (0x38, ProtocolVersion::V1_19_3),
+            (0x3c, ProtocolVersion::V1_19_4),
+            (0x3e, ProtocolVersion::V1_20_2),
+        ])),
+    );
     reg.insert::<BossBar>(produce!(BossBar), Id::Clientbound(Mapping::Single(0x0b)));
-    // reg.insert::<ChatCommand>(
-    //     produce!(ChatCommand),
-    //     Id::Serverbound(Mapping::Single(0x04)),
-    // );
+    reg.insert::<SystemChat>(
+        None,
+        Id::Clientbound(Mapping::List(vec![
+            (0x62, ProtocolVersion::V1_19_2),
+            (0x60, ProtocolVersion::V1_19_3),
+            (0x64, ProtocolVersion::V1_19_4),
+            (0x67, ProtocolVersion::V1_20_2),
+            (0x69, ProtocolVersion::V1_20_3),
+        ])),
+    );
+    // not decoded, the proxy only looks at keepalives of the ids below
+    reg.insert::<KeepAlive>(
+        None,
+        Id::Both(
+            Mapping::List(vec![
+                (0x12, ProtocolVersion::V1_19_2),
+                (0x11, ProtocolVersion::V1_19_3),
+                (0x12, ProtocolVersion::V1_19_4),
+                (0x14, ProtocolVersion::V1_20_2),
+                (0x15, ProtocolVersion::V1_20_3),
+            ]),
+            Mapping::List(vec![
+                (0x20, ProtocolVersion::V1_19_2),
+                (0x1f, ProtocolVersion::V1_19_3),
+                (0x23, ProtocolVersion::V1_19_4),
+                (0x24, ProtocolVersion::V1_20_2),
+            ]),
+        ),
+    );
+    // signed chat, the format differs before 1.19.3 but the ids stay
+    reg.insert::<ChatCommand>(
+        produce!(ChatCommand),
+        Id::Serverbound(Mapping::List(vec![(0x04, ProtocolVersion::V1_19_2)])),
+    );
+    reg.insert::<ChatMessage>(
+        produce!(ChatMessage),
+        Id::Serverbound(Mapping::List(vec![(0x05, ProtocolVersion::V1_19_2)])),
+    );
     reg
 });
 
@@ -95,6 +156,12 @@ pub struct StateRegistry {
     protocols: Vec<PacketRegistry>,
 }
 
+impl Default for StateRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl StateRegistry {
     pub fn new() -> Self {
         Self {
@@ -150,7 +217,7 @@ impl StateRegistry {
                 }
             }
             Mapping::List(mut list) => {
-                list.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+                list.sort_by_key(|(_, version)| *version);
 
                 for (index, (id, first_version)) in
                     list.iter().map(|(i, v)| (i, *v as usize)).enumerate()
@@ -193,6 +260,12 @@ pub struct PacketRegistry {
     pub clientbound: ProtocolRegistry,
 }
 
+impl Default for PacketRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PacketRegistry {
     pub fn new() -> Self {
         Self {
@@ -216,6 +289,13 @@ impl PacketRegistry {
 pub struct ProtocolRegistry {
     packet_to_id: HashMap<TypeId, u8>,
     id_to_packeta: [Option<PacketProducer>; 128],
+    id_to_name: [Option<&'static str>; 128],
+}
+
+impl Default for ProtocolRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ProtocolRegistry {
@@ -223,6 +303,7 @@ impl ProtocolRegistry {
         Self {
             packet_to_id: HashMap::new(),
             id_to_packeta: [None; 128],
+            id_to_name: [None; 128],
         }
     }
 
@@ -233,6 +314,8 @@ impl ProtocolRegistry {
 
     fn insert_packet_to_id<T: Packet + 'static>(&mut self, id: u8) {
         self.packet_to_id.insert(TypeId::of::<T>(), id);
+        let name = type_name::<T>().rsplit("::").next();
+        self.id_to_name[id as usize] = name;
     }
 
     fn insert_id_to_packet(&mut self, producer: PacketProducer, id: u8) {
@@ -246,6 +329,11 @@ impl ProtocolRegistry {
         }
     }
 
+    /// Name of the packet type registered for `id`, like `JoinGame`.
+    pub fn get_name(&self, id: u8) -> Option<&'static str> {
+        self.id_to_name.get(id as usize).copied().flatten()
+    }
+
     pub fn get_id<T: Packet + 'static>(&self) -> Result<&u8> {
         self.packet_to_id.get(&TypeId::of::<T>()).ok_or(
             anyhow!("Packet does not exist in this state or version").context(type_name::<T>()),
@@ -5,11 +5,13 @@ use super::decoder::DecodeState;
 use anyhow::{anyhow, Result};
 
 pub const MAX_PACKET_SIZE: usize = 2097151;
+/// Largest uncompressed packet accepted, same as the vanilla limit.
+pub const MAX_DATA_LENGTH: usize = 8388608;
 pub const MAX_HEADER_LENGTH: i32 = 3;
 
 #[inline(always)]
 pub fn read_varint(mut value: i32, readed_bytes: i32, src: &mut BytesMut) -> Result<DecodeState> {
-    let max_read = i32::min(MAX_HEADER_LENGTH, src.len() as i32);
+    let max_read = i32::min(MAX_HEADER_LENGTH, readed_bytes + src.len() as i32);
 
     for i in readed_bytes..max_read {
         let byte = src.get_u8();
@@ -20,7 +22,7 @@ pub fn read_varint(mut value: i32, readed_bytes: i32, src: &mut BytesMut) -> Res
         }
     }
 
-    if max_read <= MAX_HEADER_LENGTH {
+    if max_read < MAX_HEADER_LENGTH {
         return Ok(DecodeState::Length(value, max_read));
     }
     Err(anyhow!("Varint too big"))
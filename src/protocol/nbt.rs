@@ -1,18 +1,27 @@
-use anyhow::{ensure, Result};
+use std::fmt::{self, Display, Formatter, Write};
+
+use anyhow::{bail, ensure, Result};
 use bytes::{Buf, BufMut};
 
 type CompoundType = Vec<(String, Tag)>;
 
-#[derive(Debug)]
+/// Same nesting limit as vanilla, deeper data is rejected instead of overflowing the stack.
+const MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Compound(String, CompoundType);
 
 impl Compound {
+    pub fn new(name: String, tags: Vec<(String, Tag)>) -> Self {
+        Self(name, tags)
+    }
+
     pub fn read(buf: &mut impl Buf) -> Result<Self> {
-        let id = buf.get_u8();
+        let id = buf.try_get_u8()?;
         ensure!(id == 0x0a, "nbt this isnt a compund. id: {}", id);
         let name = read_string(buf)?;
 
-        Ok(Compound(name, read_compund(buf)?))
+        Ok(Compound(name, read_compund(buf, 0)?))
     }
 
     pub fn write(self, buf: &mut impl BufMut) {
@@ -22,7 +31,7 @@ impl Compound {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 #[repr(u8)]
 pub enum Tag {
     Byte(i8) = 1,
@@ -41,15 +50,21 @@ pub enum Tag {
 
 impl Tag {
     pub fn read(id: u8, buf: &mut impl Buf) -> Result<Self> {
+        Self::read_nested(id, buf, 0)
+    }
+
+    fn read_nested(id: u8, buf: &mut impl Buf, depth: usize) -> Result<Self> {
+        ensure!(depth <= MAX_DEPTH, "nbt is nested too deep");
+
         Ok(match id {
-            1 => Tag::Byte(buf.get_i8()),
-            2 => Tag::Short(buf.get_i16()),
-            3 => Tag::Int(buf.get_i32()),
-            4 => Tag::Long(buf.get_i64()),
-            5 => Tag::Float(buf.get_f32()),
-            6 => Tag::Double(buf.get_f64()),
+            1 => Tag::Byte(buf.try_get_i8()?),
+            2 => Tag::Short(buf.try_get_i16()?),
+            3 => Tag::Int(buf.try_get_i32()?),
+            4 => Tag::Long(buf.try_get_i64()?),
+            5 => Tag::Float(buf.try_get_f32()?),
+            6 => Tag::Double(buf.try_get_f64()?),
             7 => {
-                let length = buf.get_i32() as usize;
+                let length = read_length(buf, 1)?;
                 let mut vec = vec![0; length];
                 buf.copy_to_slice(&mut vec);
                 Tag::ByteArray(vec)
@@ -58,29 +73,35 @@ impl Tag {
                 buf.copy_to_slice(&mut vec);
 
                 unsafe {
-                    let byte_array = Vec::from_raw_parts(vec.as_mut_ptr() as *mut i8, vec.len(), vec.capacity()); 
+                    let byte_array = Vec::from_raw_parts(vec.as_mut_ptr() as *mut i8, vec.len(), vec.capacity());
                     Tag::ByteArray(byte_array)
                 }
                 */
             }
             8 => Tag::String(read_string(buf)?),
             9 => {
-                let id = buf.get_u8();
-                let length = buf.get_i32() as usize;
+                let id = buf.try_get_u8()?;
+                let length = read_length(buf, 1)?;
                 let mut vec = Vec::with_capacity(length);
 
                 for _ in 0..length {
-                    let tag = Self::read(id, buf)?;
+                    let tag = Self::read_nested(id, buf, depth + 1)?;
                     ensure!(id == tag.id(), "nbt list has different tags");
                     vec.push(tag);
                 }
 
                 Tag::List(vec)
             }
-            10 => Tag::Compound(read_compund(buf)?),
-            11 => unimplemented!("nbt int list"),
-            12 => unimplemented!("nbt long list"),
-            _ => panic!("nbt invalid id")
+            10 => Tag::Compound(read_compund(buf, depth + 1)?),
+            11 => {
+                let length = read_length(buf, 4)?;
+                Tag::IntArray((0..length).map(|_| buf.get_i32()).collect())
+            }
+            12 => {
+                let length = read_length(buf, 8)?;
+                Tag::LongArray((0..length).map(|_| buf.get_i64()).collect())
+            }
+            id => bail!("nbt invalid id: {}", id),
         })
     }
 
@@ -112,8 +133,14 @@ impl Tag {
                 }
             }
             Tag::Compound(v) => write_compund(v, buf),
-            Tag::IntArray(_) => unimplemented!("nbt int list"),
-            Tag::LongArray(_) => unimplemented!("nbt long list"),
+            Tag::IntArray(v) => {
+                buf.put_i32(v.len() as i32);
+                v.iter().for_each(|i| buf.put_i32(*i));
+            }
+            Tag::LongArray(v) => {
+                buf.put_i32(v.len() as i32);
+                v.iter().for_each(|l| buf.put_i64(*l));
+            }
         }
     }
 
@@ -135,12 +162,99 @@ impl Tag {
     }
 }
 
-fn read_compund(buf: &mut impl Buf) -> Result<CompoundType> {
+/// Formats as SNBT, the way the game shows NBT in commands. The name of the root compound is left
+/// out, SNBT has no place for it.
+impl Display for Compound {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write_snbt_compound(&self.1, f)
+    }
+}
+
+/// Formats as SNBT, like `{name:"Steve",health:20.0f,pos:[I;1,2,3]}`.
+impl Display for Tag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Tag::Byte(v) => write!(f, "{}b", v),
+            Tag::Short(v) => write!(f, "{}s", v),
+            Tag::Int(v) => write!(f, "{}", v),
+            Tag::Long(v) => write!(f, "{}L", v),
+            Tag::Float(v) => write!(f, "{:?}f", v),
+            Tag::Double(v) => write!(f, "{:?}d", v),
+            Tag::ByteArray(v) => {
+                write_snbt_array(f, "B;", v.iter().map(|b| format!("{}b", *b as i8)))
+            }
+            Tag::String(v) => write_snbt_string(v, f),
+            Tag::List(v) => write_snbt_array(f, "", v.iter()),
+            Tag::Compound(v) => write_snbt_compound(v, f),
+            Tag::IntArray(v) => write_snbt_array(f, "I;", v.iter()),
+            Tag::LongArray(v) => write_snbt_array(f, "L;", v.iter().map(|l| format!("{}L", l))),
+        }
+    }
+}
+
+fn write_snbt_compound(map: &CompoundType, f: &mut Formatter<'_>) -> fmt::Result {
+    f.write_char('{')?;
+    for (i, (name, tag)) in map.iter().enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+        let bare = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+'));
+        if bare {
+            f.write_str(name)?;
+        } else {
+            write_snbt_string(name, f)?;
+        }
+        write!(f, ":{}", tag)?;
+    }
+    f.write_char('}')
+}
+
+fn write_snbt_array<T: Display>(
+    f: &mut Formatter<'_>,
+    prefix: &str,
+    values: impl Iterator<Item = T>,
+) -> fmt::Result {
+    write!(f, "[{}", prefix)?;
+    for (i, value) in values.enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+        write!(f, "{}", value)?;
+    }
+    f.write_char(']')
+}
+
+fn write_snbt_string(str: &str, f: &mut Formatter<'_>) -> fmt::Result {
+    f.write_char('"')?;
+    for c in str.chars() {
+        match c {
+            '"' | '\\' => write!(f, "\\{}", c)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Reads an array length and checks that the buffer actually holds that many elements.
+fn read_length(buf: &mut impl Buf, element_size: usize) -> Result<usize> {
+    let length = buf.try_get_i32()?;
+    ensure!(length >= 0, "nbt negative length");
+    ensure!(
+        length as usize <= buf.remaining() / element_size,
+        "nbt length is bigger than the data"
+    );
+    Ok(length as usize)
+}
+
+fn read_compund(buf: &mut impl Buf, depth: usize) -> Result<CompoundType> {
     let mut vec = Vec::new();
 
-    while let id @ 1.. = buf.get_u8()  {
+    while let id @ 1.. = buf.try_get_u8()? {
         let name = read_string(buf)?;
-        let tag = Tag::read(id, buf)?;
+        let tag = Tag::read_nested(id, buf, depth)?;
         vec.push((name, tag));
     }
     Ok(vec)
@@ -156,7 +270,8 @@ fn write_compund(map: &CompoundType, buf: &mut impl BufMut) {
 }
 
 fn read_string(buf: &mut impl Buf) -> Result<String> {
-    let name_length = buf.get_u16() as usize;
+    let name_length = buf.try_get_u16()? as usize;
+    ensure!(name_length <= buf.remaining(), "nbt string is longer than the data");
     let mut vec = vec![0; name_length];
     buf.copy_to_slice(&mut vec);
 
@@ -166,4 +281,4 @@ fn read_string(buf: &mut impl Buf) -> Result<String> {
 fn write_string(str: &String, buf: &mut impl BufMut) {
     buf.put_u16(str.len() as u16);
     buf.put_slice(str.as_bytes());
-}
\ No newline at end of file
+}
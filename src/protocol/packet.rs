@@ -1,12 +1,14 @@
-use anyhow::Result;
-use bytes::{Buf, BytesMut};
+use std::any::type_name;
+
+use anyhow::{ensure, Context, Result};
+use bytes::{Buf, BufMut, BytesMut};
 
 use self::{
     login::{Disconnect, EncryptionRequest, EncryptionResponse, LoginPluginRequest, LoginStart, LoginSuccess, SetCompression},
-    play::{BossBar, ChatCommand, PluginMessage},
+    play::{BossBar, ChatCommand, ChatMessage, PluginMessage},
 };
 
-use super::{Direction, ProtocolVersion, State};
+use super::{codec::decompress, Direction, ProtocolVersion, State};
 
 pub mod handshake;
 pub mod login;
@@ -31,26 +33,94 @@ pub trait Packets {
 }
 
 pub struct RawPacket {
+    /// Packet id and data, or the zlib stream of them when the packet is still compressed.
     pub buffer: BytesMut,
+    compressed: Option<Compressed>,
+}
+
+#[derive(Clone, Copy)]
+struct Compressed {
+    id: u8,
+    data_length: usize,
+}
+
+impl Default for RawPacket {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RawPacket {
     pub fn new() -> Self {
+        Self::from_buffer(BytesMut::zeroed(1))
+    }
+
+    pub fn from_buffer(buffer: BytesMut) -> Self {
+        Self {
+            buffer,
+            compressed: None,
+        }
+    }
+
+    /// Packet kept in its compressed form, so it can be forwarded without recompressing.
+    pub fn compressed(buffer: BytesMut, id: u8, data_length: usize) -> Self {
         Self {
-            buffer: BytesMut::zeroed(1),
+            buffer,
+            compressed: Some(Compressed { id, data_length }),
         }
     }
 
     pub fn id(&self) -> u8 {
-        self.buffer[0]
+        match self.compressed {
+            Some(Compressed { id, .. }) => id,
+            None => self.buffer[0],
+        }
+    }
+
+    /// Uncompressed length of a packet that is still compressed.
+    pub fn data_length(&self) -> Option<usize> {
+        self.compressed.map(|c| c.data_length)
+    }
+
+    pub fn decompress(&mut self) -> Result<()> {
+        if let Some(Compressed { data_length, .. }) = self.compressed.take() {
+            self.buffer = decompress(&self.buffer, data_length)?;
+        }
+        Ok(())
     }
 
-    pub fn set_id(&mut self, id: u8) {
+    /// Changes the id, a compressed packet is decompressed first.
+    pub fn set_id(&mut self, id: u8) -> Result<()> {
+        self.decompress()?;
         self.buffer[0] = id;
+        Ok(())
     }
 
-    pub fn data(&mut self) -> BytesMut {
-        self.buffer.split_off(1)
+    /// Splits off the data after the id, a compressed packet is decompressed first.
+    pub fn data(&mut self) -> Result<BytesMut> {
+        self.decompress()?;
+        Ok(self.buffer.split_off(1))
+    }
+
+    /// Serializes `packet` with the given id.
+    pub fn encode<T: Packet>(packet: T, id: u8, version: ProtocolVersion) -> Self {
+        let mut buffer = BytesMut::new();
+        buffer.put_u8(id);
+        packet.put_buf(&mut buffer, version);
+        Self::from_buffer(buffer)
+    }
+
+    /// Reads the packet as `T`, without checking its id.
+    pub fn decode<T: Packet>(mut self, version: ProtocolVersion) -> Result<T> {
+        let mut data = self.data()?;
+
+        let packet = T::from_bytes(&mut data, version).context(type_name::<T>())?;
+        ensure!(
+            data.is_empty(),
+            "Packet was not been fully read. Packet: {:}",
+            type_name::<T>()
+        );
+        Ok(packet)
     }
 }
 
@@ -69,4 +139,5 @@ pub enum PacketType {
     PluginMessage(PluginMessage),
     BossBar(BossBar),
     ChatCommand(ChatCommand),
+    ChatMessage(ChatMessage),
 }
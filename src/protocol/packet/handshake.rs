@@ -3,6 +3,7 @@ use super::Packet;
 use crate::protocol::{buffer::{BufExt, BufMutExt}, ProtocolVersion};
 use bytes::{Buf, BufMut, BytesMut};
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Handshake {
     pub protocol: i32,
     pub server_address: String,
@@ -15,8 +16,8 @@ impl Packet for Handshake {
         Ok(Self {
             protocol: buf.get_varint()?,
             server_address: buf.get_string(255)?,
-            port: buf.get_u16(),
-            state: NextState::try_from(buf.get_u8())?,
+            port: buf.try_get_u16()?,
+            state: NextState::try_from(buf.try_get_u8()?)?,
         })
     }
 
@@ -28,6 +29,7 @@ impl Packet for Handshake {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum NextState {
     Status = 1,
     Login
@@ -1,14 +1,15 @@
-use crate::online::Property;
+use crate::online::{generate_offline_uuid, Property};
 use crate::protocol::buffer::{BufExt, BufMutExt};
 use crate::protocol::util::{get_array, get_property, put_array, put_property};
 use crate::protocol::{Direction, ProtocolVersion, State};
-use crate::Component;
+use crate::component::Component;
 use anyhow::{anyhow, ensure, Result};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use uuid::Uuid;
 
 use super::{Packet, Packets, RawPacket};
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct LoginStart {
     pub username: String,
     pub uuid: Option<Uuid>,
@@ -18,9 +19,9 @@ impl Packet for LoginStart {
     fn from_bytes(buf: &mut impl Buf, version: ProtocolVersion) -> Result<Self> {
         let username = buf.get_string(16)?;
         let uuid = if version >= ProtocolVersion::V1_20_2 {
-            Some(buf.get_uuid())
+            Some(buf.get_uuid()?)
         } else if version >= ProtocolVersion::V1_19_2 {
-            buf.get_option(|b| Ok(b.get_uuid()))?
+            buf.get_option(|b| b.get_uuid())?
         } else {
             None
         };
@@ -28,12 +29,17 @@ impl Packet for LoginStart {
         Ok(Self { username, uuid })
     }
 
-    fn put_buf(self, buf: &mut BytesMut, _: ProtocolVersion) {
+    fn put_buf(self, buf: &mut BytesMut, version: ProtocolVersion) {
         buf.put_string(&self.username);
-        buf.put_option(&self.uuid, |b, u| b.put_uuid(*u));
+        if version >= ProtocolVersion::V1_20_2 {
+            buf.put_uuid(self.uuid.unwrap_or_else(|| generate_offline_uuid(&self.username)));
+        } else if version >= ProtocolVersion::V1_19_2 {
+            buf.put_option(&self.uuid, |b, u| b.put_uuid(*u));
+        }
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct LoginSuccess {
     pub uuid: Uuid,
     pub username: String,
@@ -43,7 +49,7 @@ pub struct LoginSuccess {
 impl Packet for LoginSuccess {
     fn from_bytes(buf: &mut impl Buf, _: ProtocolVersion) -> Result<Self> {
         Ok(Self {
-            uuid: buf.get_uuid(),
+            uuid: buf.get_uuid()?,
             username: buf.get_string(16)?,
             properties: get_array(buf, get_property)?,
         })
@@ -56,6 +62,7 @@ impl Packet for LoginSuccess {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct LoginAcknowledged;
 
 impl Packet for LoginAcknowledged {
@@ -66,6 +73,7 @@ impl Packet for LoginAcknowledged {
     fn put_buf(self, _: &mut BytesMut, _: ProtocolVersion) {}
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Disconnect {
     pub reason: Component,
 }
@@ -82,6 +90,7 @@ impl Packet for Disconnect {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct SetCompression {
     pub threshold: i32,
 }
@@ -98,6 +107,7 @@ impl Packet for SetCompression {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct EncryptionRequest {
     pub server_id: String,
     pub public_key: Vec<u8>,
@@ -120,6 +130,7 @@ impl Packet for EncryptionRequest {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct EncryptionResponse {
     pub shared_secret: Bytes,
     pub verify_token: Bytes,
@@ -139,6 +150,7 @@ impl Packet for EncryptionResponse {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct LoginPluginRequest {
     pub message_id: i32,
     pub channel: String,
@@ -165,6 +177,7 @@ impl Packet for LoginPluginRequest {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct LoginPluginResponse {
     pub message_id: i32,
     pub successful: bool,
@@ -220,13 +233,13 @@ impl Packets for LoginPackets {
 
         Ok(match packet.id() {
             0x01 => {
-                Self::EncryptionRequest(EncryptionRequest::from_bytes(&mut packet.data(), version)?)
+                Self::EncryptionRequest(EncryptionRequest::from_bytes(&mut packet.data()?, version)?)
             }
-            0x03 => Self::SetCompression(SetCompression::from_bytes(&mut packet.data(), version)?),
-            0x02 => Self::LoginSuccess(LoginSuccess::from_bytes(&mut packet.data(), version)?),
-            0x00 => Self::Disconnect(Disconnect::from_bytes(&mut packet.data(), version)?),
+            0x03 => Self::SetCompression(SetCompression::from_bytes(&mut packet.data()?, version)?),
+            0x02 => Self::LoginSuccess(LoginSuccess::from_bytes(&mut packet.data()?, version)?),
+            0x00 => Self::Disconnect(Disconnect::from_bytes(&mut packet.data()?, version)?),
             0x04 => Self::LoginPluginRequest(LoginPluginRequest::from_bytes(
-                &mut packet.data(),
+                &mut packet.data()?,
                 version,
             )?),
             _ => return Err(anyhow!("Unknown packet id in login packets")),
@@ -1,4 +1,4 @@
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, ensure, Result};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use uuid::Uuid;
 
@@ -14,6 +14,7 @@ use crate::{
 
 use super::{login::Disconnect, Packet, Packets, RawPacket};
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct PluginMessage {
     pub channel: String,
     pub data: Bytes,
@@ -33,6 +34,7 @@ impl Packet for PluginMessage {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct JoinGame {
     pub entity_id: i32,
     pub is_hardcore: bool,
@@ -59,15 +61,15 @@ pub struct JoinGame {
 impl Packet for JoinGame {
     fn from_bytes(buf: &mut impl Buf, _: ProtocolVersion) -> Result<Self> {
         Ok(Self {
-            entity_id: buf.get_i32(),
+            entity_id: buf.try_get_i32()?,
             is_hardcore: buf.get_bool()?,
-            gamemode: buf.get_u8(),
-            previous_gamemode: buf.get_u8(),
+            gamemode: buf.try_get_u8()?,
+            previous_gamemode: buf.try_get_u8()?,
             dimensions_names: get_array(buf, |b| b.get_identifier())?,
             registry: Compound::read(buf)?,
             dimension_type: buf.get_identifier()?,
             dimension_name: buf.get_identifier()?,
-            hashed_seed: buf.get_i64(),
+            hashed_seed: buf.try_get_i64()?,
             max_players: buf.get_varint()?,
             view_distance: buf.get_varint()?,
             simulation_distance: buf.get_varint()?,
@@ -103,7 +105,7 @@ impl Packet for JoinGame {
     }
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Death {
     pub dimension_name: String,
     pub position: i64,
@@ -117,7 +119,7 @@ impl Death {
     pub fn get1(buf: &mut impl Buf) -> Result<Self> {
         Ok(Death {
             dimension_name: buf.get_identifier()?,
-            position: buf.get_i64(),
+            position: buf.try_get_i64()?,
         })
     }
 }
@@ -132,6 +134,7 @@ fn put_death(buf: &mut BytesMut, death: Option<Death>) {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Respawn {
     pub dimension_type: String,
     pub dimension_name: String,
@@ -146,8 +149,18 @@ pub struct Respawn {
 }
 
 impl Packet for Respawn {
-    fn from_bytes(_: &mut impl Buf, _: ProtocolVersion) -> Result<Self> {
-        unimplemented!()
+    fn from_bytes(buf: &mut impl Buf, _: ProtocolVersion) -> Result<Self> {
+        Ok(Self {
+            dimension_type: buf.get_identifier()?,
+            dimension_name: buf.get_identifier()?,
+            hashed_seed: buf.try_get_i64()?,
+            gamemode: buf.try_get_u8()?,
+            previous_gamemode: buf.try_get_u8()?,
+            is_debug: buf.get_bool()?,
+            is_flat: buf.get_bool()?,
+            data_kept: buf.try_get_u8()?,
+            last_death: Death::get(buf)?,
+        })
     }
 
     fn put_buf(self, buf: &mut BytesMut, _: ProtocolVersion) {
@@ -179,11 +192,56 @@ impl Respawn {
     }
 }
 
+/// Moves the player, the client leaves the loading screen once it gets the first one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerPosition {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub yaw: f32,
+    pub pitch: f32,
+    /// Which of the values are relative to the current ones.
+    pub flags: u8,
+    pub teleport_id: i32,
+    /// Takes the player off what they ride, only sent before 1.19.4.
+    pub dismount_vehicle: bool,
+}
+
+impl Packet for PlayerPosition {
+    fn from_bytes(buf: &mut impl Buf, version: ProtocolVersion) -> Result<Self> {
+        Ok(Self {
+            x: buf.try_get_f64()?,
+            y: buf.try_get_f64()?,
+            z: buf.try_get_f64()?,
+            yaw: buf.try_get_f32()?,
+            pitch: buf.try_get_f32()?,
+            flags: buf.try_get_u8()?,
+            teleport_id: buf.get_varint()?,
+            dismount_vehicle: version < ProtocolVersion::V1_19_4 && buf.get_bool()?,
+        })
+    }
+
+    fn put_buf(self, buf: &mut BytesMut, version: ProtocolVersion) {
+        buf.put_f64(self.x);
+        buf.put_f64(self.y);
+        buf.put_f64(self.z);
+        buf.put_f32(self.yaw);
+        buf.put_f32(self.pitch);
+        buf.put_u8(self.flags);
+        buf.put_varint(self.teleport_id);
+        if version < ProtocolVersion::V1_19_4 {
+            buf.put_bool(self.dismount_vehicle);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct BossBar {
     pub uuid: Uuid,
     pub action: BossBarAction,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum BossBarAction {
     Add {
         title: Component,
@@ -199,6 +257,7 @@ pub enum BossBarAction {
     UpdateFlags(u8),
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum BossBarColor {
     Pink,
     Blue,
@@ -226,6 +285,7 @@ impl TryFrom<u8> for BossBarColor {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum BossBarDivision {
     None,
     SixNotches,
@@ -255,20 +315,20 @@ impl TryFrom<u8> for BossBarDivision {
 impl Packet for BossBar {
     fn from_bytes(buf: &mut impl Buf, _: ProtocolVersion) -> Result<Self> {
         Ok(Self {
-            uuid: buf.get_uuid(),
-            action: match buf.get_u8() {
+            uuid: buf.get_uuid()?,
+            action: match buf.try_get_u8()? {
                 0 => BossBarAction::Add {
                     title: buf.get_component()?,
-                    health: buf.get_f32(),
-                    color: buf.get_u8().try_into()?,
-                    division: buf.get_u8().try_into()?,
-                    flags: buf.get_u8(),
+                    health: buf.try_get_f32()?,
+                    color: buf.try_get_u8()?.try_into()?,
+                    division: buf.try_get_u8()?.try_into()?,
+                    flags: buf.try_get_u8()?,
                 },
                 1 => BossBarAction::Remove,
-                2 => BossBarAction::UpdateHealth(buf.get_f32()),
+                2 => BossBarAction::UpdateHealth(buf.try_get_f32()?),
                 3 => BossBarAction::UpdateTitle(buf.get_component()?),
-                4 => BossBarAction::UpdateStyle(buf.get_u8().try_into()?, buf.get_u8().try_into()?),
-                5 => BossBarAction::UpdateFlags(buf.get_u8()),
+                4 => BossBarAction::UpdateStyle(buf.try_get_u8()?.try_into()?, buf.try_get_u8()?.try_into()?),
+                5 => BossBarAction::UpdateFlags(buf.try_get_u8()?),
                 value => bail!("bossbar decoding byte {}", value),
             },
         })
@@ -314,6 +374,9 @@ impl Packet for BossBar {
     }
 }
 
+/// A command typed into chat. 1.19.2 has no `message_count`, its signed preview flag and last seen
+/// messages end up in `acknowledged`.
+#[derive(Debug, Clone, PartialEq)]
 pub struct ChatCommand {
     pub command: String,
     pub timestamp: i64,
@@ -323,35 +386,148 @@ pub struct ChatCommand {
     pub acknowledged: Bytes,
 }
 
-fn get_argument_signature(buf: &mut impl Buf) -> Result<(String, Bytes)> {
-    Ok((buf.get_string(256)?, buf.get_bytes()?))
+const SIGNATURE_LENGTH: usize = 256;
+
+fn get_argument_signature(buf: &mut impl Buf, version: ProtocolVersion) -> Result<(String, Bytes)> {
+    Ok((buf.get_string(16)?, get_signature(buf, version)?))
 }
 
-fn put_argument_signature(buf: &mut BytesMut, arg: &(String, Bytes)) {
+fn put_argument_signature(buf: &mut BytesMut, arg: &(String, Bytes), version: ProtocolVersion) {
     buf.put_string(&arg.0);
-    buf.put_byte_array(&arg.1);
+    put_signature(buf, &arg.1, version);
+}
+
+/// Message signatures have a fixed length without a length prefix since 1.19.3, before they are
+/// length prefixed.
+fn get_signature(buf: &mut impl Buf, version: ProtocolVersion) -> Result<Bytes> {
+    if version < ProtocolVersion::V1_19_3 {
+        return buf.get_bytes();
+    }
+    ensure!(buf.remaining() >= SIGNATURE_LENGTH, "Signature is too short");
+    Ok(buf.copy_to_bytes(SIGNATURE_LENGTH))
+}
+
+fn put_signature(buf: &mut BytesMut, signature: &[u8], version: ProtocolVersion) {
+    if version < ProtocolVersion::V1_19_3 {
+        buf.put_byte_array(signature);
+    } else {
+        buf.put_slice(signature);
+    }
+}
+
+/// The message count only exists since 1.19.3.
+fn get_message_count(buf: &mut impl Buf, version: ProtocolVersion) -> Result<i32> {
+    if version < ProtocolVersion::V1_19_3 {
+        return Ok(0);
+    }
+    buf.get_varint()
+}
+
+fn put_message_count(buf: &mut BytesMut, message_count: i32, version: ProtocolVersion) {
+    if version >= ProtocolVersion::V1_19_3 {
+        buf.put_varint(message_count);
+    }
 }
 
 impl Packet for ChatCommand {
-    fn from_bytes(buf: &mut impl Buf, _: ProtocolVersion) -> Result<Self> {
+    fn from_bytes(buf: &mut impl Buf, version: ProtocolVersion) -> Result<Self> {
         Ok(Self {
             command: buf.get_string(256)?,
-            timestamp: buf.get_i64(),
-            salt: buf.get_i64(),
-            arguments: get_array(buf, get_argument_signature)?,
-            message_count: buf.get_varint()?,
+            timestamp: buf.try_get_i64()?,
+            salt: buf.try_get_i64()?,
+            arguments: get_array(buf, |buf| get_argument_signature(buf, version))?,
+            message_count: get_message_count(buf, version)?,
             acknowledged: buf.rest(),
         })
     }
 
-    fn put_buf(self, buf: &mut BytesMut, _: ProtocolVersion) {
+    fn put_buf(self, buf: &mut BytesMut, version: ProtocolVersion) {
         buf.put_string(&self.command);
         buf.put_i64(self.timestamp);
         buf.put_i64(self.salt);
-        put_array(buf, self.arguments, put_argument_signature);
-        buf.put_varint(self.message_count);
+        put_array(buf, self.arguments, |buf, arg| put_argument_signature(buf, arg, version));
+        put_message_count(buf, self.message_count, version);
         buf.put_slice(&self.acknowledged);
-        //buf.put_bitset(self.acknowledged);
+    }
+}
+
+/// A chat message. 1.19.2 sends an empty signature for unsigned messages, which is `None` here.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub message: String,
+    pub timestamp: i64,
+    pub salt: i64,
+    pub signature: Option<Bytes>,
+    pub message_count: i32,
+    pub acknowledged: Bytes,
+}
+
+impl Packet for ChatMessage {
+    fn from_bytes(buf: &mut impl Buf, version: ProtocolVersion) -> Result<Self> {
+        let message = buf.get_string(256)?;
+        let timestamp = buf.try_get_i64()?;
+        let salt = buf.try_get_i64()?;
+        let signature = if version < ProtocolVersion::V1_19_3 {
+            Some(get_signature(buf, version)?).filter(|signature| !signature.is_empty())
+        } else {
+            buf.get_option(|buf| get_signature(buf, version))?
+        };
+        Ok(Self {
+            message,
+            timestamp,
+            salt,
+            signature,
+            message_count: get_message_count(buf, version)?,
+            acknowledged: buf.rest(),
+        })
+    }
+
+    fn put_buf(self, buf: &mut BytesMut, version: ProtocolVersion) {
+        buf.put_string(&self.message);
+        buf.put_i64(self.timestamp);
+        buf.put_i64(self.salt);
+        if version < ProtocolVersion::V1_19_3 {
+            put_signature(buf, self.signature.as_deref().unwrap_or_default(), version);
+        } else {
+            buf.put_option(&self.signature, |buf, signature| put_signature(buf, signature, version));
+        }
+        put_message_count(buf, self.message_count, version);
+        buf.put_slice(&self.acknowledged);
+    }
+}
+
+/// A message from the server (or the proxy) shown in chat, or above the hotbar with `overlay`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemChat {
+    pub content: Component,
+    pub overlay: bool,
+}
+
+impl Packet for SystemChat {
+    fn from_bytes(buf: &mut impl Buf, _: ProtocolVersion) -> Result<Self> {
+        Ok(Self {
+            content: buf.get_component()?,
+            overlay: buf.get_bool()?,
+        })
+    }
+
+    fn put_buf(self, buf: &mut BytesMut, _: ProtocolVersion) {
+        buf.put_component(&self.content).unwrap();
+        buf.put_bool(self.overlay);
+    }
+}
+
+/// Sent by the server every few seconds, the client answers with the same id.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeepAlive(pub i64);
+
+impl Packet for KeepAlive {
+    fn from_bytes(buf: &mut impl Buf, _: ProtocolVersion) -> Result<Self> {
+        Ok(Self(buf.try_get_i64()?))
+    }
+
+    fn put_buf(self, buf: &mut BytesMut, _: ProtocolVersion) {
+        buf.put_i64(self.0);
     }
 }
 
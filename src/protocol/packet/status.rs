@@ -1,12 +1,13 @@
 use crate::protocol::{buffer::BufMutExt, ProtocolVersion};
-use crate::Component;
-use anyhow::Result;
-use bytes::{Buf, BufMut, BytesMut};
+use crate::component::Component;
+use anyhow::{bail, Result};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use serde::{Serialize, Serializer};
 use uuid::Uuid;
 
 use super::Packet;
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct StatusRequest;
 
 impl Packet for StatusRequest {
@@ -61,25 +62,26 @@ pub struct Status {
     //pub enforces_secure_chat: bool,
 }
 
-pub struct StatusResponse<'a> {
-    pub status: &'a Vec<u8>,
+pub struct StatusResponse {
+    pub status: Bytes,
 }
 
-impl Packet for StatusResponse<'_> {
+impl Packet for StatusResponse {
     fn from_bytes(_buf: &mut impl Buf, _: ProtocolVersion) -> Result<Self> {
-        unimplemented!("read status response")
+        bail!("StatusResponse can only be sent")
     }
 
     fn put_buf(self, buf: &mut BytesMut, _: ProtocolVersion) {
-        buf.put_byte_array(self.status)
+        buf.put_byte_array(&self.status)
     }
 }
 
-pub struct Ping(i64);
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ping(pub i64);
 
 impl Packet for Ping {
     fn from_bytes(buf: &mut impl Buf, _: ProtocolVersion) -> Result<Self> {
-        Ok(Self(buf.get_i64()))
+        Ok(Self(buf.try_get_i64()?))
     }
 
     fn put_buf(self, buf: &mut BytesMut, _: ProtocolVersion) {
@@ -1,7 +1,7 @@
 use crate::online::Property;
 
 use super::buffer::{BufExt, BufMutExt};
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use bytes::{Buf, BufMut};
 
 pub fn get_property(buf: &mut impl Buf) -> Result<Property> {
@@ -16,12 +16,6 @@ pub fn put_property(buf: &mut impl BufMut, property: &Property) {
     buf.put_string(&property.name);
     buf.put_string(&property.value);
     buf.put_option(&property.signature, |b, s| b.put_string(s));
-    if let Some(signature) = &property.signature {
-        buf.put_bool(true);
-        buf.put_string(signature);
-    } else {
-        buf.put_bool(false);
-    }
 }
 
 pub fn get_array<T, B, F>(buf: &mut B, fun: F) -> Result<Vec<T>>
@@ -29,8 +23,11 @@ where
     B: Buf,
     F: Fn(&mut B) -> Result<T>,
 {
-    let length = buf.get_varint()? as usize;
-    let mut array = Vec::with_capacity(length);
+    let length = buf.get_varint()?;
+    ensure!(length >= 0, "Array length is negative");
+    let length = length as usize;
+    // the declared length is untrusted, don't preallocate more than the buffer could hold
+    let mut array = Vec::with_capacity(length.min(buf.remaining()));
 
     for _ in 0..length {
         array.push(fun(buf)?)
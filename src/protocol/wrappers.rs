@@ -1,16 +1,54 @@
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
 use uuid::Uuid;
 
+use crate::channels::Registered;
 use crate::online::generate_offline_uuid;
 
-pub struct ConnectionInfo {
+use super::ProtocolVersion;
+
+#[derive(Clone, Debug)]
+pub struct Player {
     pub username: String,
     pub uuid: Uuid,
-    pub boss_bars: Vec<Uuid>
+    pub address: SocketAddr,
+    pub protocol: ProtocolVersion,
 }
 
-impl ConnectionInfo {
-    pub fn new(username: String, uuid: Option<Uuid>) -> Self {
+impl Player {
+    pub fn new(username: String, uuid: Option<Uuid>, address: SocketAddr, protocol: ProtocolVersion) -> Self {
         let uuid = uuid.unwrap_or_else(|| generate_offline_uuid(&username));
-        Self { username, uuid, boss_bars: Vec::new() }
+        Self { username, uuid, address, protocol }
+    }
+}
+
+#[derive(Clone)]
+pub struct ConnectionInfo {
+    pub player: Player,
+    /// Server the player is currently connected to.
+    pub server: SocketAddr,
+    pub boss_bars: Vec<Uuid>,
+    /// Plugin channels registered by the client and the current server.
+    pub channels: Arc<Mutex<Registered>>,
+    /// When the client was sent the keepalive it has not answered yet.
+    pub keepalive: Arc<Mutex<Option<Instant>>>,
+    /// Set while the player, kicked from their server, waits in the queue of another one and
+    /// their packets have nowhere to go.
+    pub stranded: Arc<AtomicBool>,
+}
+
+impl ConnectionInfo {
+    pub fn new(player: Player, server: SocketAddr) -> Self {
+        Self {
+            player,
+            server,
+            boss_bars: Vec::new(),
+            channels: Arc::default(),
+            keepalive: Arc::default(),
+            stranded: Arc::default(),
+        }
     }
 }
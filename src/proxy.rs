@@ -0,0 +1,1508 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, ensure, Result};
+use bytes::BytesMut;
+use tracing::field::Empty;
+use tracing::{debug, error, info, info_span, warn, Instrument, Span};
+use openssl::encrypt::Decrypter;
+use openssl::rsa::Padding;
+use reqwest::{StatusCode, Url};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::{self, JoinHandle};
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+use crate::access::Access;
+use crate::bungeecord;
+use crate::capture::CaptureSettings;
+use crate::channels::{ChannelMessage, Channels};
+use crate::commands::{Commands, Source};
+use crate::component::Component;
+use crate::config::{Config, ConfigLoader, LiveConfig};
+use crate::error::ProxyError;
+use crate::event::{
+    ChatEvent, CommandEvent, DisconnectEvent, Event, EventBus, InitialServerEvent, KickResult,
+    KickedFromServerEvent, PostLoginEvent, PreLoginEvent, Priority, ServerConnectedEvent,
+    ServerPreConnectEvent,
+};
+use crate::haproxy;
+use crate::hooks::{DefaultHooks, Hooks};
+use crate::intercept::{Intercepted, Interceptors, Outcome};
+use crate::metrics::{Login, METRICS};
+use crate::online::{decrypt, generate_server_id, GameProfile, RSA_KEYS};
+use crate::players::{OnlinePlayer, Players, Request};
+use crate::queue::{self, QueueSettings, Queues, Waiter};
+use crate::protocol::buffer::{BufExt, BufMutExt};
+use crate::protocol::codec::connection::Connection;
+use crate::protocol::codec::registry::PLAY_REG;
+use crate::protocol::packet::handshake::{Handshake, NextState};
+use crate::protocol::packet::login::{
+    Disconnect, EncryptionRequest, EncryptionResponse, LoginStart, LoginSuccess, SetCompression,
+};
+use crate::protocol::packet::play::{
+    BossBar, BossBarAction, JoinGame, KeepAlive, PlayerPosition, Respawn, SystemChat,
+};
+use crate::protocol::packet::status::{Ping, StatusRequest, StatusResponse};
+use crate::protocol::packet::{Packet, PacketType, RawPacket};
+use crate::protocol::wrappers::{ConnectionInfo, Player};
+use crate::protocol::{Direction, ProtocolVersion, State};
+use crate::throttle::{Limits, Throttle};
+
+/// Client and backend connections of a player that finished logging in.
+type Joined = (Connection, Connection, ConnectionInfo);
+
+/// A client that got its login success.
+enum LoggedIn {
+    Joined(Joined),
+    /// Waiting in the queue of `server`, held in `world`.
+    Queued {
+        client: Connection,
+        player: Player,
+        server: SocketAddr,
+        world: JoinGame,
+    },
+}
+
+/// State shared by all connections of one [`Proxy`].
+struct Context {
+    config: Arc<LiveConfig>,
+    hooks: Box<dyn Hooks>,
+    events: EventBus,
+    interceptors: Arc<Interceptors>,
+    channels: Channels,
+    players: Arc<Players>,
+    access: Arc<Access>,
+    commands: Commands,
+    throttle: Throttle,
+    timeouts: Timeouts,
+    /// Clients connect through a load balancer that sends a PROXY protocol header.
+    proxy_protocol: bool,
+    /// Send a PROXY protocol header to backends.
+    backend_proxy_protocol: bool,
+    /// What players in game are disconnected with on shutdown.
+    shutdown_message: Component,
+    shutdown_timeout: Option<Duration>,
+    capture: Option<CaptureSettings>,
+    queue: Option<Queues>,
+    shutdown: CancellationToken,
+    connections: TaskTracker,
+}
+
+/// How long the proxy waits on a connection before giving up on it, `None` waits forever.
+#[derive(Debug, Clone)]
+pub struct Timeouts {
+    /// For the handshake of a new connection.
+    pub handshake: Option<Duration>,
+    /// For each packet of a status ping.
+    pub status: Option<Duration>,
+    /// For each packet of a login, from the client and from the backend.
+    pub login: Option<Duration>,
+    /// In game, for the client to answer a keepalive and for the backend to send anything.
+    pub keepalive: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            handshake: Some(Duration::from_secs(5)),
+            status: Some(Duration::from_secs(5)),
+            login: Some(Duration::from_secs(30)),
+            keepalive: Some(Duration::from_secs(30)),
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn from_config(config: &Config) -> Self {
+        let millis = |millis| Some(Duration::from_millis(millis)).filter(|_| millis > 0);
+        Self {
+            handshake: millis(config.handshake_timeout),
+            status: millis(config.status_timeout),
+            login: millis(config.login_timeout),
+            keepalive: millis(config.keepalive_timeout),
+        }
+    }
+}
+
+/// Configures and starts a [`Proxy`].
+///
+/// ```no_run
+/// # async fn run() -> anyhow::Result<()> {
+/// let proxy = rower::ProxyBuilder::new().start().await?;
+/// proxy.wait().await;
+/// proxy.shutdown().await
+/// # }
+/// ```
+#[derive(Default)]
+pub struct ProxyBuilder {
+    config: Option<Config>,
+    config_loader: Option<ConfigLoader>,
+    listener: Option<TcpListener>,
+    hooks: Option<Box<dyn Hooks>>,
+    events: EventBus,
+    interceptors: Interceptors,
+    channels: Channels,
+    bungeecord: bool,
+    access_list: Option<PathBuf>,
+    throttle: Limits,
+    timeouts: Timeouts,
+    proxy_protocol: bool,
+    backend_proxy_protocol: bool,
+    shutdown_message: Option<Component>,
+    shutdown_timeout: Option<Duration>,
+    capture: Option<CaptureSettings>,
+    queue: Option<QueueSettings>,
+    #[cfg(feature = "console")]
+    console: bool,
+    #[cfg(feature = "api")]
+    api: Option<(SocketAddr, String)>,
+    #[cfg(feature = "plugins")]
+    plugins: Option<PathBuf>,
+}
+
+impl ProxyBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The config of this proxy, the defaults without one.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// How `/reload` and the admin API read the config again, e.g. from the file it came from.
+    /// Without one they only reload the access list.
+    pub fn config_loader(mut self, loader: impl Fn() -> Result<Config> + Send + Sync + 'static) -> Self {
+        self.config_loader = Some(Box::new(loader));
+        self
+    }
+
+    /// Listener to accept players on, the configured bind address otherwise.
+    pub fn listener(mut self, listener: TcpListener) -> Self {
+        self.listener = Some(listener);
+        self
+    }
+
+    pub fn hooks(mut self, hooks: impl Hooks) -> Self {
+        self.hooks = Some(Box::new(hooks));
+        self
+    }
+
+    /// Registers an event handler, see [`EventBus::register`].
+    pub fn on<E, F, Fut>(mut self, priority: Priority, handler: F) -> Self
+    where
+        E: Event,
+        F: Fn(E) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = E> + Send + 'static,
+    {
+        self.events.register(priority, handler);
+        self
+    }
+
+    /// Intercepts play packets of type `T`, see [`Interceptors::register`].
+    pub fn intercept<T, F, Fut>(
+        mut self,
+        direction: Direction,
+        ids: &[(ProtocolVersion, u8)],
+        handler: F,
+    ) -> Self
+    where
+        T: Packet + Send + 'static,
+        F: Fn(Intercepted<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Intercepted<T>> + Send + 'static,
+    {
+        self.interceptors.register(direction, ids, handler);
+        self
+    }
+
+    /// Makes `channel` a proxy channel, see [`Channels::subscribe`].
+    pub fn channel<F, Fut>(mut self, channel: impl Into<String>, handler: F) -> Self
+    where
+        F: Fn(ChannelMessage) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.channels.subscribe(channel, handler);
+        self
+    }
+
+    /// Answers backend plugins on the BungeeCord channel, see [`crate::bungeecord`].
+    pub fn bungeecord(mut self) -> Self {
+        self.bungeecord = true;
+        self
+    }
+
+    /// Keeps the bans and the whitelist in `path` instead of only in memory, see [`crate::access`].
+    pub fn access_list(mut self, path: impl Into<PathBuf>) -> Self {
+        self.access_list = Some(path.into());
+        self
+    }
+
+    /// Connection and login limits and the anti-bot verification, see [`crate::throttle`].
+    /// Nothing is limited without them.
+    pub fn throttle(mut self, limits: Limits) -> Self {
+        self.throttle = limits;
+        self
+    }
+
+    /// How long connections may stay silent, see [`Timeouts`].
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Reads a PROXY protocol header, see [`crate::haproxy`], at the start of every connection and
+    /// takes the client address from it. Connections without one are closed.
+    pub fn proxy_protocol(mut self) -> Self {
+        self.proxy_protocol = true;
+        self
+    }
+
+    /// Starts backend connections with a PROXY protocol header naming the player's address.
+    pub fn backend_proxy_protocol(mut self) -> Self {
+        self.backend_proxy_protocol = true;
+        self
+    }
+
+    /// What players in game are disconnected with when the proxy shuts down,
+    /// "Proxy is shutting down" otherwise.
+    pub fn shutdown_message(mut self, message: Component) -> Self {
+        self.shutdown_message = Some(message);
+        self
+    }
+
+    /// How long [`Proxy::shutdown`] waits for connections to close, without one it waits for all.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(timeout);
+        self
+    }
+
+    /// Captures the packets of `players` (everyone when empty) to files in `dir`, see [`crate::capture`].
+    pub fn capture(mut self, dir: impl Into<PathBuf>, players: Vec<String>) -> Self {
+        self.capture = Some(CaptureSettings {
+            dir: dir.into(),
+            players,
+        });
+        self
+    }
+
+    /// Keeps players a full or unreachable server refuses, at login or when they switch or are
+    /// kicked to it, in its queue, see [`crate::queue`].
+    pub fn queue(mut self, settings: QueueSettings) -> Self {
+        self.queue = Some(settings);
+        self
+    }
+
+    /// Reads proxy commands from stdin, see [`crate::console`].
+    #[cfg(feature = "console")]
+    pub fn console(mut self) -> Self {
+        self.console = true;
+        self
+    }
+
+    /// Serves the admin API on `address` to callers with `token`, see [`crate::api`].
+    #[cfg(feature = "api")]
+    pub fn api(mut self, address: SocketAddr, token: impl Into<String>) -> Self {
+        self.api = Some((address, token.into()));
+        self
+    }
+
+    /// Loads the `.wasm` plugins in `dir` when the proxy starts, see [`crate::plugin`].
+    #[cfg(feature = "plugins")]
+    pub fn plugins(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.plugins = Some(dir.into());
+        self
+    }
+
+    /// Binds (unless a listener was given) and starts accepting players on the current runtime.
+    pub async fn start(self) -> Result<Proxy> {
+        let live = Arc::new(LiveConfig::new(self.config.unwrap_or_default(), self.config_loader));
+        let config = live.get();
+        let listener = match self.listener {
+            Some(listener) => listener,
+            None => TcpListener::bind(config.address).await?,
+        };
+        let local_addr = listener.local_addr()?;
+
+        let players = Arc::new(Players::new());
+        #[allow(unused_mut)]
+        let (mut events, mut interceptors) = (self.events, self.interceptors);
+        let mut channels = self.channels;
+        if self.bungeecord {
+            bungeecord::subscribe(&mut channels, players.clone(), live.clone());
+        }
+        #[cfg(feature = "plugins")]
+        if let Some(dir) = &self.plugins {
+            crate::plugin::load(dir, &config, &mut events, &mut interceptors, players.clone())?;
+        }
+
+        let access = Arc::new(match &self.access_list {
+            Some(path) => Access::load(path)?,
+            None => Access::in_memory(),
+        });
+
+        #[cfg(feature = "api")]
+        let api = match self.api {
+            Some((address, token)) => {
+                ensure!(!token.is_empty(), "The admin API needs a token");
+                if !address.ip().is_loopback() {
+                    warn!("The admin API listens on {}, which is not a local address", address);
+                }
+                let api = crate::api::Api {
+                    config: live.clone(),
+                    players: players.clone(),
+                    access: access.clone(),
+                    token: token.into(),
+                };
+                Some((TcpListener::bind(address).await?, api))
+            }
+            None => None,
+        };
+        #[cfg(feature = "api")]
+        let api_addr = match &api {
+            Some((listener, _)) => Some(listener.local_addr()?),
+            None => None,
+        };
+
+        let shutdown = CancellationToken::new();
+        let context = Arc::new(Context {
+            config: live.clone(),
+            hooks: self.hooks.unwrap_or_else(|| Box::new(DefaultHooks)),
+            events,
+            interceptors: Arc::new(interceptors),
+            channels,
+            commands: Commands::new(live, access.clone(), players.clone(), shutdown.clone()),
+            players,
+            access,
+            throttle: Throttle::new(self.throttle),
+            timeouts: self.timeouts,
+            proxy_protocol: self.proxy_protocol,
+            backend_proxy_protocol: self.backend_proxy_protocol,
+            shutdown_message: self
+                .shutdown_message
+                .unwrap_or_else(|| Component::text("Proxy is shutting down")),
+            shutdown_timeout: self.shutdown_timeout,
+            capture: self.capture,
+            queue: self.queue.map(Queues::new),
+            shutdown,
+            connections: TaskTracker::new(),
+        });
+
+        if self.access_list.is_some() {
+            task::spawn(context.access.clone().watch(context.shutdown.clone()));
+        }
+        #[cfg(feature = "api")]
+        if let Some((listener, api)) = api {
+            spawn(&context.connections, crate::api::serve(listener, api, context.shutdown.clone()));
+        }
+        #[cfg(feature = "console")]
+        if self.console {
+            let console = context.clone();
+            crate::console::start(
+                move |line| console.commands.execute(&Source::Console, &*console.hooks, line),
+                context.shutdown.clone(),
+            )?;
+        }
+
+        let accept = task::spawn({
+            let context = context.clone();
+            async move {
+                let result = listen(listener, &context).await;
+                context.shutdown.cancel();
+                result
+            }
+        });
+
+        Ok(Proxy {
+            local_addr,
+            #[cfg(feature = "api")]
+            api_addr,
+            context,
+            accept,
+        })
+    }
+}
+
+/// Handle to a running proxy. Dropping it leaves the proxy running in the background.
+pub struct Proxy {
+    local_addr: SocketAddr,
+    #[cfg(feature = "api")]
+    api_addr: Option<SocketAddr>,
+    context: Arc<Context>,
+    accept: JoinHandle<Result<()>>,
+}
+
+impl Proxy {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Address the admin API listens on, if it was enabled.
+    #[cfg(feature = "api")]
+    pub fn api_addr(&self) -> Option<SocketAddr> {
+        self.api_addr
+    }
+
+    pub fn players(&self) -> &Players {
+        &self.context.players
+    }
+
+    /// The server queues, if they are enabled.
+    pub fn queues(&self) -> Option<&Queues> {
+        self.context.queue.as_ref()
+    }
+
+    /// Bans and the whitelist, changes made here apply to the next login.
+    pub fn access(&self) -> &Access {
+        &self.context.access
+    }
+
+    /// Resolves once the proxy stops accepting players, because of [`Proxy::shutdown`] or an accept error.
+    pub async fn wait(&self) {
+        self.context.shutdown.cancelled().await
+    }
+
+    /// Stops accepting, disconnects players that are in game and waits for every connection to close,
+    /// up to the [`ProxyBuilder::shutdown_timeout`]. Returns the error that stopped the accept loop, if any.
+    pub async fn shutdown(self) -> Result<()> {
+        self.context.shutdown.cancel();
+        let result = self.accept.await?;
+
+        let connections = &self.context.connections;
+        connections.close();
+        info!("Waiting for {} connection task(s) to finish", connections.len());
+        let finished = match self.context.shutdown_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, connections.wait()).await.is_ok(),
+            None => {
+                connections.wait().await;
+                true
+            }
+        };
+        if !finished {
+            warn!("Gave up on {} connection task(s) that did not finish in time", connections.len());
+        }
+        result
+    }
+}
+
+async fn listen(listener: TcpListener, context: &Arc<Context>) -> Result<()> {
+    loop {
+        let (stream, address) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = context.shutdown.cancelled() => return Ok(()),
+        };
+        stream.set_nodelay(true)?;
+        METRICS.connection();
+        // filled in as the connection gets further, see `crate::logging`
+        let span = info_span!(
+            "connection",
+            %address,
+            protocol = Empty,
+            username = Empty,
+            uuid = Empty,
+            server = Empty
+        );
+        span.in_scope(|| {
+            spawn(&context.connections, handle_connection(stream, address, context.clone()))
+        });
+    }
+}
+
+/// Spawns `task` on `tasks` in the current span, so shutdown waits for it.
+fn spawn(tasks: &TaskTracker, task: impl Future<Output = Result<()>> + 'static + Send) -> JoinHandle<()> {
+    tasks.spawn(log_error(task).in_current_span())
+}
+
+async fn log_error(task: impl Future<Output = Result<()>>) {
+    if let Err(err) = task.await {
+        error!("{:#}", err);
+    }
+}
+
+/// Fails when `future` takes longer than `timeout`, if there is one.
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = Result<T>>,
+    what: impl FnOnce() -> String,
+) -> Result<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| anyhow!("Timed out {}", what()))?,
+        None => future.await,
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    address: SocketAddr,
+    context: Arc<Context>,
+) -> Result<()> {
+    // players still logging in are just dropped on shutdown, the ones let in get a disconnect
+    let logged_in = tokio::select! {
+        logged_in = handle_handshake(stream, address, &context) => logged_in?,
+        _ = context.shutdown.cancelled() => return Ok(()),
+    };
+
+    let joined = match logged_in {
+        Some(LoggedIn::Joined(joined)) => Some(joined),
+        // out of the select above, the queue sends its players off on shutdown itself
+        Some(LoggedIn::Queued {
+            mut client,
+            player,
+            server,
+            world,
+        }) => match wait_in_queue(&mut client, &player, server, world, &context).await? {
+            Some((server, server_address)) => {
+                Some(joined(client, server, player, server_address, &context).await)
+            }
+            None => {
+                METRICS.login(Login::Abandoned);
+                None
+            }
+        },
+        None => None,
+    };
+    let Some((client, server, info)) = joined else {
+        return Ok(());
+    };
+
+    let player = info.player.clone();
+    let result = handle_play(client, server, info, context.clone()).await;
+    context.events.fire(DisconnectEvent { player }).await;
+    result
+}
+
+async fn handle_handshake(
+    mut stream: TcpStream,
+    mut address: SocketAddr,
+    context: &Context,
+) -> Result<Option<LoggedIn>> {
+    if context.proxy_protocol {
+        let header = haproxy::read_header(&mut stream);
+        let waiting = || format!("waiting for the PROXY header of {}", address);
+        if let Some(client) = with_timeout(context.timeouts.handshake, header, waiting).await? {
+            address = client;
+            Span::current().record("address", tracing::field::display(address));
+        }
+    }
+    // connections over the limit are dropped before anything else is read
+    if !context.throttle.connection(address.ip()) {
+        return Ok(None);
+    }
+
+    let mut client = Connection::new(stream, Direction::Clientbound);
+    client.set_compression_level(context.config.get().compression_level);
+    client.set_read_timeout(context.timeouts.handshake);
+    let Handshake {
+        state, protocol, ..
+    } = client.recv_packet().await?;
+
+    client.protocol = protocol.into();
+    Span::current().record("protocol", tracing::field::debug(client.protocol));
+
+    match state {
+        NextState::Status => handle_status(client, address, context).await.map(|_| None),
+        NextState::Login => {
+            let joined = handle_login(client, address, context).await;
+            if joined.is_err() {
+                METRICS.login(Login::Failed);
+            }
+            joined
+        }
+    }
+}
+
+async fn handle_status(mut client: Connection, address: SocketAddr, context: &Context) -> Result<()> {
+    client.change_state(State::Status);
+    client.set_read_timeout(context.timeouts.status);
+    client.recv_packet::<StatusRequest>().await?;
+    context.throttle.pinged(address.ip());
+
+    client
+        .send_packet(StatusResponse {
+            status: context.hooks.status(),
+        })
+        .await?;
+
+    let ping: Ping = client.recv_packet().await?;
+    client.send_packet(ping).await
+}
+
+async fn handle_login(
+    mut client: Connection,
+    address: SocketAddr,
+    context: &Context,
+) -> Result<Option<LoggedIn>> {
+    client.change_state(State::Login);
+    client.set_read_timeout(context.timeouts.login);
+    let LoginStart { username, uuid } = client.recv_packet().await?;
+    Span::current().record("username", &username);
+
+    // 1.20.2 and later go through the configuration state after the login, which is not implemented
+    if client.protocol < ProtocolVersion::V1_19_2 || client.protocol > ProtocolVersion::V1_20 {
+        let reason = Component::text("We support versions 1.19.2 to 1.20.1");
+        return refuse(client, reason, Login::Unsupported).await;
+    }
+    if let Some(reason) = context.throttle.login(address.ip()) {
+        return refuse(client, reason, Login::Throttled).await;
+    }
+
+    let pre_login = PreLoginEvent {
+        username,
+        address,
+        protocol: client.protocol,
+        denied: None,
+    };
+    let PreLoginEvent {
+        username, denied, ..
+    } = context.events.fire(pre_login).await;
+
+    if let Some(reason) = denied {
+        return refuse(client, reason, Login::Denied).await;
+    }
+    #[allow(unreachable_code)]
+    if context.config.get().online {
+        let reason = Component::text("Online mode is not implemented");
+        return refuse(client, reason, Login::Refused).await;
+        let mut decrypter = Decrypter::new(&RSA_KEYS.pair_key)?;
+        decrypter.set_rsa_padding(Padding::PKCS1)?;
+
+        let server_verify_token = rand::random();
+
+        client
+            .send_packet(EncryptionRequest {
+                server_id: String::new(),
+                public_key: RSA_KEYS.public_key.to_owned(),
+                verify_token: server_verify_token,
+            })
+            .await?;
+
+        let EncryptionResponse {
+            shared_secret,
+            verify_token,
+        } = client.recv_packet().await?;
+
+        let verify_token = decrypt(&mut decrypter, &verify_token)?;
+        ensure!(verify_token == server_verify_token, "Invalid verify token");
+
+        let shared_secret: [u8; 16] = decrypt(&mut decrypter, &shared_secret)?
+            .as_slice()
+            .try_into()?;
+
+        let http_client = reqwest::Client::new();
+        let server_id = generate_server_id(&shared_secret, &RSA_KEYS.public_key)?;
+        let url = Url::parse_with_params(
+            "https://sessionserver.mojang.com/session/minecraft/hasJoined",
+            &[("username", &username), ("serverId", &server_id)],
+        )?;
+
+        let responose = http_client.get(url).send().await?.error_for_status()?;
+
+        match responose.status() {
+            StatusCode::OK => {
+                let _profile: GameProfile = responose.json().await?;
+            }
+            StatusCode::NO_CONTENT => {
+                return client
+                    .disconnect(Component::text("Server is in online mode"))
+                    .await
+                    .map(|_| None);
+            }
+            _ => {
+                return client
+                    .disconnect(Component::text("Failed to authenticate with Mojang"))
+                    .await
+                    .map(|_| None);
+            }
+        }
+
+        client.enable_encryption(shared_secret)?;
+    }
+
+    let player = Player::new(username, uuid, address, client.protocol);
+    Span::current()
+        .record("username", &player.username)
+        .record("uuid", tracing::field::display(player.uuid));
+    if let Some(reason) = context.access.check(&player) {
+        return refuse(client, reason, Login::Refused).await;
+    }
+    if context.throttle.needs_verification(address.ip()) {
+        // the limbo keeps the client waiting on purpose and has timeouts of its own
+        client.set_read_timeout(None);
+        if !context.throttle.verify(&mut client, address.ip()).await? {
+            METRICS.login(Login::Unverified);
+            return Ok(None);
+        }
+        client.set_read_timeout(context.timeouts.login);
+    }
+    if let Some(settings) = context.capture.as_ref().filter(|settings| settings.wants(&player.username)) {
+        match settings.start(&player) {
+            Ok(capture) => {
+                info!("Capturing packets to {}", capture.path().display());
+                client.capture(Arc::new(capture));
+            }
+            Err(err) => warn!("Could not start a capture: {:#}", err),
+        }
+    }
+
+    let threshold = context.config.get().compression_threshold;
+    if threshold > -1 {
+        client.queue_packet(SetCompression { threshold }).await?;
+        client.enable_compression(threshold as u32);
+    }
+
+    let initial = InitialServerEvent {
+        player: player.clone(),
+        server: context.config.get().backend_server,
+    };
+    let initial_server = context.events.fire(initial).await.server;
+
+    // a player the server can't take waits in its queue, when there is a world to hold them in
+    let connected = match connect(context, &player, initial_server).await {
+        Ok(connected) => Ok(connected),
+        Err(err) => match context.queue.as_ref().and_then(|queue| queue.world(player.protocol)) {
+            Some(world) => {
+                let why = match err {
+                    ProxyError::Disconnected(reason) => reason.to_plain(),
+                    ProxyError::Other(error) => format!("{:#}", error),
+                };
+                info!("Queued for {}: {}", context.config.get().server_name(initial_server), why);
+                Err(world)
+            }
+            None => match err {
+                ProxyError::Disconnected(reason) => {
+                    return refuse(client, reason, Login::Kicked).await
+                }
+                ProxyError::Other(error) => return Err(error),
+            },
+        },
+    };
+
+    client
+        .send_packet(LoginSuccess {
+            uuid: player.uuid,
+            username: player.username.clone(),
+            properties: Vec::new(),
+        })
+        .await?;
+
+    context
+        .events
+        .fire(PostLoginEvent {
+            player: player.clone(),
+        })
+        .await;
+
+    let logged_in = match connected {
+        Ok((server, server_address)) => {
+            LoggedIn::Joined(joined(client, server, player, server_address, context).await)
+        }
+        Err(world) => LoggedIn::Queued {
+            client,
+            player,
+            server: initial_server,
+            world,
+        },
+    };
+    Ok(Some(logged_in))
+}
+
+/// Finishes the login of a player connected to their first server.
+async fn joined(
+    client: Connection,
+    server: Connection,
+    player: Player,
+    server_address: SocketAddr,
+    context: &Context,
+) -> Joined {
+    Span::current().record("server", context.config.get().server_name(server_address));
+    context
+        .events
+        .fire(ServerConnectedEvent {
+            player: player.clone(),
+            server: server_address,
+            previous: None,
+        })
+        .await;
+
+    METRICS.login(Login::Success);
+    (client, server, ConnectionInfo::new(player, server_address))
+}
+
+/// Disconnects a client that is not let in, counting the login as `outcome` once it is told why.
+async fn refuse(client: Connection, reason: Component, outcome: Login) -> Result<Option<LoggedIn>> {
+    client.disconnect(reason).await?;
+    METRICS.login(outcome);
+    Ok(None)
+}
+
+/// Holds a logged in client in the queue of `server` until the server lets them in, with the
+/// client in `world` meanwhile. `None` when the client left or the proxy shuts down first.
+async fn wait_in_queue(
+    client: &mut Connection,
+    player: &Player,
+    server: SocketAddr,
+    world: JoinGame,
+    context: &Context,
+) -> Result<Option<(Connection, SocketAddr)>> {
+    let queue = context.queue.as_ref().expect("players are only held with queues enabled");
+    let keepalive_id = *PLAY_REG
+        .get_registry(Direction::Serverbound, player.protocol)
+        .get_id::<KeepAlive>()?;
+    client.change_state(State::Play);
+    // the client is watched through keepalives instead
+    client.set_read_timeout(None);
+
+    let tier = queue.tier(&*context.hooks, &context.config.get(), player);
+    let ticket = queue.join(server, player.uuid, tier);
+    let mut waiter = Waiter::new(ticket, context.config.get().server_name(server));
+    client.queue_packet(world).await?;
+    // somewhere in the empty world, the client stays on the loading screen without a position
+    client
+        .queue_packet(PlayerPosition {
+            x: 0.0,
+            y: 100.0,
+            z: 0.0,
+            yaw: 0.0,
+            pitch: 0.0,
+            flags: 0,
+            teleport_id: 0,
+            dismount_vehicle: false,
+        })
+        .await?;
+    send_all(client, waiter.boss_bar()).await?;
+
+    let mut keepalive = tokio::time::interval(queue::KEEPALIVE_INTERVAL);
+    keepalive.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut pending = None;
+
+    let connected = loop {
+        tokio::select! {
+            packet = client.recv_raw_packet() => {
+                if packet?.id() == keepalive_id {
+                    pending = None;
+                }
+                continue;
+            }
+            _ = keepalive.tick() => {
+                let timeout = context.timeouts.keepalive;
+                if let (Some(sent), Some(timeout)) = (pending, timeout) {
+                    if Instant::now().duration_since(sent) >= timeout {
+                        let reason = Component::text("Timed out");
+                        client.send_packet(Disconnect { reason }).await?;
+                        return Ok(None);
+                    }
+                }
+                pending.get_or_insert_with(Instant::now);
+                client.send_packet(KeepAlive(rand::random())).await?;
+                continue;
+            }
+            turn = waiter.next() => {
+                send_all(client, turn.boss_bars).await?;
+                if !turn.try_server {
+                    continue;
+                }
+            }
+            _ = context.shutdown.cancelled() => {
+                client.send_packet(Disconnect { reason: context.shutdown_message.clone() }).await?;
+                return Ok(None);
+            }
+        }
+        // a server slow to answer doesn't hold up the shutdown
+        let connecting = tokio::select! {
+            connected = connect(context, player, server) => connected,
+            _ = context.shutdown.cancelled() => {
+                client.send_packet(Disconnect { reason: context.shutdown_message.clone() }).await?;
+                return Ok(None);
+            }
+        };
+        match connecting {
+            Ok(connected) => break connected,
+            Err(ProxyError::Disconnected(reason)) => {
+                debug!("Still queued for {}: {}", waiter.server_name(), reason.to_plain())
+            }
+            Err(ProxyError::Other(error)) => {
+                debug!("Still queued for {}: {:#}", waiter.server_name(), error)
+            }
+        }
+    };
+    drop(waiter);
+
+    let (mut server, server_address) = connected;
+    let join: JoinGame = server.recv_packet().await?;
+    let respawn = Respawn::from_joingame(&join);
+    client.queue_packet(queue::boss_bar_removed()).await?;
+    client.queue_packet(join).await?;
+    client.send_packet(respawn).await?;
+    Ok(Some((server, server_address)))
+}
+
+/// Sends `packets` with a single flush.
+async fn send_all<T: Packet + 'static>(client: &mut Connection, packets: Vec<T>) -> Result<()> {
+    let mut packets = packets.into_iter().peekable();
+    while let Some(packet) = packets.next() {
+        match packets.peek() {
+            Some(_) => client.queue_packet(packet).await?,
+            None => client.send_packet(packet).await?,
+        }
+    }
+    Ok(())
+}
+
+async fn handle_play(
+    mut client: Connection,
+    mut server: Connection,
+    connection: ConnectionInfo,
+    context: Arc<Context>,
+) -> Result<()> {
+    client.change_state(State::Play);
+    // in game both sides are watched through keepalives instead
+    client.set_read_timeout(None);
+    server.set_read_timeout(None);
+    let (mut server_side, client_side) = client.mix(server);
+    let (tx, rx) = tokio::sync::mpsc::channel(1);
+    // packets interceptors send to the side the other task writes to
+    let (to_client, client_queue) = mpsc::unbounded_channel();
+    let (to_server, server_queue) = mpsc::unbounded_channel();
+    let (request, requests) = mpsc::unbounded_channel();
+
+    let online = OnlinePlayer::new(
+        connection.player.clone(),
+        connection.server,
+        to_client.clone(),
+        to_server.clone(),
+        request,
+    );
+    context.players.insert(online.clone());
+    if let Some(register) = context.channels.registration(&connection.channels) {
+        server_side.send_packet(register).await?;
+    }
+
+    let client = handle_client(
+        server_side,
+        connection.clone(),
+        rx,
+        server_queue,
+        to_client,
+        context.clone(),
+    );
+    let server = handle_server(
+        client_side,
+        connection,
+        tx,
+        client_queue,
+        requests,
+        to_server,
+        context.clone(),
+    );
+    let mut server_handle = spawn(&context.connections, server);
+    let mut client_handle = spawn(&context.connections, client);
+
+    // when one side fails (e.g. sends a malformed packet) the other one goes down with it
+    tokio::select! {
+        _ = &mut server_handle => client_handle.abort(),
+        _ = &mut client_handle => server_handle.abort(),
+    }
+
+    let removed = context.players.remove(&online);
+    if let (Some(queue), Some(removed)) = (&context.queue, removed) {
+        queue.freed(removed.server);
+    }
+    Ok(())
+}
+
+async fn handle_client(
+    mut conn: Connection,
+    connection: ConnectionInfo,
+    mut rx: mpsc::Receiver<Connection>,
+    mut queue: mpsc::UnboundedReceiver<RawPacket>,
+    to_client: mpsc::UnboundedSender<RawPacket>,
+    context: Arc<Context>,
+) -> Result<()> {
+    let player = &connection.player;
+    let keepalive = *PLAY_REG
+        .get_registry(Direction::Serverbound, player.protocol)
+        .get_id::<KeepAlive>()?;
+    loop {
+        let packet = tokio::select! {
+            packet = conn.recv_raw_packet() => packet?,
+            Some(packet) = queue.recv() => {
+                // dropped along with the client's own packets while there is no server
+                if !connection.stranded.load(Ordering::Relaxed) {
+                    conn.auto_send_raw_packet(packet).await?;
+                }
+                continue;
+            }
+            server = rx.recv() => {
+                let server = server.ok_or_else(|| anyhow!("server closed"))?;
+                let (new_conn, _) = conn.mix(server);
+                conn = new_conn;
+                connection.stranded.store(false, Ordering::Relaxed);
+                continue;
+            }
+        };
+        if packet.id() == keepalive {
+            *connection.keepalive.lock().unwrap() = None;
+        }
+        if connection.stranded.load(Ordering::Relaxed) {
+            continue;
+        }
+
+        let Outcome { packet, injected, replies } = context
+            .interceptors
+            .intercept(Direction::Serverbound, player, packet)
+            .await?;
+        for packet in replies {
+            to_client.send(packet).map_err(|_| anyhow!("client closed"))?;
+        }
+
+        if let Some(packet) = packet {
+            match conn.decode(packet)? {
+                PacketType::ChatMessage(mut packet) => {
+                    let event = ChatEvent {
+                        player: player.clone(),
+                        message: packet.message,
+                        cancelled: false,
+                    };
+                    let event = context.events.fire(event).await;
+                    if !event.cancelled {
+                        packet.message = event.message;
+                        conn.auto_send_packet(packet).await?;
+                    }
+                }
+                PacketType::ChatCommand(mut packet) => {
+                    let event = CommandEvent {
+                        player: player.clone(),
+                        command: packet.command,
+                        cancelled: false,
+                    };
+                    let event = context.events.fire(event).await;
+                    let feedback = match context.players.get(&player.uuid) {
+                        Some(online) if !event.cancelled => context
+                            .commands
+                            .execute(&Source::Player(online.clone()), &*context.hooks, &event.command)
+                            .map(|content| (online, content)),
+                        _ => None,
+                    };
+                    if let Some((online, content)) = feedback {
+                        online.send_to_client(SystemChat { content, overlay: false })?;
+                    } else if !event.cancelled {
+                        packet.command = event.command;
+                        conn.auto_send_packet(packet).await?;
+                    }
+                }
+                PacketType::PluginMessage(packet) => {
+                    let packet = context
+                        .channels
+                        .route(
+                            packet,
+                            Direction::Serverbound,
+                            player,
+                            &context.players,
+                            &connection.channels,
+                        )
+                        .await;
+                    if let Some(packet) = packet {
+                        conn.auto_send_packet(packet).await?;
+                    }
+                }
+                PacketType::Raw(packet) => {
+                    conn.auto_send_raw_packet(packet).await?;
+                }
+                _ => unreachable!("client cos wysłał"),
+            }
+        }
+
+        for packet in injected {
+            conn.auto_send_raw_packet(packet).await?;
+        }
+    }
+}
+
+async fn handle_server(
+    mut conn: Connection,
+    mut connection: ConnectionInfo,
+    tx: mpsc::Sender<Connection>,
+    mut queue: mpsc::UnboundedReceiver<RawPacket>,
+    mut requests: mpsc::UnboundedReceiver<Request>,
+    to_server: mpsc::UnboundedSender<RawPacket>,
+    context: Arc<Context>,
+) -> Result<()> {
+    let keepalive = *PLAY_REG
+        .get_registry(Direction::Clientbound, connection.player.protocol)
+        .get_id::<KeepAlive>()?;
+    let pending = connection.keepalive.clone();
+    let mut last_read = Instant::now();
+    // a server that refused a switch, with the player waiting in its queue
+    let mut queued: Option<Waiter> = None;
+    // kicked while the server to go to refused them, the proxy keeps the client alive meanwhile
+    let mut stranded = false;
+    let mut stranded_keepalive = tokio::time::interval(queue::KEEPALIVE_INTERVAL);
+    stranded_keepalive.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        let packet = tokio::select! {
+            packet = conn.recv_raw_packet(), if !stranded => packet?,
+            Some(packet) = queue.recv() => {
+                conn.auto_send_raw_packet(packet).await?;
+                continue;
+            }
+            reason = unresponsive(context.timeouts.keepalive, last_read, &pending) => {
+                return conn.disconnect(Component::text(reason)).await;
+            }
+            _ = stranded_keepalive.tick(), if stranded => {
+                // the proxy stands in for the server the client is waiting for
+                pending.lock().unwrap().get_or_insert(Instant::now());
+                conn.send_packet(KeepAlive(rand::random())).await?;
+                last_read = Instant::now();
+                continue;
+            }
+            Some(request) = requests.recv() => {
+                let address = match request {
+                    Request::Connect(address) => address,
+                    Request::Disconnect(reason) => return conn.disconnect(reason).await,
+                };
+                if queued.as_ref().is_some_and(|waiter| waiter.server() == address) {
+                    continue;
+                }
+                // asking for another server leaves the queue
+                if queued.take().is_some() {
+                    conn.send_packet(queue::boss_bar_removed()).await?;
+                }
+                if address == connection.server && !stranded {
+                    continue;
+                }
+                match switch_server(&mut conn, address, &mut connection, &context).await {
+                    Ok(server) => {
+                        let (server, new_conn) = conn.mix(server);
+                        conn = new_conn;
+                        tx.send(server).await?;
+                        last_read = Instant::now();
+                        stranded = false;
+                    }
+                    Err(err) => match join_queue(&mut conn, address, &connection, &context).await? {
+                        Some(waiter) => {
+                            info!("Queued for {}: {:#}", waiter.server_name(), err);
+                            queued = Some(waiter);
+                        }
+                        None => warn!("Could not connect to {}: {:#}", address, err),
+                    },
+                }
+                continue;
+            }
+            Some(turn) = next_turn(&mut queued) => {
+                send_all(&mut conn, turn.boss_bars).await?;
+                let Some(waiter) = queued.as_ref().filter(|_| turn.try_server) else {
+                    continue;
+                };
+                let address = waiter.server();
+                match switch_server(&mut conn, address, &mut connection, &context).await {
+                    Ok(server) => {
+                        queued = None;
+                        conn.send_packet(queue::boss_bar_removed()).await?;
+                        let (server, new_conn) = conn.mix(server);
+                        conn = new_conn;
+                        tx.send(server).await?;
+                        last_read = Instant::now();
+                        stranded = false;
+                    }
+                    Err(err) => debug!("Still queued for {}: {:#}", waiter.server_name(), err),
+                }
+                continue;
+            }
+            _ = context.shutdown.cancelled() => {
+                return conn.disconnect(context.shutdown_message.clone()).await;
+            }
+        };
+        last_read = Instant::now();
+        if packet.id() == keepalive {
+            pending.lock().unwrap().get_or_insert(Instant::now());
+        }
+
+        let Outcome { packet, injected, replies } = context
+            .interceptors
+            .intercept(Direction::Clientbound, &connection.player, packet)
+            .await?;
+        for packet in replies {
+            to_server.send(packet).map_err(|_| anyhow!("server closed"))?;
+        }
+
+        if let Some(packet) = packet {
+            match conn.decode(packet)? {
+                PacketType::PluginMessage(packet) => {
+                    let packet = context
+                        .channels
+                        .route(
+                            packet,
+                            Direction::Clientbound,
+                            &connection.player,
+                            &context.players,
+                            &connection.channels,
+                        )
+                        .await;
+                    let Some(mut packet) = packet else {
+                        continue;
+                    };
+                    if packet.channel == "minecraft:brand" {
+                        let brand = context.hooks.server_brand(packet.data.get_string(32700)?);
+
+                        let mut bytes = BytesMut::with_capacity(brand.len());
+                        bytes.put_string(&brand);
+                        packet.data = bytes.freeze();
+                    }
+                    conn.auto_send_packet(packet).await?;
+                }
+                PacketType::Disconnect(Disconnect { reason }) => {
+                    // todo: close server connection
+                    let fallback = context.config.get().fallback_server;
+                    let result = if connection.server == fallback {
+                        KickResult::Disconnect(reason.clone())
+                    } else {
+                        KickResult::Fallback(fallback)
+                    };
+                    let event = KickedFromServerEvent {
+                        player: connection.player.clone(),
+                        server: connection.server,
+                        reason,
+                        result,
+                    };
+
+                    let fallback = match context.events.fire(event).await.result {
+                        KickResult::Fallback(fallback) => fallback,
+                        KickResult::Disconnect(reason) => return conn.disconnect(reason).await,
+                    };
+                    if queued.take().is_some() {
+                        conn.queue_packet(queue::boss_bar_removed()).await?;
+                    }
+                    match switch_server(&mut conn, fallback, &mut connection, &context).await {
+                        Ok(server) => {
+                            let (server, new_conn) = conn.mix(server);
+                            conn = new_conn;
+                            tx.send(server).await?;
+                        }
+                        Err(err) => {
+                            let waiter = join_queue(&mut conn, fallback, &connection, &context);
+                            let Some(waiter) = waiter.await? else {
+                                return Err(err);
+                            };
+                            info!("Kicked, queued for {}: {:#}", waiter.server_name(), err);
+                            queued = Some(waiter);
+                            stranded = true;
+                            connection.stranded.store(true, Ordering::Relaxed);
+                            if let Some(queue) = &context.queue {
+                                queue.freed(connection.server);
+                            }
+                        }
+                    }
+                    last_read = Instant::now();
+                }
+                PacketType::BossBar(packet) => {
+                    match packet.action {
+                        BossBarAction::Add { .. } => connection.boss_bars.push(packet.uuid),
+                        BossBarAction::Remove => {
+                            if let Some(index) =
+                                connection.boss_bars.iter().position(|&i| i == packet.uuid)
+                            {
+                                connection.boss_bars.swap_remove(index);
+                            }
+                        }
+                        _ => {}
+                    }
+                    conn.auto_send_packet(packet).await?;
+                }
+                PacketType::Raw(mut packet) => {
+                    if let Some(queue) = &context.queue {
+                        queue.remember_world(connection.player.protocol, &mut packet);
+                    }
+                    conn.auto_send_raw_packet(packet).await?;
+                }
+                _ => unreachable!("server cos wysłał"),
+            }
+        }
+
+        for packet in injected {
+            conn.auto_send_raw_packet(packet).await?;
+        }
+    }
+}
+
+/// Puts a player in game in the queue of `server`, showing them their place. `None` without
+/// queues.
+async fn join_queue<'a>(
+    client: &mut Connection,
+    server: SocketAddr,
+    connection: &ConnectionInfo,
+    context: &'a Context,
+) -> Result<Option<Waiter<'a>>> {
+    let Some(queue) = &context.queue else {
+        return Ok(None);
+    };
+    let config = context.config.get();
+    let tier = queue.tier(&*context.hooks, &config, &connection.player);
+    let ticket = queue.join(server, connection.player.uuid, tier);
+    let waiter = Waiter::new(ticket, config.server_name(server));
+    send_all(client, waiter.boss_bar()).await?;
+    Ok(Some(waiter))
+}
+
+/// The next turn of a player waiting in a queue, `None` right away when they don't.
+async fn next_turn(waiter: &mut Option<Waiter<'_>>) -> Option<queue::Turn> {
+    match waiter {
+        Some(waiter) => Some(waiter.next().await),
+        None => None,
+    }
+}
+
+/// Connects the player to `server_address` or wherever a [`ServerPreConnectEvent`] handler redirects them.
+async fn connect(
+    context: &Context,
+    player: &Player,
+    server_address: SocketAddr,
+) -> Result<(Connection, SocketAddr), ProxyError> {
+    let event = ServerPreConnectEvent {
+        player: player.clone(),
+        server: server_address,
+    };
+    let server_address = context.events.fire(event).await.server;
+
+    let server = create_backend_conn(context, server_address, player).await?;
+    Ok((server, server_address))
+}
+
+async fn create_backend_conn(
+    context: &Context,
+    server_address: SocketAddr,
+    player: &Player,
+) -> Result<Connection, ProxyError> {
+    let started = Instant::now();
+    let version = player.protocol;
+    let proxied_client = Some(player.address).filter(|_| context.backend_proxy_protocol);
+    let connecting =
+        Connection::connect_to(server_address, version, Direction::Serverbound, proxied_client);
+    let timeout = context.timeouts.login;
+    let what = || format!("connecting to {}", server_address);
+    let mut server = with_timeout(timeout, connecting, what).await?;
+    server.set_read_timeout(timeout);
+    server.set_compression_level(context.config.get().compression_level);
+    server.set_compression_passthrough(context.config.get().compression_passthrough);
+
+    server
+        .queue_packet(Handshake {
+            protocol: version.into(),
+            server_address: server_address.ip().to_string(),
+            port: server_address.port(),
+            state: NextState::Login,
+        })
+        .await?;
+
+    server.change_state(State::Login);
+    server
+        .send_packet(LoginStart {
+            username: player.username.clone(),
+            uuid: Some(player.uuid),
+        })
+        .await?;
+
+    loop {
+        return match server.auto_read().await? {
+            PacketType::EncryptionRequest(_) => {
+                Err(anyhow!("Backend server requested encryption, it has to be in offline mode").into())
+            }
+            PacketType::SetCompression(SetCompression { threshold }) => {
+                if threshold > -1 {
+                    server.enable_compression(threshold as u32);
+                }
+                continue;
+            }
+            PacketType::LoginSuccess(_) => {
+                server.change_state(State::Play);
+                METRICS.backend_connected(started.elapsed());
+                Ok(server)
+            }
+            PacketType::LoginPluginRequest(_) => {
+                Err(anyhow!("Login plugin requests are not supported").into())
+            }
+            PacketType::Disconnect(Disconnect { reason }) => {
+                server.shutdown().await?;
+                Err(ProxyError::Disconnected(reason))
+            }
+            _ => Err(anyhow!("unknown packet").into()),
+        };
+    }
+}
+
+async fn switch_server(
+    client: &mut Connection,
+    server_address: SocketAddr,
+    connection: &mut ConnectionInfo,
+    context: &Context,
+) -> Result<Connection> {
+    let (mut server, server_address) = connect(context, &connection.player, server_address).await?;
+    let join: JoinGame = server.recv_packet().await?;
+    server.set_read_timeout(None);
+    // an answer to the previous server's keepalive is not expected anymore
+    *connection.keepalive.lock().unwrap() = None;
+    connection.channels.lock().unwrap().server.clear();
+    if let Some(register) = context.channels.registration(&connection.channels) {
+        server.send_packet(register).await?;
+    }
+
+    let respawn = Respawn::from_joingame(&join);
+    client.queue_packet(join).await?;
+    client.queue_packet(respawn).await?;
+
+    for uuid in &connection.boss_bars {
+        client
+            .queue_packet(BossBar {
+                uuid: *uuid,
+                action: BossBarAction::Remove,
+            })
+            .await?;
+    }
+    connection.boss_bars.clear();
+
+    let previous = std::mem::replace(&mut connection.server, server_address);
+    context.players.set_server(&connection.player.uuid, server_address);
+    if let Some(queue) = &context.queue {
+        queue.freed(previous);
+    }
+    Span::current().record("server", context.config.get().server_name(server_address));
+    METRICS.switch();
+    let event = ServerConnectedEvent {
+        player: connection.player.clone(),
+        server: server_address,
+        previous: Some(previous),
+    };
+    context.events.fire(event).await;
+    Ok(server)
+}
+
+/// Resolves with the reason to disconnect the player for once the client left a keepalive
+/// unanswered or the backend sent nothing for `timeout`.
+async fn unresponsive(
+    timeout: Option<Duration>,
+    last_read: Instant,
+    pending: &Mutex<Option<Instant>>,
+) -> &'static str {
+    let Some(timeout) = timeout else {
+        return std::future::pending().await;
+    };
+    loop {
+        let (deadline, reason) = match *pending.lock().unwrap() {
+            Some(sent) => (sent + timeout, "Timed out"),
+            None => (last_read + timeout, "The server stopped responding"),
+        };
+        if Instant::now() >= deadline {
+            return reason;
+        }
+        // the client may have answered in the meantime, so the deadline is looked at again
+        tokio::time::sleep_until(deadline.into()).await;
+    }
+}
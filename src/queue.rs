@@ -0,0 +1,355 @@
+//! Queues for backends that are full or restarting.
+//!
+//! A player whose server refuses them, or can't be reached, waits in that server's queue instead
+//! of being disconnected or left behind. The place in the queue is shown in a boss bar, and the
+//! player at the front tries the server again every `retry`, and right away when a player leaves
+//! it, until it lets them in.
+//!
+//! Where the player waits depends on how they got there:
+//! - at login the proxy holds them in a world of its own: the client gets the join game of a
+//!   backend it saw earlier for the same version and a spectator position. Without one yet the
+//!   player is disconnected with the server's reason.
+//! - switching servers (`/send`, the API, BungeeCord `Connect`), they stay and play on the server
+//!   they are on.
+//! - kicked to a fallback that refuses them, they stay in the world they were kicked from, with
+//!   the proxy keeping the client alive.
+//!
+//! Players are ordered by tier, then by when they joined. The tier is the first of the
+//! `priorities` permissions (see [`Hooks::has_permission`]) a player has, players with none of
+//! them come last.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bytes::Buf;
+use tokio::sync::watch;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+use uuid::Uuid;
+
+use crate::component::{Color, Component};
+use crate::config::Config;
+use crate::hooks::{self, Hooks};
+use crate::protocol::codec::registry::PLAY_REG;
+use crate::protocol::packet::play::{
+    BossBar, BossBarAction, BossBarColor, BossBarDivision, JoinGame,
+};
+use crate::protocol::packet::{Packet, RawPacket};
+use crate::protocol::wrappers::Player;
+use crate::protocol::{Direction, ProtocolVersion};
+
+/// Boss bar the queue position is shown in.
+pub const BOSS_BAR: Uuid = Uuid::from_u128(0x726f_7765_722d_7175_6575_6500_0000_0001);
+
+/// How often a held client is sent a keepalive, it gives up on a silent server after 30 seconds.
+pub(crate) const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The game mode of a player held in the queue, so they float in the empty world.
+const SPECTATOR: u8 = 3;
+
+#[derive(Debug, Clone)]
+pub struct QueueSettings {
+    /// Permissions giving a better place in the queue, the first one the best.
+    pub priorities: Vec<String>,
+    /// How often the player at the front tries the server again.
+    pub retry: Duration,
+}
+
+impl Default for QueueSettings {
+    fn default() -> Self {
+        Self {
+            priorities: Vec::new(),
+            retry: Duration::from_secs(5),
+        }
+    }
+}
+
+impl QueueSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            priorities: config.queue_priorities.clone(),
+            retry: Duration::from_millis(config.queue_retry),
+        }
+    }
+}
+
+/// The queues of all servers.
+pub struct Queues {
+    pub settings: QueueSettings,
+    state: Mutex<State>,
+    /// Bumped on every change, so waiting players can look at their place again.
+    changed: watch::Sender<()>,
+}
+
+#[derive(Default)]
+struct State {
+    waiting: HashMap<SocketAddr, Vec<Entry>>,
+    joined: u64,
+    /// Times a player left each server, a slot may have opened up.
+    freed: HashMap<SocketAddr, u64>,
+    /// Join game of a backend for each version, the world players are held in.
+    worlds: HashMap<ProtocolVersion, JoinGame>,
+}
+
+struct Entry {
+    uuid: Uuid,
+    tier: usize,
+    joined: u64,
+}
+
+impl Queues {
+    pub fn new(settings: QueueSettings) -> Self {
+        Self {
+            settings,
+            state: Mutex::default(),
+            changed: watch::Sender::new(()),
+        }
+    }
+
+    /// Place of the player in the queue of `server` from 1, and the length of the queue.
+    pub fn position(&self, server: SocketAddr, uuid: &Uuid) -> Option<(usize, usize)> {
+        let state = self.state.lock().unwrap();
+        let queue = state.waiting.get(&server)?;
+        let index = queue.iter().position(|entry| entry.uuid == *uuid)?;
+        Some((index + 1, queue.len()))
+    }
+
+    /// Players waiting for `server`, in order.
+    pub fn waiting(&self, server: SocketAddr) -> Vec<Uuid> {
+        let state = self.state.lock().unwrap();
+        let queue = state.waiting.get(&server).map(Vec::as_slice).unwrap_or_default();
+        queue.iter().map(|entry| entry.uuid).collect()
+    }
+
+    /// Tier of `player`, lower is better.
+    pub(crate) fn tier(&self, hooks: &dyn Hooks, config: &Config, player: &Player) -> usize {
+        let priorities = &self.settings.priorities;
+        priorities
+            .iter()
+            .position(|permission| hooks::permitted(hooks, config, player, permission))
+            .unwrap_or(priorities.len())
+    }
+
+    /// Puts the player in the queue of `server`, they leave it when the ticket is dropped.
+    pub(crate) fn join(&self, server: SocketAddr, uuid: Uuid, tier: usize) -> Ticket<'_> {
+        let mut state = self.state.lock().unwrap();
+        state.joined += 1;
+        let entry_joined = state.joined;
+        let entry = Entry {
+            uuid,
+            tier,
+            joined: entry_joined,
+        };
+        let queue = state.waiting.entry(server).or_default();
+        let index =
+            queue.partition_point(|other| (other.tier, other.joined) < (tier, entry.joined));
+        queue.insert(index, entry);
+        drop(state);
+
+        self.changed.send_replace(());
+        Ticket {
+            queues: self,
+            server,
+            joined: entry_joined,
+        }
+    }
+
+    /// A player left `server`, the player at the front of its queue gets to try it.
+    pub(crate) fn freed(&self, server: SocketAddr) {
+        let mut state = self.state.lock().unwrap();
+        if !state.waiting.contains_key(&server) {
+            return;
+        }
+        *state.freed.entry(server).or_default() += 1;
+        drop(state);
+        self.changed.send_replace(());
+    }
+
+    pub(crate) fn times_freed(&self, server: SocketAddr) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.freed.get(&server).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<()> {
+        self.changed.subscribe()
+    }
+
+    /// Keeps the join game a backend sent in `packet`, if there is none for `version` yet.
+    pub(crate) fn remember_world(&self, version: ProtocolVersion, packet: &mut RawPacket) {
+        let registry = PLAY_REG.get_registry(Direction::Clientbound, version);
+        let join_game = registry.get_id::<JoinGame>();
+        if join_game.map_or(true, |id| *id != packet.id())
+            || self.state.lock().unwrap().worlds.contains_key(&version)
+        {
+            return;
+        }
+        if packet.decompress().is_err() {
+            return;
+        }
+        let mut data = &packet.buffer[1..];
+        if let Ok(join) = JoinGame::from_bytes(&mut data, version) {
+            if !data.has_remaining() {
+                self.state.lock().unwrap().worlds.insert(version, join);
+            }
+        }
+    }
+
+    /// The world to hold a player of `version` in, `None` while no backend was joined with it.
+    pub(crate) fn world(&self, version: ProtocolVersion) -> Option<JoinGame> {
+        let state = self.state.lock().unwrap();
+        let mut join = state.worlds.get(&version)?.clone();
+        join.gamemode = SPECTATOR;
+        join.last_death = None;
+        Some(join)
+    }
+}
+
+/// A place in a queue.
+pub(crate) struct Ticket<'a> {
+    queues: &'a Queues,
+    server: SocketAddr,
+    joined: u64,
+}
+
+impl Ticket<'_> {
+    /// Place from 1 and the length of the queue.
+    pub fn position(&self) -> (usize, usize) {
+        let state = self.queues.state.lock().unwrap();
+        let queue = &state.waiting[&self.server];
+        let index = queue.iter().position(|entry| entry.joined == self.joined);
+        (index.expect("a ticket stays in its queue") + 1, queue.len())
+    }
+
+    /// The boss bar showing the place in the queue for `server_name`, `add` it the first time.
+    pub fn boss_bar(&self, server_name: &str, add: bool) -> Vec<BossBar> {
+        let (position, length) = self.position();
+        let title = Component::text(&format!(
+            "Position {} of {} in the queue for {}",
+            position, length, server_name
+        ))
+        .color(Color::Yellow);
+        let health = 1.0 - (position - 1) as f32 / length as f32;
+
+        let actions = if add {
+            vec![BossBarAction::Add {
+                title,
+                health,
+                color: BossBarColor::Yellow,
+                division: BossBarDivision::None,
+                flags: 0,
+            }]
+        } else {
+            vec![BossBarAction::UpdateTitle(title), BossBarAction::UpdateHealth(health)]
+        };
+        actions
+            .into_iter()
+            .map(|action| BossBar {
+                uuid: BOSS_BAR,
+                action,
+            })
+            .collect()
+    }
+}
+
+impl Drop for Ticket<'_> {
+    fn drop(&mut self) {
+        let mut state = self.queues.state.lock().unwrap();
+        if let Some(queue) = state.waiting.get_mut(&self.server) {
+            queue.retain(|entry| entry.joined != self.joined);
+            if queue.is_empty() {
+                state.waiting.remove(&self.server);
+                state.freed.remove(&self.server);
+            }
+        }
+        drop(state);
+        self.queues.changed.send_replace(());
+    }
+}
+
+/// A player waiting in a queue, it tells when their place changes and when to try the server.
+pub(crate) struct Waiter<'a> {
+    ticket: Ticket<'a>,
+    server_name: String,
+    changes: watch::Receiver<()>,
+    shown: (usize, usize),
+    freed: u64,
+    retry: Interval,
+}
+
+/// What a waiting player is told next.
+pub(crate) struct Turn {
+    /// Updates of the boss bar, when their place changed.
+    pub boss_bars: Vec<BossBar>,
+    /// They are at the front and try the server now.
+    pub try_server: bool,
+}
+
+impl<'a> Waiter<'a> {
+    pub fn new(ticket: Ticket<'a>, server_name: String) -> Self {
+        let queues = ticket.queues;
+        let retry_every = queues.settings.retry;
+        let mut retry = tokio::time::interval_at(Instant::now() + retry_every, retry_every);
+        retry.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self {
+            changes: queues.subscribe(),
+            shown: ticket.position(),
+            freed: queues.times_freed(ticket.server),
+            ticket,
+            server_name,
+            retry,
+        }
+    }
+
+    pub fn server(&self) -> SocketAddr {
+        self.ticket.server
+    }
+
+    pub fn server_name(&self) -> &str {
+        &self.server_name
+    }
+
+    /// The boss bar to add when the player starts waiting.
+    pub fn boss_bar(&self) -> Vec<BossBar> {
+        self.ticket.boss_bar(&self.server_name, true)
+    }
+
+    /// Waits until the place changes or the player gets to try the server. The player at the front
+    /// tries every `retry`, and right away when they got there or a slot freed up.
+    pub async fn next(&mut self) -> Turn {
+        loop {
+            let queues = self.ticket.queues;
+            let mut try_server = tokio::select! {
+                Ok(()) = self.changes.changed() => {
+                    let times_freed = queues.times_freed(self.ticket.server);
+                    let slot_freed = times_freed != self.freed;
+                    self.freed = times_freed;
+                    slot_freed
+                }
+                _ = self.retry.tick() => true,
+            };
+            let position = self.ticket.position();
+            try_server |= self.shown.0 != 1;
+            try_server &= position.0 == 1;
+            let mut boss_bars = Vec::new();
+            if position != self.shown {
+                self.shown = position;
+                boss_bars = self.ticket.boss_bar(&self.server_name, false);
+            }
+            if try_server || !boss_bars.is_empty() {
+                return Turn {
+                    boss_bars,
+                    try_server,
+                };
+            }
+        }
+    }
+}
+
+/// Removes the boss bar of the queue, once the player got in.
+pub(crate) fn boss_bar_removed() -> BossBar {
+    BossBar {
+        uuid: BOSS_BAR,
+        action: BossBarAction::Remove,
+    }
+}
@@ -0,0 +1,231 @@
+//! Connection throttling and the anti-bot verification of logins.
+//!
+//! Connections are counted per IP and logins for the whole proxy, both over a fixed window.
+//! Verification holds a login in a limbo before any backend connection is made: the client has to
+//! answer a login plugin request the way the vanilla client does, and stay connected for
+//! `verification_delay`. An IP that passed is not asked again.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::component::Component;
+use crate::config::Config;
+use crate::protocol::codec::connection::Connection;
+use crate::protocol::packet::login::{Disconnect, LoginPluginRequest, LoginPluginResponse};
+
+/// How long verification stays on in [`Verification::Auto`] after the login limit was hit.
+const ATTACK_COOLDOWN: Duration = Duration::from_secs(60);
+/// How long a status ping counts for `require_ping`.
+const PING_MEMORY: Duration = Duration::from_secs(10 * 60);
+/// How long a verified IP is remembered.
+const VERIFIED_MEMORY: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long the client has to answer the verification request.
+const VERIFICATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+const VERIFICATION_CHANNEL: &str = "rower:verify";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Verification {
+    Off,
+    /// Every IP that was not verified yet.
+    On,
+    /// Like `On`, while the login limit is being hit.
+    Auto,
+}
+
+/// Limits of a [`Throttle`], a limit of 0 is no limit.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub window: Duration,
+    /// Connections, status pings included, one IP may open per window.
+    pub connections_per_ip: u32,
+    /// Logins the whole proxy accepts per window.
+    pub logins: u32,
+    /// Only let IPs that pinged the server list in the last minutes log in.
+    pub require_ping: bool,
+    pub verification: Verification,
+    /// How long a login is held in verification.
+    pub verification_delay: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(10),
+            connections_per_ip: 0,
+            logins: 0,
+            require_ping: false,
+            verification: Verification::Off,
+            verification_delay: Duration::ZERO,
+        }
+    }
+}
+
+impl Limits {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            window: Duration::from_secs(config.throttle_window),
+            connections_per_ip: config.connections_per_ip,
+            logins: config.logins_per_window,
+            require_ping: config.require_ping,
+            verification: config.verification,
+            verification_delay: Duration::from_millis(config.verification_delay),
+        }
+    }
+}
+
+/// A count that starts over every window.
+#[derive(Clone, Copy)]
+struct Counter {
+    start: Instant,
+    count: u32,
+}
+
+impl Counter {
+    fn new(now: Instant) -> Self {
+        Self { start: now, count: 0 }
+    }
+
+    /// Counts one more, false when that goes over `limit`.
+    fn hit(&mut self, now: Instant, window: Duration, limit: u32) -> bool {
+        if now.duration_since(self.start) >= window {
+            *self = Self::new(now);
+        }
+        self.count += 1;
+        limit == 0 || self.count <= limit
+    }
+}
+
+struct State {
+    connections: HashMap<IpAddr, Counter>,
+    logins: Counter,
+    pinged: HashMap<IpAddr, Instant>,
+    verified: HashMap<IpAddr, Instant>,
+    attack_until: Option<Instant>,
+    last_sweep: Instant,
+}
+
+pub(crate) struct Throttle {
+    limits: Limits,
+    state: Mutex<State>,
+}
+
+impl Throttle {
+    pub(crate) fn new(limits: Limits) -> Self {
+        let now = Instant::now();
+        Self {
+            limits,
+            state: Mutex::new(State {
+                connections: HashMap::new(),
+                logins: Counter::new(now),
+                pinged: HashMap::new(),
+                verified: HashMap::new(),
+                attack_until: None,
+                last_sweep: now,
+            }),
+        }
+    }
+
+    /// Counts a new connection of `ip`, false when it should be dropped.
+    pub(crate) fn connection(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        if now.duration_since(state.last_sweep) >= self.limits.window {
+            self.sweep(&mut state, now);
+        }
+        state
+            .connections
+            .entry(ip)
+            .or_insert_with(|| Counter::new(now))
+            .hit(now, self.limits.window, self.limits.connections_per_ip)
+    }
+
+    pub(crate) fn pinged(&self, ip: IpAddr) {
+        if self.limits.require_ping {
+            self.state.lock().unwrap().pinged.insert(ip, Instant::now());
+        }
+    }
+
+    /// Why a login from `ip` is refused before it gets anywhere, if it is.
+    pub(crate) fn login(&self, ip: IpAddr) -> Option<Component> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        if !state.logins.hit(now, self.limits.window, self.limits.logins) {
+            state.attack_until = Some(now + ATTACK_COOLDOWN);
+            return Some(Component::text("Too many players are joining, try again in a moment"));
+        }
+        let pinged = state.pinged.get(&ip).is_some_and(|at| now.duration_since(*at) < PING_MEMORY);
+        if self.limits.require_ping && !pinged {
+            return Some(Component::text(
+                "Add the server to your server list and refresh it before joining",
+            ));
+        }
+        None
+    }
+
+    pub(crate) fn needs_verification(&self, ip: IpAddr) -> bool {
+        let state = self.state.lock().unwrap();
+        if state.verified.contains_key(&ip) {
+            return false;
+        }
+        match self.limits.verification {
+            Verification::Off => false,
+            Verification::On => true,
+            Verification::Auto => state.attack_until.is_some_and(|until| Instant::now() < until),
+        }
+    }
+
+    /// Holds the login in the limbo, false when the client failed and should be dropped.
+    pub(crate) async fn verify(&self, client: &mut Connection, ip: IpAddr) -> Result<bool> {
+        let started = Instant::now();
+        let message_id = rand::random::<u16>() as i32;
+        client
+            .send_packet(LoginPluginRequest {
+                message_id,
+                channel: VERIFICATION_CHANNEL.to_owned(),
+                data: Bytes::new(),
+            })
+            .await?;
+
+        // the vanilla client answers right away that it does not know the channel
+        let response = tokio::time::timeout(
+            VERIFICATION_TIMEOUT,
+            client.recv_packet::<LoginPluginResponse>(),
+        )
+        .await;
+        let passed = matches!(
+            response,
+            Ok(Ok(LoginPluginResponse { message_id: id, successful: false, .. })) if id == message_id
+        );
+        if !passed {
+            let reason = Component::text("Verification failed, try again");
+            let _ = client.send_packet(Disconnect { reason }).await;
+            return Ok(false);
+        }
+
+        // a client in the limbo has nothing to send, bots tend to give up or keep talking
+        let delay = self.limits.verification_delay.saturating_sub(started.elapsed());
+        if tokio::time::timeout(delay, client.recv_raw_packet()).await.is_ok() {
+            return Ok(false);
+        }
+        self.state.lock().unwrap().verified.insert(ip, Instant::now());
+        Ok(true)
+    }
+
+    /// Forgets counters of past windows and pings or verifications that are too old.
+    fn sweep(&self, state: &mut State, now: Instant) {
+        let window = self.limits.window;
+        state.connections.retain(|_, counter| now.duration_since(counter.start) < window);
+        state.pinged.retain(|_, at| now.duration_since(*at) < PING_MEMORY);
+        state.verified.retain(|_, at| now.duration_since(*at) < VERIFIED_MEMORY);
+        state.last_sweep = now;
+    }
+}
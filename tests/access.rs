@@ -0,0 +1,159 @@
+mod support;
+
+use std::fs;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+
+use anyhow::Result;
+use bytes::Bytes;
+use rower::access::{AccessList, Ban, BanTarget};
+use rower::component::{Color, Component};
+use rower::protocol::packet::login::Disconnect;
+use rower::protocol::packet::play::{ChatCommand, SystemChat};
+use rower::protocol::wrappers::Player;
+use rower::{Hooks, ProxyBuilder};
+
+use support::{within, FakeClient, LoginResult, TestProxy, VERSION};
+
+/// Lets Steve run every command.
+struct SteveIsAdmin;
+
+impl Hooks for SteveIsAdmin {
+    fn has_permission(&self, player: &Player, _: &str) -> bool {
+        player.username == "Steve"
+    }
+}
+
+/// A path for one test's access list, without the file.
+fn access_file(test: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rower-access-{}-{}.toml", process::id(), test));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn offline_uuid(username: &str) -> uuid::Uuid {
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+    Player::new(username.to_owned(), None, address, VERSION).uuid
+}
+
+fn command(command: &str) -> ChatCommand {
+    ChatCommand {
+        command: command.to_owned(),
+        timestamp: 0,
+        salt: 0,
+        arguments: Vec::new(),
+        message_count: 0,
+        acknowledged: Bytes::from_static(&[0; 3]),
+    }
+}
+
+async fn denied(proxy: &TestProxy, username: &str) -> Result<Component> {
+    match within(FakeClient::login(proxy.addr, username)).await? {
+        LoginResult::Disconnected(reason) => Ok(reason),
+        LoginResult::Success(..) => panic!("{} was let in", username),
+    }
+}
+
+#[tokio::test]
+async fn bans_and_whitelist_from_the_file() -> Result<()> {
+    let path = access_file("file");
+    let list = AccessList {
+        whitelist: false,
+        whitelisted: vec!["Alex".to_owned()],
+        bans: vec![
+            Ban {
+                target: BanTarget::Uuid(offline_uuid("Steve")),
+                reason: Component::text("Griefing"),
+                expires: None,
+                source: "Console".to_owned(),
+            },
+            Ban {
+                target: BanTarget::Username("Herobrine".to_owned()),
+                reason: Component::text("Expired"),
+                expires: Some(1),
+                source: "Console".to_owned(),
+            },
+        ],
+    };
+    fs::write(&path, toml::to_string(&list)?)?;
+
+    let proxy = TestProxy::start_with(ProxyBuilder::new().access_list(&path)).await?;
+    assert_eq!(proxy.proxy.access().list(), list);
+
+    assert_eq!(denied(&proxy, "Steve").await?, Component::text("Griefing"));
+    // expired bans don't count
+    proxy.join("Herobrine").await?;
+
+    // the file is watched, a whitelist and an IP ban apply without a restart
+    let mut list = list;
+    list.whitelist = true;
+    fs::write(&path, toml::to_string(&list)?)?;
+    within(async {
+        while !proxy.proxy.access().list().whitelist {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+    denied(&proxy, "Notch").await?;
+    proxy.join("Alex").await?;
+
+    list.bans.push(Ban {
+        target: BanTarget::parse("127.0.0.0/8"),
+        reason: Component::text("Local network"),
+        expires: None,
+        source: "Console".to_owned(),
+    });
+    fs::write(&path, toml::to_string(&list)?)?;
+    within(async {
+        while proxy.proxy.access().list().bans.len() < 3 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+    assert_eq!(denied(&proxy, "Alex").await?, Component::text("Local network"));
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn ban_commands() -> Result<()> {
+    let path = access_file("commands");
+    let builder = ProxyBuilder::new().hooks(SteveIsAdmin).access_list(&path);
+    let proxy = TestProxy::start_with(builder).await?;
+    let (mut steve, mut steve_session) = proxy.join("Steve").await?;
+    let (mut alex, mut alex_session) = proxy.join("Alex").await?;
+
+    // commands without the permission go to the backend
+    alex.send(command("ban Steve")).await?;
+    let forwarded: ChatCommand = within(alex_session.expect()).await?;
+    assert_eq!(forwarded.command, "ban Steve");
+
+    steve.send(command("ban Alex 2h &cGriefing")).await?;
+    let feedback: SystemChat = within(steve.expect()).await?;
+    let banned = Component::text("Banned Alex for 2 hours").color(Color::Green);
+    assert_eq!(feedback.content, banned);
+    let kick: Disconnect = within(alex.expect()).await?;
+    assert_eq!(kick.reason, denied(&proxy, "Alex").await?);
+
+    let list: AccessList = toml::from_str(&fs::read_to_string(&path)?)?;
+    assert_eq!(list.bans.len(), 1);
+    assert_eq!(list.bans[0].target, BanTarget::Username("Alex".to_owned()));
+    assert_eq!(list.bans[0].source, "Steve");
+
+    steve.send(command("unban alex")).await?;
+    within(steve.expect::<SystemChat>()).await?;
+    steve.send(command("whitelist on")).await?;
+    within(steve.expect::<SystemChat>()).await?;
+    assert_eq!(
+        denied(&proxy, "Alex").await?,
+        Component::text("You are not whitelisted on this server")
+    );
+
+    // none of the proxy commands reached the backend
+    steve.send_raw(0x12, &[1]).await?;
+    assert_eq!(within(steve_session.recv_raw()).await?.id(), 0x12);
+
+    proxy.proxy.shutdown().await
+}
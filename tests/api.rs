@@ -0,0 +1,215 @@
+mod support;
+
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use rower::access::BanTarget;
+use rower::component::{Color, Component};
+use rower::protocol::packet::login::Disconnect;
+use rower::protocol::packet::play::{ChatCommand, SystemChat};
+use rower::ProxyBuilder;
+use bytes::Bytes;
+use serde_json::{json, Value};
+
+use support::{within, Setup, TestProxy};
+
+const TOKEN: &str = "secret";
+
+struct Api {
+    client: Client,
+    base: String,
+}
+
+impl Api {
+    fn new(proxy: &TestProxy) -> Self {
+        let address = proxy.proxy.api_addr().expect("the API is not enabled");
+        Self {
+            client: Client::new(),
+            base: format!("http://{}", address),
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client.request(method, format!("{}{}", self.base, path)).bearer_auth(TOKEN)
+    }
+}
+
+#[tokio::test]
+async fn requests_need_the_token() -> Result<()> {
+    let setup = Setup { api_token: Some(TOKEN), ..Default::default() };
+    let proxy = TestProxy::start_setup(setup).await?;
+    let api = Api::new(&proxy);
+
+    let missing = api.client.get(format!("{}/players", api.base)).send().await?;
+    assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+    let wrong = api.client.get(format!("{}/players", api.base)).bearer_auth("guess").send().await?;
+    assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+    let players = api.request(Method::GET, "/players").send().await?;
+    assert_eq!(players.json::<Value>().await?, json!([]));
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn players_messages_and_bans() -> Result<()> {
+    let setup = Setup { api_token: Some(TOKEN), ..Default::default() };
+    let proxy = TestProxy::start_setup(setup).await?;
+    let api = Api::new(&proxy);
+    let (mut steve, _steve_session) = proxy.join("Steve").await?;
+    let (mut alex, _alex_session) = proxy.join("Alex").await?;
+
+    let players: Value = api.request(Method::GET, "/players").send().await?.json().await?;
+    assert_eq!(players[0]["username"], "Alex");
+    assert_eq!(players[1]["username"], "Steve");
+    assert_eq!(players[1]["server"], "backend");
+
+    let message = Component::text("Restart in 5 minutes").color(Color::Gold);
+    let sent: Value = api
+        .request(Method::POST, "/broadcast")
+        .json(&json!({ "message": message }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    assert_eq!(sent, json!({ "players": 2 }));
+    assert_eq!(within(steve.expect::<SystemChat>()).await?.content, message);
+    assert_eq!(within(alex.expect::<SystemChat>()).await?.content, message);
+
+    let kick = api
+        .request(Method::POST, "/players/steve/kick")
+        .json(&json!({ "reason": { "text": "Bye" } }))
+        .send()
+        .await?;
+    assert_eq!(kick.status(), StatusCode::NO_CONTENT);
+    assert_eq!(within(steve.expect::<Disconnect>()).await?.reason, Component::text("Bye"));
+
+    let ban = api
+        .request(Method::POST, "/bans")
+        .json(&json!({ "target": "Alex", "duration": "2h" }))
+        .send()
+        .await?;
+    assert_eq!(ban.status(), StatusCode::CREATED);
+    within(alex.expect::<Disconnect>()).await?;
+    let bans = proxy.proxy.access().list().bans;
+    assert_eq!(bans[0].target, BanTarget::Username("Alex".to_owned()));
+    assert_eq!(bans[0].source, "API");
+
+    let unban = api.request(Method::DELETE, "/bans/alex").send().await?;
+    assert_eq!(unban.status(), StatusCode::NO_CONTENT);
+    let again = api.request(Method::DELETE, "/bans/alex").send().await?;
+    assert_eq!(again.status(), StatusCode::NOT_FOUND);
+    assert_eq!(again.json::<Value>().await?, json!({ "error": "alex is not banned" }));
+
+    let nobody = api.request(Method::POST, "/players/Notch/kick").json(&json!({})).send().await?;
+    assert_eq!(nobody.status(), StatusCode::NOT_FOUND);
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn server_health() -> Result<()> {
+    let setup = Setup { api_token: Some(TOKEN), ..Default::default() };
+    let proxy = TestProxy::start_setup(setup).await?;
+    let api = Api::new(&proxy);
+    let (_steve, _session) = proxy.join("Steve").await?;
+
+    // the fake backends only speak login, so they don't answer the pings
+    let request = async { api.request(Method::GET, "/servers").send().await?.json::<Value>().await };
+    let (servers, _, _) = within(async {
+        tokio::join!(request, proxy.backend.accept(), proxy.fallback.accept())
+    })
+    .await;
+    let servers = servers?;
+    assert_eq!(servers[0]["name"], "backend");
+    assert_eq!(servers[0]["players"], 1);
+    assert_eq!(servers[0]["online"], false);
+    assert_eq!(servers[1]["name"], "fallback");
+    assert_eq!(servers[1]["players"], 0);
+
+    proxy.proxy.shutdown().await
+}
+
+/// The value of the sample `name` (labels included) in a Prometheus scrape.
+fn sample(metrics: &str, name: &str) -> f64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+        .unwrap_or_else(|| panic!("{} is missing", name))
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn metrics() -> Result<()> {
+    let setup = Setup { api_token: Some(TOKEN), ..Default::default() };
+    let proxy = TestProxy::start_setup(setup).await?;
+    let api = Api::new(&proxy);
+    let (mut steve, mut session) = proxy.join("Steve").await?;
+
+    // long enough to be compressed on the way to the backend
+    let command = ChatCommand {
+        command: format!("say {}", "a".repeat(200)),
+        timestamp: 0,
+        salt: 0,
+        arguments: Vec::new(),
+        message_count: 0,
+        acknowledged: Bytes::from_static(&[0; 3]),
+    };
+    steve.send(command.clone()).await?;
+    assert_eq!(within(session.expect::<ChatCommand>()).await?, command);
+
+    let response = api.request(Method::GET, "/metrics").send().await?;
+    assert!(response.headers()["content-type"].to_str()?.starts_with("text/plain"));
+    let metrics = response.text().await?;
+
+    // the counters are shared by every proxy in this process, so only lower bounds hold
+    assert!(sample(&metrics, "rower_connections_total") >= 1.0);
+    assert!(sample(&metrics, "rower_logins_total{outcome=\"success\"}") >= 1.0);
+    assert_eq!(sample(&metrics, "rower_players{server=\"backend\"}"), 1.0);
+    assert_eq!(sample(&metrics, "rower_players{server=\"fallback\"}"), 0.0);
+    assert!(sample(&metrics, "rower_packets_total{direction=\"serverbound\"}") >= 3.0);
+    assert!(sample(&metrics, "rower_bytes_total{direction=\"serverbound\"}") > 0.0);
+    assert!(sample(&metrics, "rower_compression_ratio{codec=\"compressor\"}") > 1.0);
+    assert!(sample(&metrics, "rower_codec_seconds_total{codec=\"decompressor\"}") > 0.0);
+    assert!(sample(&metrics, "rower_backend_connect_seconds_count") >= 1.0);
+    assert_eq!(
+        sample(&metrics, "rower_backend_connect_seconds_bucket{le=\"+Inf\"}"),
+        sample(&metrics, "rower_backend_connect_seconds_count")
+    );
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn reload_reads_the_config_again() -> Result<()> {
+    // the backends are only bound once the proxy starts
+    let servers = Arc::new(Mutex::new(None));
+    let loaded = servers.clone();
+    let builder = ProxyBuilder::new()
+        .api((Ipv4Addr::LOCALHOST, 0).into(), TOKEN)
+        .config_loader(move || {
+            let (backend, fallback) = loaded.lock().unwrap().expect("loaded before the start");
+            let mut config = support::config(backend, fallback);
+            config.servers.insert("lobby".to_owned(), backend);
+            Ok(config)
+        });
+    let proxy = TestProxy::start_with(builder).await?;
+    *servers.lock().unwrap() = Some((proxy.backend.addr(), proxy.fallback.addr()));
+    let api = Api::new(&proxy);
+    let (_steve, _session) = proxy.join("Steve").await?;
+
+    let players: Value = api.request(Method::GET, "/players").send().await?.json().await?;
+    assert_eq!(players[0]["server"], "backend");
+
+    let reloaded: Value = api.request(Method::POST, "/reload").send().await?.json().await?;
+    assert_eq!(reloaded, json!({ "config": true, "access_list": false }));
+    let players: Value = api.request(Method::GET, "/players").send().await?.json().await?;
+    assert_eq!(players[0]["server"], "lobby");
+
+    let reloaded: Value = api.request(Method::POST, "/reload").send().await?.json().await?;
+    assert_eq!(reloaded, json!({ "config": false, "access_list": false }));
+
+    proxy.proxy.shutdown().await
+}
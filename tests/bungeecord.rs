@@ -0,0 +1,189 @@
+mod support;
+
+use anyhow::Result;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use rower::bungeecord::CHANNEL;
+use rower::channels::REGISTER;
+use rower::component::Component;
+use rower::protocol::packet::play::{JoinGame, PluginMessage, Respawn, SystemChat};
+use rower::ProxyBuilder;
+
+use support::{join_game, within, BackendSession, FakeClient, TestProxy};
+
+/// Writes strings the way `DataOutput.writeUTF` does.
+fn message(parts: &[&str]) -> PluginMessage {
+    let mut data = BytesMut::new();
+    for part in parts {
+        data.put_u16(part.len() as u16);
+        data.put_slice(part.as_bytes());
+    }
+    PluginMessage {
+        channel: CHANNEL.to_owned(),
+        data: data.freeze(),
+    }
+}
+
+fn get_utf(data: &mut Bytes) -> String {
+    let len = data.get_u16() as usize;
+    String::from_utf8(data.split_to(len).to_vec()).unwrap()
+}
+
+async fn start() -> Result<TestProxy> {
+    TestProxy::start_with(ProxyBuilder::new().bungeecord()).await
+}
+
+/// Joins and skips the registration of the BungeeCord channel.
+async fn join(proxy: &TestProxy, username: &str) -> Result<(FakeClient, BackendSession)> {
+    let (client, mut session) = proxy.join(username).await?;
+    let register: PluginMessage = within(session.expect()).await?;
+    assert_eq!(register.channel, REGISTER);
+    assert_eq!(register.data, Bytes::from_static(CHANNEL.as_bytes()));
+    Ok((client, session))
+}
+
+async fn request(session: &mut BackendSession, parts: &[&str]) -> Result<Bytes> {
+    session.send(message(parts)).await?;
+    let reply: PluginMessage = within(session.expect()).await?;
+    assert_eq!(reply.channel, CHANNEL);
+    Ok(reply.data)
+}
+
+#[tokio::test]
+async fn server_and_player_queries() -> Result<()> {
+    let proxy = start().await?;
+    let (_steve, mut session) = join(&proxy, "Steve").await?;
+    let (_alex, _alex_session) = join(&proxy, "Alex").await?;
+
+    let mut reply = request(&mut session, &["GetServers"]).await?;
+    assert_eq!(get_utf(&mut reply), "GetServers");
+    assert_eq!(get_utf(&mut reply), "backend, fallback");
+
+    let mut reply = request(&mut session, &["PlayerCount", "backend"]).await?;
+    assert_eq!(get_utf(&mut reply), "PlayerCount");
+    assert_eq!(get_utf(&mut reply), "backend");
+    assert_eq!(reply.get_i32(), 2);
+
+    let mut reply = request(&mut session, &["PlayerList", "ALL"]).await?;
+    assert_eq!(get_utf(&mut reply), "PlayerList");
+    assert_eq!(get_utf(&mut reply), "ALL");
+    assert_eq!(get_utf(&mut reply), "Alex, Steve");
+
+    let alex = proxy.proxy.players().find("alex").unwrap();
+    let mut reply = request(&mut session, &["UUIDOther", "alex"]).await?;
+    assert_eq!(get_utf(&mut reply), "UUIDOther");
+    assert_eq!(get_utf(&mut reply), "Alex");
+    assert_eq!(get_utf(&mut reply), alex.player.uuid.simple().to_string());
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn connect_and_forward() -> Result<()> {
+    let proxy = start().await?;
+    let (mut steve, mut steve_session) = join(&proxy, "Steve").await?;
+    let (_alex, mut alex_session) = join(&proxy, "Alex").await?;
+
+    steve_session.send(message(&["Connect", "fallback"])).await?;
+    let mut fallback = within(proxy.fallback.accept()).await?;
+    assert_eq!(fallback.login_start.username, "Steve");
+    fallback.join(join_game(2, "minecraft:fallback")).await?;
+    let register: PluginMessage = within(fallback.expect()).await?;
+    assert_eq!(register.channel, REGISTER);
+
+    // the switch packets are queued, whatever the fallback sends next flushes them
+    let hello = PluginMessage {
+        channel: "lobby:hello".to_owned(),
+        data: Bytes::new(),
+    };
+    fallback.send(hello).await?;
+    let join: JoinGame = within(steve.expect()).await?;
+    assert_eq!(join.entity_id, 2);
+    within(steve.expect::<Respawn>()).await?;
+    within(steve.expect::<PluginMessage>()).await?;
+
+    let mut reply = request(&mut fallback, &["GetServer"]).await?;
+    assert_eq!(get_utf(&mut reply), "GetServer");
+    assert_eq!(get_utf(&mut reply), "fallback");
+
+    // Alex's server reaches the fallback through Steve, but not itself
+    let mut forward = message(&["Forward", "ALL", "party"]);
+    let mut data = BytesMut::from(&forward.data[..]);
+    data.put_u16(3);
+    data.put_slice(b"hey");
+    forward.data = data.freeze();
+    alex_session.send(forward).await?;
+
+    let mut forwarded = within(fallback.expect::<PluginMessage>()).await?.data;
+    assert_eq!(get_utf(&mut forwarded), "party");
+    assert_eq!(forwarded.get_u16(), 3);
+    assert_eq!(&forwarded[..], b"hey");
+
+    let mut reply = request(&mut alex_session, &["PlayerCount", "fallback"]).await?;
+    get_utf(&mut reply);
+    get_utf(&mut reply);
+    assert_eq!(reply.get_i32(), 1);
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn messages_and_client_requests() -> Result<()> {
+    let proxy = start().await?;
+    let (mut client, mut session) = join(&proxy, "Steve").await?;
+
+    // clients can't talk to the proxy on this channel, and the message is not forwarded either
+    client.send(message(&["Message", "ALL", "&cfake"])).await?;
+    session.send(message(&["Message", "steve", "&aWelcome"])).await?;
+
+    let chat: SystemChat = within(client.expect()).await?;
+    assert_eq!(chat.content, Component::legacy("&aWelcome"));
+    assert!(!chat.overlay);
+
+    client.send_raw(0x12, &[1]).await?;
+    assert_eq!(within(session.recv_raw()).await?.id(), 0x12);
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn strings_are_java_modified_utf8() -> Result<()> {
+    let proxy = start().await?;
+    let (mut client, mut session) = join(&proxy, "Steve").await?;
+
+    // "x\0😀" as writeUTF puts it: NUL in two bytes, the emoji as two surrogates of three bytes
+    let text: &[u8] = b"x\xc0\x80\xed\xa0\xbd\xed\xb8\x80";
+    let raw = |parts: &[&[u8]]| {
+        let mut data = BytesMut::new();
+        for part in parts {
+            data.put_u16(part.len() as u16);
+            data.put_slice(part);
+        }
+        PluginMessage {
+            channel: CHANNEL.to_owned(),
+            data: data.freeze(),
+        }
+    };
+
+    session.send(raw(&[b"Message", b"Steve", text])).await?;
+    let chat: SystemChat = within(client.expect()).await?;
+    assert_eq!(chat.content, Component::legacy("x\0😀"));
+
+    // forwarded to the sender's own server, the subchannel is written back the same way
+    let mut forward = raw(&[b"Forward", b"backend", text]);
+    let mut data = BytesMut::from(&forward.data[..]);
+    data.put_u16(0);
+    forward.data = data.freeze();
+    session.send(forward).await?;
+    let forwarded = within(session.expect::<PluginMessage>()).await?.data;
+    assert_eq!(&forwarded[..2], &[0, text.len() as u8]);
+    assert_eq!(&forwarded[2..2 + text.len()], text);
+
+    // a lone surrogate or a raw NUL is not something writeUTF produces
+    session.send(raw(&[b"Message", b"Steve", b"\xed\xa0\xbd"])).await?;
+    session.send(raw(&[b"Message", b"Steve", b"\0"])).await?;
+    session.send(message(&["Message", "Steve", "done"])).await?;
+    let chat: SystemChat = within(client.expect()).await?;
+    assert_eq!(chat.content, Component::legacy("done"));
+
+    proxy.proxy.shutdown().await
+}
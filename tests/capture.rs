@@ -0,0 +1,349 @@
+mod support;
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use anyhow::{ensure, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use rower::access::{AccessList, Ban, BanTarget};
+use rower::capture::{self, Capture, Record, Records};
+use rower::component::Component;
+use rower::inspect::{self, Filter};
+use rower::protocol::codec::registry::{LOGIN_REG, PLAY_REG};
+use rower::protocol::packet::login::{LoginSuccess, SetCompression};
+use rower::protocol::nbt::{Compound, Tag};
+use rower::protocol::buffer::BufMutExt;
+use rower::protocol::packet::play::{
+    BossBar, BossBarAction, ChatCommand, ChatMessage, JoinGame, PluginMessage, SystemChat,
+};
+use rower::protocol::packet::{Packet, RawPacket};
+use rower::protocol::wrappers::Player;
+use rower::protocol::{Direction, State};
+use rower::ProxyBuilder;
+use uuid::Uuid;
+
+use support::{join_game, within, FakeClient, LoginResult, TestProxy, VERSION};
+
+fn dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rower-captures-{}-{}", process::id(), test));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn command(command: &str) -> ChatCommand {
+    ChatCommand {
+        command: command.to_owned(),
+        timestamp: 0,
+        salt: 0,
+        arguments: Vec::new(),
+        message_count: 0,
+        acknowledged: Bytes::from_static(&[0; 3]),
+    }
+}
+
+fn is<T: Packet + 'static>(record: &Record, direction: Direction, state: State) -> bool {
+    let registry = match state {
+        State::Login => LOGIN_REG.get_registry(direction, VERSION),
+        _ => PLAY_REG.get_registry(direction, VERSION),
+    };
+    record.direction == direction
+        && record.state == state
+        && registry.get_id::<T>().is_ok_and(|id| *id == record.id())
+}
+
+#[tokio::test]
+async fn captures_a_player_and_replays_cleanly() -> Result<()> {
+    let dir = dir("player");
+    let proxy =
+        TestProxy::start_with(ProxyBuilder::new().capture(&dir, vec!["steve".to_owned()])).await?;
+    let (mut steve, mut steve_session) = proxy.join("Steve").await?;
+    let (_alex, _alex_session) = proxy.join("Alex").await?;
+
+    // long enough to be sent compressed
+    let long = command(&format!("say {}", "a".repeat(200)));
+    steve.send(long.clone()).await?;
+    assert_eq!(within(steve_session.expect::<ChatCommand>()).await?, long);
+    // the files are complete once the connections are closed
+    proxy.proxy.shutdown().await?;
+
+    let files = std::fs::read_dir(&dir)?.collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(files.len(), 1, "only Steve is captured");
+    let name = files[0].file_name().into_string().unwrap();
+    assert!(
+        name.starts_with("Steve-") && name.ends_with(".rwcap"),
+        "{}",
+        name
+    );
+
+    let records = Records::open(files[0].path())?.collect::<Result<Vec<_>>>()?;
+    let position = |check: &dyn Fn(&Record) -> bool| records.iter().position(check).unwrap();
+    let compression = position(&|r| is::<SetCompression>(r, Direction::Clientbound, State::Login));
+    let success = position(&|r| is::<LoginSuccess>(r, Direction::Clientbound, State::Login));
+    let join = position(&|r| is::<JoinGame>(r, Direction::Clientbound, State::Play));
+    let chat = position(&|r| is::<ChatCommand>(r, Direction::Serverbound, State::Play));
+    assert!(compression < success && success < join && join < chat);
+    assert!(records.windows(2).all(|pair| pair[0].time <= pair[1].time));
+
+    assert!(capture::replay(records).is_empty());
+    Ok(())
+}
+
+fn brand(brand: &str) -> PluginMessage {
+    let mut data = BytesMut::new();
+    data.put_string(brand);
+    PluginMessage {
+        channel: "minecraft:brand".to_owned(),
+        data: data.freeze(),
+    }
+}
+
+/// Records a proxied session and turns it into the `session-*` seeds of the `decoder` fuzz
+/// target with `rower-replay --seeds`, the way captures from a real server would be.
+#[tokio::test]
+#[ignore = "rewrites fuzz/corpus/decoder/session-*, run with `--ignored` to refresh them"]
+async fn record_decoder_seeds() -> Result<()> {
+    let dir = dir("seeds");
+    let proxy = TestProxy::start_with(ProxyBuilder::new().capture(&dir, Vec::new())).await?;
+    let (mut client, mut session) = proxy.join("Steve").await?;
+
+    client.send(brand("fabric")).await?;
+    within(session.expect::<PluginMessage>()).await?;
+    client
+        .send(PluginMessage {
+            channel: "minecraft:register".to_owned(),
+            data: Bytes::from_static(b"fabric:container/open\0fabric:registry/sync"),
+        })
+        .await?;
+    within(session.expect::<PluginMessage>()).await?;
+    session.send(brand("Paper")).await?;
+    within(client.expect::<PluginMessage>()).await?;
+    session
+        .send(SystemChat {
+            content: Component::text("Welcome ").push(Component::text("Steve").bold(true)),
+            overlay: false,
+        })
+        .await?;
+    within(client.expect::<SystemChat>()).await?;
+    session
+        .send(BossBar {
+            uuid: Uuid::from_u128(1),
+            action: BossBarAction::Remove,
+        })
+        .await?;
+    within(client.expect::<BossBar>()).await?;
+    client
+        .send(ChatMessage {
+            message: "hello".to_owned(),
+            timestamp: 1767225600000,
+            salt: 42,
+            signature: None,
+            message_count: 0,
+            acknowledged: Bytes::from_static(&[0; 3]),
+        })
+        .await?;
+    within(session.expect::<ChatMessage>()).await?;
+    // long enough to be sent compressed
+    let long = command(&format!("say {}", "a".repeat(200)));
+    client.send(long).await?;
+    within(session.expect::<ChatCommand>()).await?;
+    proxy.proxy.shutdown().await?;
+
+    let files = std::fs::read_dir(&dir)?.collect::<Result<Vec<_>, _>>()?;
+    let capture = dir.join("session.rwcap");
+    std::fs::rename(files[0].path(), &capture)?;
+
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus/decoder");
+    for seed in std::fs::read_dir(&corpus)? {
+        let seed = seed?;
+        if seed.file_name().to_string_lossy().starts_with("session-") {
+            std::fs::remove_file(seed.path())?;
+        }
+    }
+    let status = Command::new(env!("CARGO_BIN_EXE_rower-replay"))
+        .arg(&capture)
+        .arg("--seeds")
+        .arg(&corpus)
+        .status()?;
+    ensure!(status.success(), "the recorded session did not replay cleanly");
+    Ok(())
+}
+
+#[tokio::test]
+async fn hostile_usernames_stay_in_the_directory() -> Result<()> {
+    let dir = dir("hostile");
+    let access = dir.with_extension("toml");
+    let list = AccessList {
+        bans: vec![Ban {
+            target: BanTarget::Username("Notch".to_owned()),
+            reason: Component::text("Banned"),
+            expires: None,
+            source: "Console".to_owned(),
+        }],
+        ..AccessList::default()
+    };
+    std::fs::write(&access, toml::to_string(&list)?)?;
+    let builder = ProxyBuilder::new().capture(&dir, Vec::new()).access_list(&access);
+    let proxy = TestProxy::start_with(builder).await?;
+
+    let hostile = "../../escaped";
+    let (_client, _session) = proxy.join(hostile).await?;
+    // refused logins don't get a file
+    match within(FakeClient::login(proxy.addr, "Notch")).await? {
+        LoginResult::Disconnected(_) => {}
+        LoginResult::Success(..) => panic!("Notch was let in"),
+    }
+    proxy.proxy.shutdown().await?;
+
+    let files = std::fs::read_dir(&dir)?.collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(files.len(), 1);
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+    let uuid = Player::new(hostile.to_owned(), None, address, VERSION).uuid;
+    let name = files[0].file_name().into_string().unwrap();
+    assert!(name.starts_with(&format!("{}-", uuid)), "{}", name);
+    let mut escaped = dir.join(hostile).parent().unwrap().read_dir()?.filter_map(Result::ok);
+    assert!(escaped.all(|file| !file.file_name().to_string_lossy().starts_with("escaped-")));
+    Ok(())
+}
+
+#[test]
+fn replay_reports_packets_that_do_not_decode() -> Result<()> {
+    let dir = dir("replay");
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("broken.rwcap");
+
+    let id = *PLAY_REG
+        .get_registry(Direction::Clientbound, VERSION)
+        .get_id::<BossBar>()?;
+    let remove = || BossBar {
+        uuid: Uuid::from_u128(1),
+        action: BossBarAction::Remove,
+    };
+    let capture = Capture::create(&path)?;
+    let good = RawPacket::encode(remove(), id, VERSION);
+    capture.record(Direction::Clientbound, State::Play, VERSION, &good);
+    // a byte more than the packet has fields for
+    let mut bad = RawPacket::encode(remove(), id, VERSION);
+    bad.buffer.put_u8(0);
+    capture.record(Direction::Clientbound, State::Play, VERSION, &bad);
+    // a packet the proxy does not decode goes through as it is
+    let unknown = RawPacket::from_buffer(BytesMut::from(&[0x7f, 1, 2, 3][..]));
+    capture.record(Direction::Clientbound, State::Play, VERSION, &unknown);
+    drop(capture);
+
+    let records = Records::open(&path)?.collect::<Result<Vec<_>>>()?;
+    assert_eq!(records.len(), 3);
+    let failures = capture::replay(records);
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].index, 1);
+    assert_eq!(
+        failures[0].error.to_string(),
+        "Packet was not been fully read"
+    );
+    Ok(())
+}
+
+#[test]
+fn inspect_shows_names_and_fields() -> Result<()> {
+    let dir = dir("inspect");
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("inspect.rwcap");
+
+    let clientbound = PLAY_REG.get_registry(Direction::Clientbound, VERSION);
+    let serverbound = PLAY_REG.get_registry(Direction::Serverbound, VERSION);
+    let mut join = join_game(1, "minecraft:overworld");
+    join.registry = Compound::new(
+        "ignored".to_owned(),
+        vec![
+            ("name".to_owned(), Tag::String("say \"hi\"".to_owned())),
+            ("minecraft:biome".to_owned(), Tag::List(vec![Tag::Int(1), Tag::Int(2)])),
+            ("scale".to_owned(), Tag::Double(0.5)),
+            ("ids".to_owned(), Tag::LongArray(vec![3, 4])),
+            ("nested".to_owned(), Tag::Compound(vec![("flag".to_owned(), Tag::Byte(1))])),
+        ],
+    );
+    let chat = SystemChat {
+        content: Component::text("Hello ").push(Component::text("world").bold(true)),
+        overlay: false,
+    };
+
+    let capture = Capture::create(&path)?;
+    let record = |direction, packet: RawPacket| {
+        capture.record(direction, State::Play, VERSION, &packet)
+    };
+    record(
+        Direction::Clientbound,
+        RawPacket::encode(join, *clientbound.get_id::<JoinGame>()?, VERSION),
+    );
+    record(
+        Direction::Clientbound,
+        RawPacket::encode(chat, *clientbound.get_id::<SystemChat>()?, VERSION),
+    );
+    record(
+        Direction::Serverbound,
+        RawPacket::encode(command("spawn"), *serverbound.get_id::<ChatCommand>()?, VERSION),
+    );
+    record(
+        Direction::Clientbound,
+        RawPacket::from_buffer(BytesMut::from(&[0x7f, 1, 2, 3][..])),
+    );
+    drop(capture);
+
+    let records = Records::open(&path)?.collect::<Result<Vec<_>>>()?;
+    let described = records
+        .iter()
+        .enumerate()
+        .map(|(index, record)| inspect::describe(index, record))
+        .collect::<Vec<_>>();
+
+    assert!(
+        described[0].starts_with("#0 ")
+            && described[0].contains("Clientbound Play 0x28 JoinGame\n"),
+        "{}",
+        described[0]
+    );
+    assert!(described[0].contains("\n    entity_id: 1\n"), "{}", described[0]);
+    assert!(
+        described[0].contains(
+            "\n    registry: {name:\"say \\\"hi\\\"\",\"minecraft:biome\":[1,2],scale:0.5d,ids:[L;3L,4L],nested:{flag:1b}}\n"
+        ),
+        "{}",
+        described[0]
+    );
+    assert!(
+        described[1].contains("SystemChat\n    content: \"Hello world\""),
+        "{}",
+        described[1]
+    );
+    assert!(described[2].contains("Serverbound Play"), "{}", described[2]);
+    assert!(described[2].contains("\n    command: \"spawn\""), "{}", described[2]);
+    assert!(described[3].ends_with("0x7f unknown (3 bytes)"), "{}", described[3]);
+
+    let shown = |filter: Filter| {
+        records
+            .iter()
+            .filter(|record| filter.matches(record))
+            .filter_map(inspect::name)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        shown(Filter {
+            direction: Some(Direction::Clientbound),
+            ..Filter::default()
+        }),
+        ["JoinGame", "SystemChat"]
+    );
+    assert_eq!(
+        shown(Filter {
+            packets: vec!["chatcommand".to_owned(), "SystemChat".to_owned()],
+            ..Filter::default()
+        }),
+        ["SystemChat", "ChatCommand"]
+    );
+    assert!(shown(Filter {
+        state: Some(State::Login),
+        ..Filter::default()
+    })
+    .is_empty());
+    Ok(())
+}
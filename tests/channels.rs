@@ -0,0 +1,97 @@
+mod support;
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use bytes::Bytes;
+use rower::channels::{ChannelMessage, REGISTER, UNREGISTER};
+use rower::component::Component;
+use rower::protocol::packet::login::Disconnect;
+use rower::protocol::packet::play::PluginMessage;
+use rower::protocol::Direction;
+use rower::ProxyBuilder;
+
+use support::{join_game, within, TestProxy};
+
+fn message(channel: &str, data: &'static [u8]) -> PluginMessage {
+    PluginMessage {
+        channel: channel.to_owned(),
+        data: Bytes::from_static(data),
+    }
+}
+
+#[tokio::test]
+async fn registrations_follow_the_player() -> Result<()> {
+    let proxy = TestProxy::start().await?;
+    let (mut client, mut session) = proxy.join("Steve").await?;
+
+    client.send(message(REGISTER, b"shop:buy\0shop:sell\0party:invite")).await?;
+    client.send(message(UNREGISTER, b"party:invite")).await?;
+    let register: PluginMessage = within(session.expect()).await?;
+    assert_eq!(register.data, Bytes::from_static(b"shop:buy\0shop:sell\0party:invite"));
+    within(session.expect::<PluginMessage>()).await?;
+
+    session.send(Disconnect { reason: Component::text("Restarting") }).await?;
+    let mut fallback = within(proxy.fallback.accept()).await?;
+    fallback.join(join_game(2, "minecraft:fallback")).await?;
+
+    // the new backend learns what the client registered on the previous one
+    let register: PluginMessage = within(fallback.expect()).await?;
+    assert_eq!(register.channel, REGISTER);
+    assert_eq!(register.data, Bytes::from_static(b"shop:buy\0shop:sell"));
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn proxy_channels_do_not_leak() -> Result<()> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let tx = Arc::new(Mutex::new(tx));
+    let builder = ProxyBuilder::new().channel("rower:ping", move |message: ChannelMessage| {
+        let tx = tx.clone();
+        async move {
+            if message.direction == Direction::Serverbound {
+                let pong = PluginMessage {
+                    channel: "rower:pong".to_owned(),
+                    data: message.data.clone(),
+                };
+                message.player.send_to_client(pong).unwrap();
+            }
+            tx.lock().unwrap().send((message.direction, message.data)).unwrap();
+        }
+    });
+    let proxy = TestProxy::start_with(builder).await?;
+    let (mut client, mut session) = proxy.join("Steve").await?;
+
+    // the backend is told about the proxy channel right away
+    let register: PluginMessage = within(session.expect()).await?;
+    assert_eq!(register.channel, REGISTER);
+    assert_eq!(register.data, Bytes::from_static(b"rower:ping"));
+
+    client.send(message("rower:ping", b"client")).await?;
+    let pong: PluginMessage = within(client.expect()).await?;
+    assert_eq!(pong.channel, "rower:pong");
+    assert_eq!(pong.data, Bytes::from_static(b"client"));
+
+    session.send(message("rower:ping", b"server")).await?;
+    session.send(message(REGISTER, b"rower:ping\0shop:buy")).await?;
+
+    // neither ping got through, and the registration lost the proxy channel
+    let register: PluginMessage = within(client.expect()).await?;
+    assert_eq!(register.channel, REGISTER);
+    assert_eq!(register.data, Bytes::from_static(b"shop:buy"));
+    client.send(message("shop:buy", b"apple")).await?;
+    let forwarded: PluginMessage = within(session.expect()).await?;
+    assert_eq!(forwarded.channel, "shop:buy");
+
+    let seen = rx.try_iter().collect::<Vec<_>>();
+    assert_eq!(
+        seen,
+        [
+            (Direction::Serverbound, Bytes::from_static(b"client")),
+            (Direction::Clientbound, Bytes::from_static(b"server")),
+        ]
+    );
+
+    proxy.proxy.shutdown().await
+}
@@ -0,0 +1,166 @@
+use anyhow::Result;
+use bytes::{BufMut, BytesMut};
+use rower::protocol::buffer::BufMutExt;
+use rower::protocol::codec::decoder::MinecraftDecoder;
+use rower::protocol::codec::encoder::MinecraftEncoder;
+use rower::protocol::packet::RawPacket;
+use tokio_util::codec::{Decoder, Encoder};
+
+const THRESHOLD: u32 = 256;
+
+/// A packet of `length` bytes, id included, that compresses well.
+fn packet(id: u8, length: usize) -> RawPacket {
+    let mut buffer = BytesMut::with_capacity(length);
+    buffer.put_u8(id);
+    buffer.put_bytes(7, length - 1);
+    RawPacket::from_buffer(buffer)
+}
+
+fn encoder(threshold: Option<u32>) -> MinecraftEncoder {
+    let mut encoder = MinecraftEncoder::new();
+    if let Some(threshold) = threshold {
+        encoder.enable_compression(threshold);
+    }
+    encoder
+}
+
+fn decoder(threshold: Option<u32>, passthrough: bool) -> MinecraftDecoder {
+    let mut decoder = MinecraftDecoder::new();
+    if let Some(threshold) = threshold {
+        decoder.enable_compression(threshold, passthrough);
+    }
+    decoder
+}
+
+fn encode(encoder: &mut MinecraftEncoder, packet: RawPacket) -> Result<BytesMut> {
+    let mut frame = BytesMut::new();
+    encoder.encode(packet, &mut frame)?;
+    Ok(frame)
+}
+
+fn decode_one(decoder: &mut MinecraftDecoder, mut frame: BytesMut) -> Result<RawPacket> {
+    let packet = decoder.decode(&mut frame)?.expect("a whole frame");
+    assert!(frame.is_empty());
+    Ok(packet)
+}
+
+#[test]
+fn passthrough_keeps_packets_from_the_threshold_on_compressed() -> Result<()> {
+    for length in [THRESHOLD as usize - 1, THRESHOLD as usize, THRESHOLD as usize + 1] {
+        let frame = encode(&mut encoder(Some(THRESHOLD)), packet(0x25, length))?;
+        let packet = decode_one(&mut decoder(Some(THRESHOLD), true), frame)?;
+
+        assert_eq!(packet.id(), 0x25);
+        if length < THRESHOLD as usize {
+            assert_eq!(packet.data_length(), None, "{} bytes", length);
+        } else {
+            assert_eq!(packet.data_length(), Some(length), "{} bytes", length);
+        }
+
+        // forwarded to a side with the same threshold, the payload goes out as it came in
+        let buffer = packet.buffer.clone();
+        let forwarded = encode(&mut encoder(Some(THRESHOLD)), packet)?;
+        let received = decode_one(&mut decoder(Some(THRESHOLD), true), forwarded)?;
+        assert_eq!(received.buffer, buffer, "{} bytes", length);
+    }
+    Ok(())
+}
+
+#[test]
+fn passthrough_packets_are_recompressed_for_a_higher_threshold() -> Result<()> {
+    let length = THRESHOLD as usize;
+    let frame = encode(&mut encoder(Some(THRESHOLD)), packet(0x25, length))?;
+    let packet = decode_one(&mut decoder(Some(THRESHOLD), true), frame)?;
+    assert!(packet.data_length().is_some());
+
+    // under the receiver's threshold, so it has to be sent uncompressed
+    let forwarded = encode(&mut encoder(Some(THRESHOLD + 1)), packet)?;
+    let received = decode_one(&mut decoder(Some(THRESHOLD + 1), false), forwarded)?;
+    assert_eq!(received.data_length(), None);
+    assert_eq!(received.buffer.len(), length);
+
+    Ok(())
+}
+
+#[test]
+fn passthrough_packets_decompress_when_read_or_changed() -> Result<()> {
+    let length = THRESHOLD as usize + 1;
+    let frame = encode(&mut encoder(Some(THRESHOLD)), packet(0x25, length))?;
+
+    let mut packet = decode_one(&mut decoder(Some(THRESHOLD), true), frame.clone())?;
+    let data = packet.data()?;
+    assert_eq!(data.len(), length - 1);
+    assert!(data.iter().all(|&byte| byte == 7));
+
+    let mut packet = decode_one(&mut decoder(Some(THRESHOLD), true), frame)?;
+    packet.set_id(0x26)?;
+    assert_eq!(packet.data_length(), None);
+
+    let forwarded = encode(&mut encoder(Some(THRESHOLD)), packet)?;
+    let received = decode_one(&mut decoder(Some(THRESHOLD), false), forwarded)?;
+    assert_eq!(received.id(), 0x26);
+    assert_eq!(received.buffer.len(), length);
+
+    Ok(())
+}
+
+/// A frame with the given length prefix and body, as a hostile peer might send it.
+fn frame(length: &[u8], body: &[u8]) -> BytesMut {
+    let mut frame = BytesMut::from(length);
+    frame.put_slice(body);
+    frame
+}
+
+#[test]
+fn declared_length_does_not_reserve_up_front() -> Result<()> {
+    let mut decoder = decoder(None, false);
+    // claims the largest frame a 3 byte length allows, sends 3 bytes of it
+    let mut src = frame(&[0xff, 0xff, 0x7f], &[1, 2, 3]);
+
+    assert!(decoder.decode(&mut src)?.is_none());
+    assert!(src.capacity() < 64 * 1024, "reserved {} bytes", src.capacity());
+    Ok(())
+}
+
+#[test]
+fn oversized_frames_are_rejected() {
+    // a length that needs more than 3 bytes
+    let mut src = frame(&[0x80, 0x80, 0x80, 0x01], &[]);
+    assert!(decoder(None, false).decode(&mut src).is_err());
+
+    // an uncompressed length over the vanilla limit
+    let mut src = frame(&[0x06], &[0x80, 0x80, 0x80, 0x05, 0x78, 0x9c]);
+    assert!(decoder(Some(THRESHOLD), false).decode(&mut src).is_err());
+}
+
+#[test]
+fn empty_frames_are_rejected() {
+    assert!(decoder(None, false).decode(&mut frame(&[0x00], &[])).is_err());
+    // compressed framing, uncompressed length 0 and no id
+    assert!(decoder(Some(THRESHOLD), false).decode(&mut frame(&[0x01], &[0x00])).is_err());
+    assert!(decoder(Some(THRESHOLD), false).decode(&mut frame(&[0x00], &[])).is_err());
+}
+
+#[test]
+fn length_mismatched_frames_are_rejected() -> Result<()> {
+    let length = THRESHOLD as usize + 10;
+    let valid = encode(&mut encoder(Some(THRESHOLD)), packet(0x25, length))?;
+    let compressed = decode_one(&mut decoder(Some(THRESHOLD), true), valid)?.buffer;
+
+    // the same compressed data claiming one byte more or less than it inflates to
+    for data_length in [length - 1, length + 1] {
+        let mut body = BytesMut::new();
+        body.put_uvarint(data_length as u32);
+        body.put_slice(&compressed);
+        let mut src = BytesMut::new();
+        src.put_uvarint(body.len() as u32);
+        src.put_slice(&body);
+
+        assert!(decoder(Some(THRESHOLD), false).decode(&mut src).is_err(), "{}", data_length);
+    }
+
+    // a tiny frame claiming far more than it could inflate to
+    let mut src = frame(&[0x05], &[0x80, 0x80, 0x02, 0x78, 0x9c]);
+    assert!(decoder(Some(THRESHOLD), false).decode(&mut src).is_err());
+    Ok(())
+}
@@ -0,0 +1,132 @@
+mod support;
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use bytes::Bytes;
+use rower::component::{Color, Component};
+use rower::protocol::packet::login::Disconnect;
+use rower::protocol::packet::play::{ChatCommand, SystemChat};
+use rower::protocol::wrappers::Player;
+use rower::{Hooks, ProxyBuilder};
+
+use support::{join_game, within, FakeClient, TestProxy};
+
+/// Lets Steve run every command.
+struct SteveIsAdmin;
+
+impl Hooks for SteveIsAdmin {
+    fn has_permission(&self, player: &Player, _: &str) -> bool {
+        player.username == "Steve"
+    }
+}
+
+fn command(command: &str) -> ChatCommand {
+    ChatCommand {
+        command: command.to_owned(),
+        timestamp: 0,
+        salt: 0,
+        arguments: Vec::new(),
+        message_count: 0,
+        acknowledged: Bytes::from_static(&[0; 3]),
+    }
+}
+
+async fn run(client: &mut FakeClient, line: &str) -> Result<Component> {
+    client.send(command(line)).await?;
+    let feedback: SystemChat = within(client.expect()).await?;
+    Ok(feedback.content)
+}
+
+#[tokio::test]
+async fn player_and_server_commands() -> Result<()> {
+    let proxy = TestProxy::start_with(ProxyBuilder::new().hooks(SteveIsAdmin)).await?;
+    let (mut steve, _steve_session) = proxy.join("Steve").await?;
+    let (mut alex, _alex_session) = proxy.join("Alex").await?;
+    let (mut notch, _notch_session) = proxy.join("Notch").await?;
+
+    let list = Component::text("3 player(s) online\nbackend (3): Alex, Notch, Steve");
+    assert_eq!(run(&mut steve, "list").await?, list.color(Color::Green));
+
+    // Steve gets the alert too, before the feedback
+    let alert = Component::legacy("&eRestart in 5 minutes");
+    assert_eq!(run(&mut steve, "alert &eRestart in 5 minutes").await?, alert);
+    let sent: SystemChat = within(steve.expect()).await?;
+    assert_eq!(sent.content, Component::text("Sent the alert to 3 player(s)").color(Color::Green));
+    assert_eq!(within(alex.expect::<SystemChat>()).await?.content, alert);
+    assert_eq!(within(notch.expect::<SystemChat>()).await?.content, alert);
+
+    let missing = Component::text("Herobrine is not online").color(Color::Red);
+    assert_eq!(run(&mut steve, "kick Herobrine").await?, missing);
+    run(&mut steve, "kick notch &cBe nice").await?;
+    let kick: Disconnect = within(notch.expect()).await?;
+    assert_eq!(kick.reason, Component::legacy("&cBe nice"));
+
+    let sending = Component::text("Sending 1 player(s) to fallback").color(Color::Green);
+    assert_eq!(run(&mut steve, "send alex fallback").await?, sending);
+    let mut session = within(proxy.fallback.accept()).await?;
+    assert_eq!(session.login_start.username, "Alex");
+    session.join(join_game(2, "minecraft:the_nether")).await?;
+
+    // players get the shutdown message, the feedback may not make it before it
+    steve.send(command("shutdown")).await?;
+    within(proxy.proxy.wait()).await;
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn kicked_clients_are_closed_even_when_they_stay() -> Result<()> {
+    let proxy = TestProxy::start_with(ProxyBuilder::new().hooks(SteveIsAdmin)).await?;
+    let (mut steve, _steve_session) = proxy.join("Steve").await?;
+    let (mut notch, mut notch_session) = proxy.join("Notch").await?;
+
+    run(&mut steve, "ban notch Griefing").await?;
+    let kick: Disconnect = within(notch.expect()).await?;
+    assert_eq!(kick.reason.to_plain(), "Griefing");
+    // Notch ignores the disconnect, the proxy closes both connections itself
+    assert!(within(notch.recv()).await.is_err());
+    assert!(within(notch_session.recv_raw()).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn reload_reads_the_config_again() -> Result<()> {
+    // the backends are only bound once the proxy starts
+    let servers = Arc::new(Mutex::new(None));
+    let loaded = servers.clone();
+    let builder = ProxyBuilder::new().hooks(SteveIsAdmin).config_loader(move || {
+        let (backend, fallback) = loaded.lock().unwrap().expect("loaded before the start");
+        let mut config = support::config(backend, fallback);
+        config.admins = vec!["Alex".to_owned()];
+        Ok(config)
+    });
+    let proxy = TestProxy::start_with(builder).await?;
+    *servers.lock().unwrap() = Some((proxy.backend.addr(), proxy.fallback.addr()));
+    let (mut steve, _steve_session) = proxy.join("Steve").await?;
+    let (mut alex, mut alex_session) = proxy.join("Alex").await?;
+
+    // not an admin yet, the backend gets the command
+    alex.send(command("list")).await?;
+    assert_eq!(within(alex_session.expect::<ChatCommand>()).await?.command, "list");
+
+    let reloaded = Component::text("Reloaded the config").color(Color::Green);
+    assert_eq!(run(&mut steve, "reload").await?, reloaded);
+    let list = Component::text("2 player(s) online\nbackend (2): Alex, Steve");
+    assert_eq!(run(&mut alex, "list").await?, list.color(Color::Green));
+
+    let unchanged = Component::text("The config and the access list did not change");
+    assert_eq!(run(&mut steve, "reload").await?, unchanged.color(Color::Green));
+
+    proxy.proxy.shutdown().await
+}
+
+#[test]
+fn components_in_the_terminal() {
+    let component = Component::legacy("&cBanned &lforever");
+    assert_eq!(
+        component.to_ansi(),
+        "\x1b[0;91mBanned \x1b[0;1;91mforever\x1b[0m"
+    );
+    let rgb = Component::text("rgb").color(Color::Rgb(255, 128, 0)).bold(true);
+    assert_eq!(rgb.to_ansi(), "\x1b[0;1;38;2;255;128;0mrgb\x1b[0m");
+}
@@ -0,0 +1,88 @@
+use std::path::{Path, PathBuf};
+use std::{fs, process};
+
+use anyhow::Result;
+use clap::Parser;
+use rower::config::{self, Args};
+
+fn config_file(test: &str, content: &str) -> Result<PathBuf> {
+    let path = std::env::temp_dir().join(format!("rower-config-{}-{}.toml", process::id(), test));
+    fs::write(&path, content)?;
+    Ok(path)
+}
+
+fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+    vars.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+}
+
+fn args(path: &Path, flags: &[&str]) -> Args {
+    let config = path.to_str().unwrap();
+    Args::parse_from(["rower", "--config", config].iter().chain(flags))
+}
+
+#[test]
+fn env_overrides_the_file_and_flags_override_env() -> Result<()> {
+    let path = config_file(
+        "layers",
+        r#"
+        compression_threshold = 128
+        online = false
+        api_token = "from-file"
+        queue_retry = 1000
+
+        [servers]
+        lobby = "127.0.0.1:25570"
+        "#,
+    )?;
+    let env = vars(&[
+        ("ROWER_COMPRESSION_THRESHOLD", "300"),
+        ("ROWER_QUEUE_RETRY", "2000"),
+        ("ROWER_SERVERS__GAMES", "127.0.0.1:25571"),
+        ("ROWER_ADMINS", r#"["Steve", "Alex"]"#),
+        ("OTHER_QUEUE_RETRY", "3000"),
+    ]);
+
+    let config = config::load(&args(&path, &["--compression-threshold", "512"]), env)?;
+
+    assert_eq!(config.compression_threshold, 512);
+    assert_eq!(config.queue_retry, 2000);
+    assert!(!config.online);
+    assert_eq!(config.api_token, "from-file");
+    assert_eq!(config.server("lobby"), Some("127.0.0.1:25570".parse()?));
+    assert_eq!(config.server("games"), Some("127.0.0.1:25571".parse()?));
+    assert_eq!(config.admins, ["Steve", "Alex"]);
+    Ok(())
+}
+
+#[test]
+fn string_fields_keep_values_that_look_like_numbers() -> Result<()> {
+    let path = config_file("strings", "online = false\n")?;
+    let env = vars(&[
+        ("ROWER_API_TOKEN", "123456"),
+        ("ROWER_SHUTDOWN_MESSAGE", "true"),
+        ("ROWER_CAPTURE_DIR", "2024"),
+    ]);
+
+    let config = config::load(&args(&path, &[]), env)?;
+
+    assert_eq!(config.api_token, "123456");
+    assert_eq!(config.shutdown_message, "true");
+    assert_eq!(config.capture_dir, Some(PathBuf::from("2024")));
+    assert!(!config.online);
+    Ok(())
+}
+
+#[test]
+fn invalid_values_fail_instead_of_falling_back_to_defaults() -> Result<()> {
+    let path = config_file("invalid", "online = false\n")?;
+
+    let env = vars(&[("ROWER_ONLINE", "maybe")]);
+    assert!(config::load(&args(&path, &[]), env).is_err());
+
+    let env = vars(&[("ROWER_COMPRESSION_LEVEL", "fast")]);
+    assert!(config::load(&args(&path, &[]), env).is_err());
+
+    let path = config_file("invalid-file", "compression_threshold = \"high\"\n")?;
+    assert!(config::load(&args(&path, &[]), Vec::new()).is_err());
+    Ok(())
+}
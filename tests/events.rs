@@ -0,0 +1,207 @@
+mod support;
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use bytes::Bytes;
+use rower::component::Component;
+use rower::event::{
+    ChatEvent, CommandEvent, DisconnectEvent, InitialServerEvent, KickResult,
+    KickedFromServerEvent, PostLoginEvent, PreLoginEvent, Priority, ServerConnectedEvent,
+    ServerPreConnectEvent,
+};
+use rower::protocol::packet::login::Disconnect;
+use rower::protocol::packet::play::{ChatCommand, ChatMessage, JoinGame};
+use rower::protocol::packet::PacketType;
+use rower::ProxyBuilder;
+use tokio::sync::mpsc;
+
+use support::{join_game, within, FakeClient, LoginResult, TestProxy};
+
+fn command(command: &str) -> ChatCommand {
+    ChatCommand {
+        command: command.to_owned(),
+        timestamp: 0,
+        salt: 0,
+        arguments: Vec::new(),
+        message_count: 0,
+        acknowledged: Bytes::from_static(&[0; 3]),
+    }
+}
+
+#[tokio::test]
+async fn pre_login_deny() -> Result<()> {
+    let builder = ProxyBuilder::new().on(Priority::Normal, |mut event: PreLoginEvent| async move {
+        if event.username == "Griefer" {
+            event.deny(Component::text("You are banned"));
+        }
+        event
+    });
+    let proxy = TestProxy::start_with(builder).await?;
+
+    match within(FakeClient::login(proxy.addr, "Griefer")).await? {
+        LoginResult::Disconnected(reason) => assert_eq!(reason, Component::text("You are banned")),
+        LoginResult::Success(..) => panic!("login should have been denied"),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn handlers_run_in_priority_order() -> Result<()> {
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let record = |name: &'static str| {
+        let order = order.clone();
+        move |event: ServerPreConnectEvent| {
+            order.lock().unwrap().push(name);
+            async move { event }
+        }
+    };
+
+    let builder = ProxyBuilder::new()
+        .on(Priority::Last, record("last"))
+        .on(Priority::Normal, record("normal"))
+        .on(Priority::First, record("first"))
+        .on(Priority::Normal, record("normal again"));
+    let proxy = TestProxy::start_with(builder).await?;
+    proxy.join("Steve").await?;
+
+    assert_eq!(
+        *order.lock().unwrap(),
+        ["first", "normal", "normal again", "last"]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn initial_server_and_redirect() -> Result<()> {
+    let unreachable: SocketAddr = "127.0.0.1:1".parse()?;
+    let requested = Arc::new(Mutex::new(None));
+    let seen = requested.clone();
+    // the fallback is only bound once the proxy starts
+    let redirect = Arc::new(Mutex::new(unreachable));
+    let to = redirect.clone();
+
+    let builder = ProxyBuilder::new()
+        .on(Priority::Normal, move |mut event: InitialServerEvent| async move {
+            event.server = unreachable;
+            event
+        })
+        // pre-connect comes after the initial server and has the final word
+        .on(Priority::Normal, move |mut event: ServerPreConnectEvent| {
+            *seen.lock().unwrap() = Some(event.server);
+            event.server = *to.lock().unwrap();
+            async move { event }
+        });
+    let proxy = TestProxy::start_with(builder).await?;
+    *redirect.lock().unwrap() = proxy.fallback.addr();
+
+    let fallback = async {
+        let mut session = proxy.fallback.accept().await?;
+        session.join(join_game(2, "minecraft:fallback")).await?;
+        Ok::<_, anyhow::Error>(session)
+    };
+    let (client, session) = within(async {
+        tokio::join!(FakeClient::expect_login(proxy.addr, "Steve"), fallback)
+    })
+    .await;
+    let ((mut client, _), _session) = (client?, session?);
+
+    let join: JoinGame = within(client.expect()).await?;
+    assert_eq!(join.entity_id, 2);
+    assert_eq!(*requested.lock().unwrap(), Some(unreachable));
+    Ok(())
+}
+
+#[tokio::test]
+async fn login_lifecycle_events() -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let (post, connected, disconnected) = (tx.clone(), tx.clone(), tx);
+
+    let builder = ProxyBuilder::new()
+        .on(Priority::Normal, move |event: PostLoginEvent| {
+            post.send(format!("post login {}", event.player.username)).unwrap();
+            async move { event }
+        })
+        .on(Priority::Normal, move |event: ServerConnectedEvent| {
+            connected
+                .send(format!("connected {:?}", event.previous))
+                .unwrap();
+            async move { event }
+        })
+        .on(Priority::Normal, move |event: DisconnectEvent| {
+            disconnected
+                .send(format!("disconnect {}", event.player.username))
+                .unwrap();
+            async move { event }
+        });
+    let proxy = TestProxy::start_with(builder).await?;
+
+    let (client, _session) = proxy.join("Steve").await?;
+    assert_eq!(within(rx.recv()).await.unwrap(), "post login Steve");
+    assert_eq!(within(rx.recv()).await.unwrap(), "connected None");
+
+    drop(client);
+    assert_eq!(within(rx.recv()).await.unwrap(), "disconnect Steve");
+    Ok(())
+}
+
+#[tokio::test]
+async fn chat_and_commands() -> Result<()> {
+    let builder = ProxyBuilder::new()
+        .on(Priority::Normal, |mut event: CommandEvent| async move {
+            event.cancelled = event.command.starts_with("proxy");
+            event
+        })
+        .on(Priority::Normal, |mut event: ChatEvent| async move {
+            event.message = event.message.replace("heck", "****");
+            event
+        });
+    let proxy = TestProxy::start_with(builder).await?;
+    let (mut client, mut session) = proxy.join("Steve").await?;
+
+    client.send(command("proxy reload")).await?;
+    client.send(command("spawn")).await?;
+    let forwarded: ChatCommand = within(session.expect()).await?;
+    assert_eq!(forwarded.command, "spawn");
+
+    client
+        .send(ChatMessage {
+            message: "what the heck".to_owned(),
+            timestamp: 0,
+            salt: 0,
+            signature: None,
+            message_count: 0,
+            acknowledged: Bytes::from_static(&[0; 3]),
+        })
+        .await?;
+    let forwarded: ChatMessage = within(session.expect()).await?;
+    assert_eq!(forwarded.message, "what the ****");
+    Ok(())
+}
+
+#[tokio::test]
+async fn kicked_event_can_disconnect() -> Result<()> {
+    let builder = ProxyBuilder::new().on(Priority::Normal, |mut event: KickedFromServerEvent| async move {
+        let reason = Component::text("Kicked: ").append(vec![event.reason.clone()]);
+        event.result = KickResult::Disconnect(reason);
+        event
+    });
+    let proxy = TestProxy::start_with(builder).await?;
+    let (mut client, mut session) = proxy.join("Steve").await?;
+
+    session
+        .send(Disconnect {
+            reason: Component::text("Server restarting"),
+        })
+        .await?;
+
+    match within(client.recv()).await? {
+        PacketType::Disconnect(Disconnect { reason }) => assert_eq!(
+            reason,
+            Component::text("Kicked: ").append(vec![Component::text("Server restarting")])
+        ),
+        _ => panic!("expected a disconnect"),
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+mod support;
+
+use std::net::SocketAddr;
+
+use anyhow::Result;
+use rower::access::{Ban, BanTarget};
+use rower::component::Component;
+use rower::haproxy;
+use rower::protocol::packet::handshake::NextState;
+use rower::protocol::packet::status::StatusRequest;
+use rower::protocol::State;
+
+use support::{join_game, within, FakeClient, LoginResult, Setup, TestProxy};
+
+#[tokio::test]
+async fn v2_header_is_passed_on_to_the_backend() -> Result<()> {
+    let setup = Setup { proxy_protocol: true, ..Default::default() };
+    let proxy = TestProxy::start_setup(setup).await?;
+    let player: SocketAddr = "198.51.100.4:40000".parse()?;
+
+    let header = haproxy::header(player, proxy.addr);
+    let client = FakeClient::connect_with_header(proxy.addr, NextState::Login, &header).await?;
+    let backend = async {
+        let (client, mut session) = proxy.backend.accept_proxied().await?;
+        session.join(join_game(1, "minecraft:overworld")).await?;
+        Ok::<_, anyhow::Error>((client, session))
+    };
+    let (login, backend) = within(async { tokio::join!(client.log_in("Steve"), backend) }).await;
+    let (proxied, _session) = backend?;
+    assert!(matches!(login?, LoginResult::Success(..)));
+
+    assert_eq!(proxied, Some(player));
+    assert_eq!(proxy.proxy.players().all()[0].player.address, player);
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn v1_header_address_is_checked_against_bans() -> Result<()> {
+    let setup = Setup { proxy_protocol: true, ..Default::default() };
+    let proxy = TestProxy::start_setup(setup).await?;
+    proxy.proxy.access().ban(Ban {
+        target: BanTarget::parse("203.0.113.0/24"),
+        reason: Component::text("Banned network"),
+        expires: None,
+        source: "Console".to_owned(),
+    })?;
+
+    let header = b"PROXY TCP4 203.0.113.7 127.0.0.1 51234 25565\r\n";
+    let client = FakeClient::connect_with_header(proxy.addr, NextState::Login, header).await?;
+    match within(client.log_in("Steve")).await? {
+        LoginResult::Disconnected(reason) => assert_eq!(reason, Component::text("Banned network")),
+        LoginResult::Success(..) => panic!("Steve was let in"),
+    }
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn connections_without_a_header_are_closed() -> Result<()> {
+    let setup = Setup { proxy_protocol: true, ..Default::default() };
+    let proxy = TestProxy::start_setup(setup).await?;
+
+    let mut client = FakeClient::connect(proxy.addr, NextState::Status).await?;
+    client.conn.change_state(State::Status);
+    client.send(StatusRequest).await?;
+    assert!(within(client.conn.recv_raw_packet()).await.is_err());
+
+    proxy.proxy.shutdown().await
+}
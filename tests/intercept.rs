@@ -0,0 +1,155 @@
+mod support;
+
+use anyhow::Result;
+use bytes::{Buf, Bytes, BytesMut};
+use rower::intercept::Intercepted;
+use rower::protocol::buffer::{BufExt, BufMutExt};
+use rower::protocol::packet::play::PluginMessage;
+use rower::protocol::packet::Packet;
+use rower::protocol::{Direction, ProtocolVersion};
+use rower::ProxyBuilder;
+
+use support::{within, TestProxy, VERSION};
+
+const ARM_SWING: u8 = 0x2f;
+const TITLE: u8 = 0x5d;
+
+/// Neither packet is known to the proxy itself.
+#[derive(Debug, PartialEq)]
+struct ArmSwing {
+    hand: i32,
+}
+
+impl Packet for ArmSwing {
+    fn from_bytes(buf: &mut impl Buf, _: ProtocolVersion) -> Result<Self> {
+        Ok(Self { hand: buf.get_varint()? })
+    }
+
+    fn put_buf(self, buf: &mut BytesMut, _: ProtocolVersion) {
+        buf.put_varint(self.hand);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Title {
+    text: String,
+}
+
+impl Packet for Title {
+    fn from_bytes(buf: &mut impl Buf, _: ProtocolVersion) -> Result<Self> {
+        Ok(Self { text: buf.get_string(256)? })
+    }
+
+    fn put_buf(self, buf: &mut BytesMut, _: ProtocolVersion) {
+        buf.put_string(&self.text);
+    }
+}
+
+#[tokio::test]
+async fn modify_drop_and_inject() -> Result<()> {
+    let builder = ProxyBuilder::new().intercept(
+        Direction::Serverbound,
+        &[(ProtocolVersion::V1_19_4, ARM_SWING)],
+        |mut swing: Intercepted<ArmSwing>| async move {
+            match swing.packet.as_ref().map(|packet| packet.hand) {
+                Some(0) => swing.packet = Some(ArmSwing { hand: 1 }),
+                Some(5) => swing.cancel(),
+                _ => swing.inject(ArmSwing { hand: 3 }).unwrap(),
+            }
+            swing
+        },
+    );
+    let proxy = TestProxy::start_with(builder).await?;
+    let (mut client, mut session) = proxy.join("Steve").await?;
+
+    for hand in [0, 5, 2] {
+        client.send_raw(ARM_SWING, &[hand]).await?;
+    }
+    for hand in [1, 2, 3] {
+        let swing: ArmSwing = within(session.recv_raw()).await?.decode(VERSION)?;
+        assert_eq!(swing, ArmSwing { hand });
+    }
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn reply_to_the_sender() -> Result<()> {
+    let builder = ProxyBuilder::new()
+        .intercept(
+            Direction::Serverbound,
+            &[(ProtocolVersion::V1_19_4, ARM_SWING)],
+            |swing: Intercepted<ArmSwing>| async move { swing },
+        )
+        .intercept(
+            Direction::Clientbound,
+            &[(ProtocolVersion::V1_19_3, 0x5b), (ProtocolVersion::V1_19_4, TITLE)],
+            |mut title: Intercepted<Title>| async move {
+                if let Some(packet) = &mut title.packet {
+                    packet.text = packet.text.to_uppercase();
+                }
+                title.reply(ArmSwing { hand: 9 }).unwrap();
+                title
+                    .inject(PluginMessage {
+                        channel: "rower:title".to_owned(),
+                        data: Bytes::from_static(b"seen"),
+                    })
+                    .unwrap();
+                title
+            },
+        );
+    let proxy = TestProxy::start_with(builder).await?;
+    let (mut client, mut session) = proxy.join("Steve").await?;
+
+    let mut data = BytesMut::new();
+    data.put_string("hello");
+    session.send_raw(TITLE, &data).await?;
+
+    let title: Title = within(client.conn.recv_raw_packet()).await?.decode(VERSION)?;
+    assert_eq!(title.text, "HELLO");
+    let message: PluginMessage = within(client.expect()).await?;
+    assert_eq!(message.channel, "rower:title");
+
+    let swing: ArmSwing = within(session.recv_raw()).await?.decode(VERSION)?;
+    assert_eq!(swing, ArmSwing { hand: 9 });
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn core_packets_can_be_intercepted() -> Result<()> {
+    let builder = ProxyBuilder::new().intercept(
+        Direction::Clientbound,
+        &[(ProtocolVersion::V1_19_4, 0x17)],
+        |mut message: Intercepted<PluginMessage>| async move {
+            if message.packet.as_ref().is_some_and(|packet| packet.channel == "rower:secret") {
+                message.cancel();
+            }
+            message
+        },
+    );
+    let proxy = TestProxy::start_with(builder).await?;
+    let (mut client, mut session) = proxy.join("Steve").await?;
+
+    session
+        .send(PluginMessage {
+            channel: "rower:secret".to_owned(),
+            data: Bytes::new(),
+        })
+        .await?;
+    let mut brand = BytesMut::new();
+    brand.put_string("vanilla");
+    session
+        .send(PluginMessage {
+            channel: "minecraft:brand".to_owned(),
+            data: brand.freeze(),
+        })
+        .await?;
+
+    // dropped by the interceptor, the brand still goes through the proxy's own handling
+    let mut message: PluginMessage = within(client.expect()).await?;
+    assert_eq!(message.channel, "minecraft:brand");
+    assert_eq!(message.data.get_string(32700)?, "vanilla inside a bike");
+
+    proxy.proxy.shutdown().await
+}
@@ -0,0 +1,68 @@
+mod support;
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use rower::logging::{self, LogFormat};
+use serde_json::Value;
+
+use support::{within, TestProxy};
+
+/// Collects what the subscriber writes.
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Output {
+    fn lines(&self) -> Vec<Value> {
+        let output = self.0.lock().unwrap();
+        String::from_utf8_lossy(&output)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+}
+
+#[tokio::test]
+async fn errors_carry_the_connection_span() -> Result<()> {
+    let output = Output::default();
+    let writer = output.clone();
+    logging::init_with_writer(LogFormat::Json, move || writer.clone())?;
+
+    let proxy = TestProxy::start().await?;
+    let (_client, session) = proxy.join("Steve").await?;
+    // the backend going away fails the connection
+    drop(session);
+
+    let error = within(async {
+        loop {
+            if let Some(line) = output.lines().into_iter().find(|line| line["level"] == "ERROR") {
+                return line;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+    assert_eq!(error["message"], "Connection aborted");
+    let span = &error["span"];
+    assert_eq!(span["name"], "connection");
+    assert_eq!(span["username"], "Steve");
+    assert_eq!(span["server"], "backend");
+    assert_eq!(span["protocol"], "V1_19_4");
+    assert!(span["uuid"].as_str().is_some_and(|uuid| uuid.len() == 36));
+    assert!(span["address"].as_str().is_some_and(|address| address.starts_with("127.0.0.1:")));
+
+    proxy.proxy.shutdown().await
+}
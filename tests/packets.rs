@@ -0,0 +1,543 @@
+use std::fmt::Debug;
+
+use bytes::{Bytes, BytesMut};
+use proptest::prelude::*;
+use rower::component::{Component, Type};
+use rower::online::Property;
+use rower::protocol::nbt::{Compound, Tag};
+use rower::protocol::packet::{
+    handshake::{Handshake, NextState},
+    login::{
+        Disconnect, EncryptionRequest, EncryptionResponse, LoginAcknowledged, LoginPluginRequest,
+        LoginPluginResponse, LoginStart, LoginSuccess, SetCompression,
+    },
+    play::{
+        BossBar, BossBarAction, BossBarColor, BossBarDivision, ChatCommand, ChatMessage, Death,
+        JoinGame, KeepAlive, PlayerPosition, PluginMessage, Respawn, SystemChat,
+    },
+    status::{Ping, StatusRequest},
+    Packet,
+};
+use rower::protocol::codec::registry::PLAY_REG;
+use rower::protocol::{Direction, ProtocolVersion};
+use strum::IntoEnumIterator;
+use uuid::Uuid;
+
+fn round_trip<T>(packet: T, version: ProtocolVersion) -> Result<(), TestCaseError>
+where
+    T: Packet + Clone + PartialEq + Debug,
+{
+    let mut buf = BytesMut::new();
+    packet.clone().put_buf(&mut buf, version);
+    let mut bytes = buf.freeze();
+
+    let decoded = T::from_bytes(&mut bytes, version)
+        .map_err(|err| TestCaseError::fail(format!("{:?} failed to decode: {:#}", packet, err)))?;
+
+    prop_assert_eq!(&decoded, &packet, "version {:?}", version);
+    prop_assert!(bytes.is_empty(), "{} bytes left after decoding", bytes.len());
+    Ok(())
+}
+
+fn version() -> impl Strategy<Value = ProtocolVersion> {
+    prop::sample::select(ProtocolVersion::iter().collect::<Vec<_>>())
+}
+
+fn uuid() -> impl Strategy<Value = Uuid> {
+    any::<u128>().prop_map(Uuid::from_u128)
+}
+
+fn username() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9_]{1,16}"
+}
+
+fn identifier() -> impl Strategy<Value = String> {
+    "[a-z0-9_.-]{1,16}:[a-z0-9_./-]{1,32}"
+}
+
+fn bytes(max: usize) -> impl Strategy<Value = Bytes> {
+    prop::collection::vec(any::<u8>(), 0..max).prop_map(Bytes::from)
+}
+
+fn component() -> impl Strategy<Value = Component> {
+    let leaf = prop_oneof![
+        ".{0,32}".prop_map(|text| Component::text(&text)),
+        "[a-z.]{1,32}".prop_map(|key| Component::translate(&key)),
+        "key\\.[a-z.]{1,16}".prop_map(|key| Component::content(Type::Keybind(key))),
+    ];
+    let styled = (leaf, any::<Option<bool>>(), any::<Option<bool>>()).prop_map(
+        |(mut component, bold, underlined)| {
+            if let Some(bold) = bold {
+                component = component.bold(bold);
+            }
+            if let Some(underlined) = underlined {
+                component = component.underlined(underlined);
+            }
+            component
+        },
+    );
+
+    styled.prop_recursive(3, 16, 4, |inner| {
+        (inner.clone(), prop::collection::vec(inner, 0..4))
+            .prop_map(|(component, extra)| component.append(extra))
+    })
+}
+
+fn tag() -> impl Strategy<Value = Tag> {
+    let leaf = prop_oneof![
+        any::<i8>().prop_map(Tag::Byte),
+        any::<i16>().prop_map(Tag::Short),
+        any::<i32>().prop_map(Tag::Int),
+        any::<i64>().prop_map(Tag::Long),
+        (-1e6f32..1e6).prop_map(Tag::Float),
+        (-1e12f64..1e12).prop_map(Tag::Double),
+        prop::collection::vec(any::<u8>(), 0..16).prop_map(Tag::ByteArray),
+        "[a-z:_ ]{0,16}".prop_map(Tag::String),
+        prop::collection::vec(any::<i32>(), 0..8).prop_map(Tag::IntArray),
+        prop::collection::vec(any::<i64>(), 0..8).prop_map(Tag::LongArray),
+    ];
+
+    leaf.prop_recursive(4, 32, 6, |inner| {
+        prop_oneof![
+            // lists are homogeneous, repeat one generated tag
+            (inner.clone(), 1..4usize).prop_map(|(tag, n)| Tag::List(vec![tag; n])),
+            Just(Tag::List(Vec::new())),
+            prop::collection::vec(("[a-z_]{1,8}", inner), 0..6).prop_map(Tag::Compound),
+        ]
+    })
+}
+
+fn compound() -> impl Strategy<Value = Compound> {
+    ("[a-z]{0,8}", prop::collection::vec(("[a-z_:]{1,24}", tag()), 0..6))
+        .prop_map(|(name, tags)| Compound::new(name, tags))
+}
+
+fn property() -> impl Strategy<Value = Property> {
+    ("[a-z]{1,16}", "[A-Za-z0-9+/=]{0,64}", prop::option::of("[A-Za-z0-9+/=]{1,64}"))
+        .prop_map(|(name, value, signature)| Property { name, value, signature })
+}
+
+fn death() -> impl Strategy<Value = Option<Death>> {
+    prop::option::of((identifier(), any::<i64>()).prop_map(|(dimension_name, position)| Death {
+        dimension_name,
+        position,
+    }))
+}
+
+fn color() -> impl Strategy<Value = BossBarColor> {
+    prop_oneof![
+        Just(BossBarColor::Pink),
+        Just(BossBarColor::Blue),
+        Just(BossBarColor::Red),
+        Just(BossBarColor::Green),
+        Just(BossBarColor::Yellow),
+        Just(BossBarColor::Purple),
+        Just(BossBarColor::White),
+    ]
+}
+
+fn division() -> impl Strategy<Value = BossBarDivision> {
+    prop_oneof![
+        Just(BossBarDivision::None),
+        Just(BossBarDivision::SixNotches),
+        Just(BossBarDivision::TenNotches),
+        Just(BossBarDivision::TwelveNotches),
+        Just(BossBarDivision::TwentyNotches),
+    ]
+}
+
+fn boss_bar_action() -> impl Strategy<Value = BossBarAction> {
+    prop_oneof![
+        (component(), 0f32..=1.0, color(), division(), any::<u8>()).prop_map(
+            |(title, health, color, division, flags)| BossBarAction::Add {
+                title,
+                health,
+                color,
+                division,
+                flags,
+            }
+        ),
+        Just(BossBarAction::Remove),
+        (0f32..=1.0).prop_map(BossBarAction::UpdateHealth),
+        component().prop_map(BossBarAction::UpdateTitle),
+        (color(), division()).prop_map(|(c, d)| BossBarAction::UpdateStyle(c, d)),
+        any::<u8>().prop_map(BossBarAction::UpdateFlags),
+    ]
+}
+
+/// The uuid field only exists from 1.19.2 and is mandatory since 1.20.2.
+fn login_start() -> impl Strategy<Value = (LoginStart, ProtocolVersion)> {
+    (version(), username(), uuid(), any::<bool>()).prop_map(|(version, username, uuid, has_uuid)| {
+        let uuid = if version >= ProtocolVersion::V1_20_2 {
+            Some(uuid)
+        } else if version >= ProtocolVersion::V1_19_2 {
+            has_uuid.then_some(uuid)
+        } else {
+            None
+        };
+        (LoginStart { username, uuid }, version)
+    })
+}
+
+fn join_game() -> impl Strategy<Value = JoinGame> {
+    (
+        (any::<i32>(), any::<bool>(), any::<u8>(), any::<u8>()),
+        prop::collection::vec(identifier(), 0..4),
+        compound(),
+        (identifier(), identifier(), any::<i64>()),
+        (any::<i32>(), any::<i32>(), any::<i32>()),
+        any::<[bool; 4]>(),
+        death(),
+    )
+        .prop_map(
+            |(
+                (entity_id, is_hardcore, gamemode, previous_gamemode),
+                dimensions_names,
+                registry,
+                (dimension_type, dimension_name, hashed_seed),
+                (max_players, view_distance, simulation_distance),
+                [reduced_debug_info, respawn_screen, is_debug, is_flat],
+                last_death,
+            )| JoinGame {
+                entity_id,
+                is_hardcore,
+                gamemode,
+                previous_gamemode,
+                dimensions_names,
+                registry,
+                dimension_type,
+                dimension_name,
+                hashed_seed,
+                max_players,
+                view_distance,
+                simulation_distance,
+                reduced_debug_info,
+                respawn_screen,
+                is_debug,
+                is_flat,
+                last_death,
+            },
+        )
+}
+
+fn respawn() -> impl Strategy<Value = Respawn> {
+    (
+        (identifier(), identifier(), any::<i64>()),
+        (any::<u8>(), any::<u8>(), any::<bool>(), any::<bool>(), any::<u8>()),
+        death(),
+    )
+        .prop_map(
+            |(
+                (dimension_type, dimension_name, hashed_seed),
+                (gamemode, previous_gamemode, is_debug, is_flat, data_kept),
+                last_death,
+            )| Respawn {
+                dimension_type,
+                dimension_name,
+                hashed_seed,
+                gamemode,
+                previous_gamemode,
+                is_debug,
+                is_flat,
+                data_kept,
+                last_death,
+            },
+        )
+}
+
+fn signature() -> impl Strategy<Value = Bytes> {
+    prop::collection::vec(any::<u8>(), 256).prop_map(Bytes::from)
+}
+
+/// 1.19.2 has no message count and always length prefixes signatures.
+fn message_count(version: ProtocolVersion, count: i32) -> i32 {
+    if version >= ProtocolVersion::V1_19_3 {
+        count
+    } else {
+        0
+    }
+}
+
+fn chat_message() -> impl Strategy<Value = (ChatMessage, ProtocolVersion)> {
+    (
+        version(),
+        ".{0,64}",
+        any::<i64>(),
+        any::<i64>(),
+        prop::option::of(signature()),
+        0..i32::MAX,
+        bytes(8),
+    )
+        .prop_map(
+            |(version, message, timestamp, salt, signature, count, acknowledged)| {
+                let message = ChatMessage {
+                    message,
+                    timestamp,
+                    salt,
+                    signature,
+                    message_count: message_count(version, count),
+                    acknowledged,
+                };
+                (message, version)
+            },
+        )
+}
+
+fn chat_command() -> impl Strategy<Value = (ChatCommand, ProtocolVersion)> {
+    (
+        version(),
+        "[a-z0-9 ]{0,64}",
+        any::<i64>(),
+        any::<i64>(),
+        prop::collection::vec(("[a-z]{1,16}", signature()), 0..4),
+        0..i32::MAX,
+        bytes(8),
+    )
+        .prop_map(
+            |(version, command, timestamp, salt, arguments, count, acknowledged)| {
+                let command = ChatCommand {
+                    command,
+                    timestamp,
+                    salt,
+                    arguments,
+                    message_count: message_count(version, count),
+                    acknowledged,
+                };
+                (command, version)
+            },
+        )
+}
+
+proptest! {
+    #[test]
+    fn handshake(version in version(), protocol in any::<i32>(), server_address in "[a-z0-9.-]{1,255}", port in any::<u16>(), login in any::<bool>()) {
+        let state = if login { NextState::Login } else { NextState::Status };
+        round_trip(Handshake { protocol, server_address, port, state }, version)?;
+    }
+
+    #[test]
+    fn login_start_packet((packet, version) in login_start()) {
+        round_trip(packet, version)?;
+    }
+
+    #[test]
+    fn login_success(version in version(), uuid in uuid(), username in username(), properties in prop::collection::vec(property(), 0..3)) {
+        round_trip(LoginSuccess { uuid, username, properties }, version)?;
+    }
+
+    #[test]
+    fn login_acknowledged(version in version()) {
+        round_trip(LoginAcknowledged, version)?;
+    }
+
+    #[test]
+    fn disconnect(version in version(), reason in component()) {
+        round_trip(Disconnect { reason }, version)?;
+    }
+
+    #[test]
+    fn set_compression(version in version(), threshold in any::<i32>()) {
+        round_trip(SetCompression { threshold }, version)?;
+    }
+
+    #[test]
+    fn encryption_request(version in version(), server_id in "[a-f0-9]{0,20}", public_key in prop::collection::vec(any::<u8>(), 0..300), verify_token in any::<[u8; 4]>()) {
+        round_trip(EncryptionRequest { server_id, public_key, verify_token }, version)?;
+    }
+
+    #[test]
+    fn encryption_response(version in version(), shared_secret in bytes(256), verify_token in bytes(256)) {
+        round_trip(EncryptionResponse { shared_secret, verify_token }, version)?;
+    }
+
+    #[test]
+    fn login_plugin_request(version in version().prop_filter("added in 1.13", |v| *v >= ProtocolVersion::V1_13), message_id in any::<i32>(), channel in identifier(), data in bytes(64)) {
+        round_trip(LoginPluginRequest { message_id, channel, data }, version)?;
+    }
+
+    #[test]
+    fn login_plugin_response(version in version(), message_id in any::<i32>(), data in prop::option::of(bytes(64).prop_filter("no data is None", |d| !d.is_empty()))) {
+        // only a successful response carries data
+        let successful = data.is_some();
+        round_trip(LoginPluginResponse { message_id, successful, data }, version)?;
+    }
+
+    #[test]
+    fn plugin_message(version in version(), channel in identifier(), data in bytes(256)) {
+        round_trip(PluginMessage { channel, data }, version)?;
+    }
+
+    #[test]
+    fn join_game_packet(version in version(), packet in join_game()) {
+        round_trip(packet, version)?;
+    }
+
+    #[test]
+    fn respawn_packet(version in version(), packet in respawn()) {
+        round_trip(packet, version)?;
+    }
+
+    #[test]
+    fn system_chat(version in version(), content in component(), overlay in any::<bool>()) {
+        round_trip(SystemChat { content, overlay }, version)?;
+    }
+
+    #[test]
+    fn keep_alive(version in version(), id in any::<i64>()) {
+        round_trip(KeepAlive(id), version)?;
+    }
+
+    #[test]
+    fn player_position(version in version(), x in any::<f64>(), y in any::<f64>(), z in any::<f64>(), yaw in any::<f32>(), pitch in any::<f32>(), flags in any::<u8>(), teleport_id in any::<i32>(), dismount in any::<bool>()) {
+        prop_assume!(!(x.is_nan() || y.is_nan() || z.is_nan() || yaw.is_nan() || pitch.is_nan()));
+        let dismount_vehicle = dismount && version < ProtocolVersion::V1_19_4;
+        round_trip(PlayerPosition { x, y, z, yaw, pitch, flags, teleport_id, dismount_vehicle }, version)?;
+    }
+
+    #[test]
+    fn boss_bar(version in version(), uuid in uuid(), action in boss_bar_action()) {
+        round_trip(BossBar { uuid, action }, version)?;
+    }
+
+    #[test]
+    fn chat_command_packet((packet, version) in chat_command()) {
+        round_trip(packet, version)?;
+    }
+
+    #[test]
+    fn chat_message_packet((packet, version) in chat_message()) {
+        round_trip(packet, version)?;
+    }
+
+    #[test]
+    fn status_request(version in version()) {
+        round_trip(StatusRequest, version)?;
+    }
+
+    #[test]
+    fn ping(version in version(), payload in any::<i64>()) {
+        round_trip(Ping(payload), version)?;
+    }
+}
+
+// Golden vectors, checked byte for byte against what vanilla sends.
+
+fn encode(packet: impl Packet, version: ProtocolVersion) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    packet.put_buf(&mut buf, version);
+    buf.to_vec()
+}
+
+#[test]
+fn handshake_golden() {
+    // vanilla 1.20.4 client connecting to localhost:25565
+    let bytes: &[u8] = &[
+        0xFD, 0x05, 0x09, 0x6C, 0x6F, 0x63, 0x61, 0x6C, 0x68, 0x6F, 0x73, 0x74, 0x63, 0xDD, 0x02,
+    ];
+    let packet = Handshake {
+        protocol: 765,
+        server_address: "localhost".to_string(),
+        port: 25565,
+        state: NextState::Login,
+    };
+
+    assert_eq!(encode(packet.clone(), ProtocolVersion::V1_20_3), bytes);
+    assert_eq!(
+        Handshake::from_bytes(&mut Bytes::from_static(bytes), ProtocolVersion::V1_20_3).unwrap(),
+        packet
+    );
+}
+
+#[test]
+fn plugin_message_golden() {
+    // minecraft:register sent by a fabric client, same capture as benches/decoder.rs without the id
+    let bytes: &[u8] = b"\x12minecraft:registerfabric:container/open\0fabric:registry/sync\0\
+        fabric:registry/sync/direct\0fabric-screen-handler-api-v1:open_screen";
+
+    let packet =
+        PluginMessage::from_bytes(&mut Bytes::from_static(bytes), ProtocolVersion::V1_19_4).unwrap();
+    assert_eq!(packet.channel, "minecraft:register");
+    assert_eq!(
+        packet.data.split(|&b| b == 0).collect::<Vec<_>>(),
+        [
+            &b"fabric:container/open"[..],
+            b"fabric:registry/sync",
+            b"fabric:registry/sync/direct",
+            b"fabric-screen-handler-api-v1:open_screen",
+        ]
+    );
+    assert_eq!(encode(packet, ProtocolVersion::V1_19_4), bytes);
+}
+
+#[test]
+fn set_compression_golden() {
+    assert_eq!(
+        encode(SetCompression { threshold: 256 }, ProtocolVersion::V1_20_3),
+        [0x80, 0x02]
+    );
+}
+
+#[test]
+fn ping_golden() {
+    assert_eq!(
+        encode(Ping(0x0102030405060708), ProtocolVersion::V1_20_3),
+        [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]
+    );
+}
+
+#[test]
+fn chat_command_1_19_2_golden() {
+    // "/msg Alex hi" with one argument signature and the signed preview flag unset
+    let mut bytes = b"\x0bmsg Alex hi".to_vec();
+    bytes.extend_from_slice(&1_i64.to_be_bytes());
+    bytes.extend_from_slice(&2_i64.to_be_bytes());
+    bytes.extend_from_slice(b"\x01\x07message\x03\xaa\xbb\xcc");
+    // signed preview, no last seen messages, no last received
+    bytes.extend_from_slice(b"\x00\x00\x00");
+
+    let packet =
+        ChatCommand::from_bytes(&mut Bytes::from(bytes.clone()), ProtocolVersion::V1_19_2).unwrap();
+    assert_eq!(packet.command, "msg Alex hi");
+    assert_eq!((packet.timestamp, packet.salt), (1, 2));
+    assert_eq!(packet.arguments, [("message".to_owned(), Bytes::from_static(&[0xaa, 0xbb, 0xcc]))]);
+    assert_eq!(packet.message_count, 0);
+    assert_eq!(encode(packet, ProtocolVersion::V1_19_2), bytes);
+}
+
+#[test]
+fn player_position_1_19_2_golden() {
+    let mut bytes = Vec::new();
+    for coordinate in [0.0_f64, 100.0, 0.0] {
+        bytes.extend_from_slice(&coordinate.to_be_bytes());
+    }
+    bytes.extend_from_slice(&[0; 8]);
+    // flags, teleport id and dismount vehicle
+    bytes.extend_from_slice(b"\x00\x00\x01");
+
+    let packet =
+        PlayerPosition::from_bytes(&mut Bytes::from(bytes.clone()), ProtocolVersion::V1_19_2).unwrap();
+    assert_eq!(packet.y, 100.0);
+    assert!(packet.dismount_vehicle);
+    assert_eq!(encode(packet, ProtocolVersion::V1_19_2), bytes);
+
+    let clientbound = |version| PLAY_REG.get_registry(Direction::Clientbound, version);
+    let id = |version| *clientbound(version).get_id::<PlayerPosition>().unwrap();
+    assert_eq!(id(ProtocolVersion::V1_19_2), 0x39);
+    assert_eq!(id(ProtocolVersion::V1_19_3), 0x38);
+    assert_eq!(id(ProtocolVersion::V1_19_4), 0x3c);
+}
+
+#[test]
+fn unsigned_chat_message_1_19_2_golden() {
+    let mut bytes = b"\x02hi".to_vec();
+    bytes.extend_from_slice(&1_i64.to_be_bytes());
+    bytes.extend_from_slice(&2_i64.to_be_bytes());
+    // empty signature, signed preview, no last seen messages, no last received
+    bytes.extend_from_slice(b"\x00\x00\x00\x00");
+
+    let packet =
+        ChatMessage::from_bytes(&mut Bytes::from(bytes.clone()), ProtocolVersion::V1_19_2).unwrap();
+    assert_eq!(packet.message, "hi");
+    assert_eq!(packet.signature, None);
+    assert_eq!(encode(packet, ProtocolVersion::V1_19_2), bytes);
+}
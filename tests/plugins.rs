@@ -0,0 +1,191 @@
+mod support;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use anyhow::{ensure, Result};
+use bytes::{Bytes, BytesMut};
+use rower::component::Component;
+use rower::protocol::buffer::{BufExt, BufMutExt};
+use rower::protocol::packet::play::{ChatCommand, ChatMessage, PluginMessage};
+use rower::ProxyBuilder;
+
+use support::{within, TestProxy};
+
+/// A fresh plugins directory for one test.
+fn plugins_dir(test: &str) -> Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("rower-plugins-{}-{}", process::id(), test));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn add_wat(dir: &Path, name: &str, wat: &str) -> Result<()> {
+    fs::write(dir.join(format!("{}.wasm", name)), wat::parse_str(wat)?)?;
+    Ok(())
+}
+
+/// Builds `examples/brand-plugin` for `wasm32-unknown-unknown`.
+fn build_brand_plugin() -> Result<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let target = root.join("target/plugins");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--release", "--target", "wasm32-unknown-unknown"])
+        .arg("--manifest-path")
+        .arg(root.join("examples/brand-plugin/Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target)
+        .status()?;
+    ensure!(
+        status.success(),
+        "building the example plugin failed, is the wasm32-unknown-unknown target installed?"
+    );
+    Ok(target.join("wasm32-unknown-unknown/release/brand_plugin.wasm"))
+}
+
+fn chat(message: &str) -> ChatMessage {
+    ChatMessage {
+        message: message.to_owned(),
+        timestamp: 0,
+        salt: 0,
+        signature: None,
+        message_count: 0,
+        acknowledged: Bytes::from_static(&[0; 3]),
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs the wasm32-unknown-unknown target, run with `cargo test -- --ignored`"]
+async fn brand_plugin() -> Result<()> {
+    let plugin = build_brand_plugin()?;
+    let dir = plugins_dir("brand")?;
+    fs::copy(plugin, dir.join("brand.wasm"))?;
+
+    let builder = ProxyBuilder::new().plugins(&dir);
+    let proxy = TestProxy::start_with(builder).await?;
+    let (mut client, mut session) = proxy.join("Steve").await?;
+
+    let mut brand = BytesMut::new();
+    brand.put_string("vanilla");
+    session
+        .send(PluginMessage {
+            channel: "minecraft:brand".to_owned(),
+            data: brand.freeze(),
+        })
+        .await?;
+
+    let mut message: PluginMessage = within(client.expect()).await?;
+    assert_eq!(message.channel, "minecraft:brand");
+    assert_eq!(message.data.get_string(32700)?, "Rower | vanilla inside a bike");
+
+    proxy.proxy.shutdown().await
+}
+
+/// Cancels every command and tells the player why over a plugin message.
+const GUARD: &str = r#"
+(module
+  (import "rower" "subscribe" (func $subscribe (param i32 i32)))
+  (import "rower" "get_field" (func $get_field (param i32 i32) (result i64)))
+  (import "rower" "set_field" (func $set_field (param i32 i32 i32 i32) (result i32)))
+  (import "rower" "component" (func $component (param i32 i32) (result i64)))
+  (import "rower" "send_plugin_message" (func $send (param i32 i32 i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (data (i32.const 0) "uuid")
+  (data (i32.const 16) "cancelled")
+  (data (i32.const 32) "true")
+  (data (i32.const 48) "&cNo commands")
+  (data (i32.const 64) "rower:denied")
+  (func (export "rower_abi_version") (result i32) (i32.const 1))
+  (func (export "rower_alloc") (param $len i32) (result i32)
+    (global.get $next)
+    (global.set $next (i32.add (global.get $next) (local.get $len))))
+  (func (export "rower_init")
+    (call $subscribe (i32.const 4) (i32.const 2)))
+  (func (export "rower_on_event") (param $event i32)
+    (local $uuid i64)
+    (local $json i64)
+    (drop (call $set_field (i32.const 16) (i32.const 9) (i32.const 32) (i32.const 4)))
+    (local.set $uuid (call $get_field (i32.const 0) (i32.const 4)))
+    (local.set $json (call $component (i32.const 48) (i32.const 13)))
+    (drop (call $send
+      (i32.wrap_i64 (i64.shr_u (local.get $uuid) (i64.const 32)))
+      (i32.wrap_i64 (local.get $uuid))
+      (i32.const 64) (i32.const 12)
+      (i32.wrap_i64 (i64.shr_u (local.get $json) (i64.const 32)))
+      (i32.wrap_i64 (local.get $json))))))
+"#;
+
+#[tokio::test]
+async fn host_functions() -> Result<()> {
+    let dir = plugins_dir("guard")?;
+    add_wat(&dir, "guard", GUARD)?;
+
+    let proxy = TestProxy::start_with(ProxyBuilder::new().plugins(&dir)).await?;
+    let (mut client, mut session) = proxy.join("Steve").await?;
+
+    client
+        .send(ChatCommand {
+            command: "stop".to_owned(),
+            timestamp: 0,
+            salt: 0,
+            arguments: Vec::new(),
+            message_count: 0,
+            acknowledged: Bytes::from_static(&[0; 3]),
+        })
+        .await?;
+    let message: PluginMessage = within(client.expect()).await?;
+    assert_eq!(message.channel, "rower:denied");
+    let reason: Component = serde_json::from_slice(&message.data)?;
+    assert_eq!(reason, Component::legacy("&cNo commands"));
+
+    // the command never reached the backend
+    client.send_raw(0x12, &[1]).await?;
+    assert_eq!(within(session.recv_raw()).await?.id(), 0x12);
+
+    proxy.proxy.shutdown().await
+}
+
+/// Never returns from a chat event.
+const SPIN: &str = r#"
+(module
+  (import "rower" "subscribe" (func $subscribe (param i32 i32)))
+  (memory (export "memory") 1)
+  (func (export "rower_abi_version") (result i32) (i32.const 1))
+  (func (export "rower_alloc") (param i32) (result i32) (i32.const 1024))
+  (func (export "rower_init") (call $subscribe (i32.const 3) (i32.const 2)))
+  (func (export "rower_on_event") (param i32) (loop $spin (br $spin))))
+"#;
+
+/// Would cancel chat, but asks for 64 MiB of memory.
+const GREEDY: &str = r#"
+(module
+  (import "rower" "subscribe" (func $subscribe (param i32 i32)))
+  (import "rower" "set_field" (func $set_field (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1024)
+  (data (i32.const 16) "cancelled")
+  (data (i32.const 32) "true")
+  (func (export "rower_abi_version") (result i32) (i32.const 1))
+  (func (export "rower_alloc") (param i32) (result i32) (i32.const 1024))
+  (func (export "rower_init") (call $subscribe (i32.const 3) (i32.const 2)))
+  (func (export "rower_on_event") (param i32)
+    (drop (call $set_field (i32.const 16) (i32.const 9) (i32.const 32) (i32.const 4)))))
+"#;
+
+#[tokio::test]
+async fn fuel_and_memory_limits() -> Result<()> {
+    let dir = plugins_dir("limits")?;
+    add_wat(&dir, "spin", SPIN)?;
+    add_wat(&dir, "greedy", GREEDY)?;
+
+    let proxy = TestProxy::start_with(ProxyBuilder::new().plugins(&dir)).await?;
+    let (mut client, mut session) = proxy.join("Steve").await?;
+
+    // the spinning plugin runs out of fuel and the greedy one never loaded, so chat goes through
+    client.send(chat("hello")).await?;
+    let message: ChatMessage = within(session.expect()).await?;
+    assert_eq!(message.message, "hello");
+
+    proxy.proxy.shutdown().await
+}
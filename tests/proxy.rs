@@ -0,0 +1,266 @@
+mod support;
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use bytes::BytesMut;
+use rower::component::Component;
+use rower::event::{DisconnectEvent, Priority};
+use rower::online::generate_offline_uuid;
+use rower::protocol::buffer::{BufExt, BufMutExt};
+use rower::protocol::packet::handshake::NextState;
+use rower::protocol::packet::login::Disconnect;
+use rower::protocol::packet::play::{
+    BossBar, BossBarAction, BossBarColor, BossBarDivision, JoinGame, PluginMessage, Respawn,
+};
+use rower::protocol::packet::status::{Ping, StatusRequest};
+use rower::protocol::packet::PacketType;
+use rower::protocol::{ProtocolVersion, State};
+use rower::ProxyBuilder;
+use uuid::Uuid;
+
+use support::{join_game, within, FakeClient, LoginResult, TestProxy, VERSION};
+
+fn brand(brand: &str) -> PluginMessage {
+    let mut data = BytesMut::new();
+    data.put_string(brand);
+    PluginMessage {
+        channel: "minecraft:brand".to_owned(),
+        data: data.freeze(),
+    }
+}
+
+#[tokio::test]
+async fn login_and_play() -> Result<()> {
+    let proxy = TestProxy::start().await?;
+
+    let backend = async {
+        let mut session = proxy.backend.accept().await?;
+        session.join(join_game(1, "minecraft:overworld")).await?;
+        Ok::<_, anyhow::Error>(session)
+    };
+    let (client, session) = within(async {
+        tokio::join!(FakeClient::expect_login(proxy.addr, "Steve"), backend)
+    })
+    .await;
+    let ((mut client, success), mut session) = (client?, session?);
+
+    let uuid = generate_offline_uuid(&"Steve".to_owned());
+    assert_eq!(session.handshake.protocol, i32::from(VERSION));
+    assert_eq!(session.handshake.state, NextState::Login);
+    assert_eq!(session.login_start.username, "Steve");
+    assert_eq!(session.login_start.uuid, Some(uuid));
+    assert_eq!(success.username, "Steve");
+    assert_eq!(success.uuid, uuid);
+
+    // big enough to stay compressed on its way through
+    let join: JoinGame = within(client.expect()).await?;
+    assert_eq!(join, join_game(1, "minecraft:overworld"));
+
+    session.send(brand("vanilla")).await?;
+    let mut message: PluginMessage = within(client.expect()).await?;
+    assert_eq!(message.data.get_string(32700)?, "vanilla inside a bike");
+
+    client.send_raw(0x12, &[1, 2, 3]).await?;
+    let packet = within(session.recv_raw()).await?;
+    assert_eq!(&packet.buffer[..], &[0x12, 1, 2, 3]);
+
+    let payload = vec![42; 4096];
+    client.send_raw(0x0d, &payload).await?;
+    let mut packet = within(session.recv_raw()).await?;
+    assert_eq!(packet.id(), 0x0d);
+    assert_eq!(&packet.data()?[..], &payload[..]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn backend_kick_during_login() -> Result<()> {
+    let proxy = TestProxy::start().await?;
+    let reason = Component::text("You are not whitelisted");
+
+    let backend = async {
+        let session = proxy.backend.accept().await?;
+        session.kick(reason.clone()).await
+    };
+    let (result, kicked) = within(async {
+        tokio::join!(FakeClient::login(proxy.addr, "Alex"), backend)
+    })
+    .await;
+    kicked?;
+
+    match result? {
+        LoginResult::Disconnected(got) => assert_eq!(got, reason),
+        LoginResult::Success(..) => panic!("login should have been denied"),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn switch_to_fallback_on_kick() -> Result<()> {
+    let proxy = TestProxy::start().await?;
+
+    let backend = async {
+        let mut session = proxy.backend.accept().await?;
+        session.join(join_game(1, "minecraft:lobby")).await?;
+        Ok::<_, anyhow::Error>(session)
+    };
+    let (client, session) = within(async {
+        tokio::join!(FakeClient::expect_login(proxy.addr, "Steve"), backend)
+    })
+    .await;
+    let ((mut client, _), mut session) = (client?, session?);
+    within(client.expect::<JoinGame>()).await?;
+
+    let boss_bar = Uuid::from_u128(7);
+    session
+        .send(BossBar {
+            uuid: boss_bar,
+            action: BossBarAction::Add {
+                title: Component::text("Lobby"),
+                health: 1.0,
+                color: BossBarColor::Pink,
+                division: BossBarDivision::None,
+                flags: 0,
+            },
+        })
+        .await?;
+    within(client.expect::<BossBar>()).await?;
+
+    session
+        .send(Disconnect {
+            reason: Component::text("Server closed"),
+        })
+        .await?;
+
+    let mut fallback = within(proxy.fallback.accept()).await?;
+    assert_eq!(fallback.login_start.username, "Steve");
+    fallback.join(join_game(2, "minecraft:fallback")).await?;
+    // the switch packets are queued, whatever the fallback sends next flushes them
+    fallback.send(brand("fallback")).await?;
+
+    let join: JoinGame = within(client.expect()).await?;
+    assert_eq!(join.entity_id, 2);
+    let respawn: Respawn = within(client.expect()).await?;
+    assert_eq!(respawn.dimension_name, "minecraft:fallback");
+
+    match within(client.recv()).await? {
+        PacketType::BossBar(BossBar {
+            uuid,
+            action: BossBarAction::Remove,
+        }) => assert_eq!(uuid, boss_bar),
+        _ => panic!("expected the lobby boss bar to be removed"),
+    }
+    let mut message: PluginMessage = within(client.expect()).await?;
+    assert_eq!(message.data.get_string(32700)?, "fallback inside a bike");
+
+    // and the client is now talking to the fallback
+    client.send_raw(0x12, &[9]).await?;
+    let packet = within(fallback.recv_raw()).await?;
+    assert_eq!(&packet.buffer[..], &[0x12, 9]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn status_ping() -> Result<()> {
+    let proxy = TestProxy::start().await?;
+
+    let mut client = FakeClient::connect(proxy.addr, NextState::Status).await?;
+    client.conn.change_state(State::Status);
+    client.send(StatusRequest).await?;
+
+    let mut response = within(client.conn.recv_raw_packet()).await?;
+    assert_eq!(response.id(), 0x00);
+    let mut data = response.data()?;
+    let status: serde_json::Value = serde_json::from_str(&data.get_string(32767)?)?;
+    assert!(status["version"]["protocol"].is_number());
+
+    client.send(Ping(1234)).await?;
+    let Ping(payload) = within(client.expect()).await?;
+    assert_eq!(payload, 1234);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn graceful_shutdown() -> Result<()> {
+    let proxy = TestProxy::start().await?;
+
+    let backend = async {
+        let mut session = proxy.backend.accept().await?;
+        session.join(join_game(1, "minecraft:overworld")).await?;
+        Ok::<_, anyhow::Error>(session)
+    };
+    let (client, session) = within(async {
+        tokio::join!(FakeClient::expect_login(proxy.addr, "Steve"), backend)
+    })
+    .await;
+    let ((mut client, _), _session) = (client?, session?);
+    within(client.expect::<JoinGame>()).await?;
+
+    let addr = proxy.addr;
+    within(proxy.proxy.shutdown()).await?;
+
+    match within(client.recv()).await? {
+        PacketType::Disconnect(Disconnect { reason }) => {
+            assert_eq!(reason, Component::text("Proxy is shutting down"))
+        }
+        _ => panic!("expected a disconnect"),
+    }
+    assert!(FakeClient::connect(addr, NextState::Status).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn shutdown_message_and_deadline() -> Result<()> {
+    // a disconnect handler that keeps the connection task busy past the deadline
+    let builder = ProxyBuilder::new()
+        .shutdown_message(Component::text("Restarting, be right back"))
+        .shutdown_timeout(Duration::from_millis(300))
+        .on(Priority::Normal, |event: DisconnectEvent| async {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            event
+        });
+    let proxy = TestProxy::start_with(builder).await?;
+    let (mut client, _session) = proxy.join("Steve").await?;
+
+    let started = Instant::now();
+    within(proxy.proxy.shutdown()).await?;
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    let kick: Disconnect = within(client.expect()).await?;
+    assert_eq!(kick.reason, Component::text("Restarting, be right back"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_clients_that_need_the_configuration_state() -> Result<()> {
+    let proxy = TestProxy::start().await?;
+
+    for version in [ProtocolVersion::V1_20_2, ProtocolVersion::V1_20_3] {
+        let client = FakeClient::connect_as(proxy.addr, NextState::Login, version).await?;
+        match within(client.log_in("Alex")).await? {
+            LoginResult::Disconnected(reason) => {
+                assert_eq!(reason, Component::text("We support versions 1.19.2 to 1.20.1"))
+            }
+            LoginResult::Success(..) => panic!("{:?} was let in", version),
+        }
+    }
+    // the latest version without it still gets in
+    let client = async {
+        let client = FakeClient::connect_as(proxy.addr, NextState::Login, ProtocolVersion::V1_20);
+        client.await?.log_in("Steve").await
+    };
+    let backend = async {
+        let mut session = proxy.backend.accept().await?;
+        session.join(join_game(1, "minecraft:overworld")).await?;
+        Ok::<_, anyhow::Error>(session)
+    };
+    let (client, session) = within(async { tokio::join!(client, backend) }).await;
+    session?;
+    assert!(matches!(client?, LoginResult::Success(..)));
+    Ok(())
+}
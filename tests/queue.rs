@@ -0,0 +1,237 @@
+mod support;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use bytes::Bytes;
+use rower::component::Component;
+use rower::hooks::Hooks;
+use rower::protocol::packet::login::Disconnect;
+use rower::protocol::packet::PacketType;
+use rower::protocol::packet::play::{
+    BossBar, BossBarAction, ChatCommand, JoinGame, KeepAlive, PlayerPosition, Respawn,
+};
+use rower::protocol::wrappers::Player;
+use rower::queue::{QueueSettings, BOSS_BAR};
+use rower::ProxyBuilder;
+
+use support::{join_game, within, FakeBackend, FakeClient, LoginResult, TestProxy};
+
+const VIP: &str = "rower.queue.vip";
+
+struct VipHooks;
+
+impl Hooks for VipHooks {
+    fn has_permission(&self, player: &Player, permission: &str) -> bool {
+        permission == VIP && player.username.starts_with("Vip")
+    }
+}
+
+fn queued(retry: Duration) -> ProxyBuilder {
+    ProxyBuilder::new().hooks(VipHooks).queue(QueueSettings {
+        priorities: vec![VIP.to_owned()],
+        retry,
+    })
+}
+
+async fn refuse(backend: &FakeBackend) -> Result<()> {
+    let session = backend.accept().await?;
+    session.kick(Component::text("The server is full")).await
+}
+
+/// Logs in while the backend is full and reads the world the proxy holds the player in.
+async fn join_queue(proxy: &TestProxy, username: &str) -> Result<(FakeClient, BossBar)> {
+    let (client, refused) = within(async {
+        tokio::join!(FakeClient::expect_login(proxy.addr, username), refuse(&proxy.backend))
+    })
+    .await;
+    refused?;
+    let (mut client, _) = client?;
+
+    let world: JoinGame = within(client.expect()).await?;
+    assert_eq!(world.gamemode, 3, "held players are spectators");
+    within(client.expect::<PlayerPosition>()).await?;
+    let boss_bar: BossBar = within(client.expect()).await?;
+    assert_eq!(boss_bar.uuid, BOSS_BAR);
+    within(client.expect::<KeepAlive>()).await?;
+    Ok((client, boss_bar))
+}
+
+fn title(boss_bar: &BossBar) -> String {
+    match &boss_bar.action {
+        BossBarAction::Add { title, .. } | BossBarAction::UpdateTitle(title) => title.to_plain(),
+        action => panic!("no title in {:?}", action),
+    }
+}
+
+fn spawn() -> ChatCommand {
+    ChatCommand {
+        command: "spawn".to_owned(),
+        timestamp: 0,
+        salt: 0,
+        arguments: Vec::new(),
+        message_count: 0,
+        acknowledged: Bytes::from_static(&[0; 3]),
+    }
+}
+
+/// Reads the switch to the server with `entity_id`, and the queue's boss bar going away.
+async fn expect_switch(client: &mut FakeClient, entity_id: i32) -> Result<()> {
+    assert_eq!(within(client.expect::<JoinGame>()).await?.entity_id, entity_id);
+    within(client.expect::<Respawn>()).await?;
+    let removed: BossBar = within(client.expect()).await?;
+    assert_eq!((removed.uuid, removed.action), (BOSS_BAR, BossBarAction::Remove));
+    Ok(())
+}
+
+#[tokio::test]
+async fn holds_players_until_the_server_lets_them_in() -> Result<()> {
+    let proxy = TestProxy::start_with(queued(Duration::from_millis(50))).await?;
+    // the backend's world is what later players are held in
+    let (_steve, _steve_session) = proxy.join("Steve").await?;
+
+    let (mut alex, boss_bar) = join_queue(&proxy, "Alex").await?;
+    assert_eq!(title(&boss_bar), "Position 1 of 1 in the queue for backend");
+
+    // still full on the first try, in on the second
+    within(refuse(&proxy.backend)).await?;
+    let mut session = within(proxy.backend.accept()).await?;
+    session.join(join_game(2, "minecraft:the_nether")).await?;
+
+    let removed: BossBar = within(alex.expect()).await?;
+    assert_eq!((removed.uuid, removed.action), (BOSS_BAR, BossBarAction::Remove));
+    let join: JoinGame = within(alex.expect()).await?;
+    assert_eq!(join.entity_id, 2);
+    let respawn: Respawn = within(alex.expect()).await?;
+    assert_eq!(respawn.dimension_name, "minecraft:the_nether");
+    assert!(proxy.proxy.queues().unwrap().waiting(proxy.backend.addr()).is_empty());
+
+    let command = spawn();
+    alex.send(command.clone()).await?;
+    assert_eq!(within(session.expect::<ChatCommand>()).await?, command);
+    Ok(())
+}
+
+#[tokio::test]
+async fn priority_players_go_first_once_a_slot_frees_up() -> Result<()> {
+    // only a player leaving the server makes the queue try it
+    let proxy = TestProxy::start_with(queued(Duration::from_secs(60))).await?;
+    let (steve, _steve_session) = proxy.join("Steve").await?;
+    let queues = proxy.proxy.queues().unwrap();
+    let server = proxy.backend.addr();
+
+    let (mut alex, _) = join_queue(&proxy, "Alex").await?;
+    let (mut vip, boss_bar) = join_queue(&proxy, "VipBob").await?;
+    assert_eq!(title(&boss_bar), "Position 1 of 2 in the queue for backend");
+    let moved: BossBar = within(alex.expect()).await?;
+    assert_eq!(title(&moved), "Position 2 of 2 in the queue for backend");
+    within(alex.expect::<BossBar>()).await?;
+    let waiting = queues.waiting(server);
+    assert_eq!(waiting.len(), 2);
+    assert_eq!(queues.position(server, &waiting[1]), Some((2, 2)));
+
+    drop(steve);
+    let mut session = within(proxy.backend.accept()).await?;
+    assert_eq!(session.login_start.username, "VipBob");
+    session.join(join_game(3, "minecraft:overworld")).await?;
+    within(vip.expect::<BossBar>()).await?;
+    assert_eq!(within(vip.expect::<JoinGame>()).await?.entity_id, 3);
+
+    // Alex is at the front now and tries right away
+    let moved: BossBar = within(alex.expect()).await?;
+    assert_eq!(title(&moved), "Position 1 of 1 in the queue for backend");
+    let session = within(proxy.backend.accept()).await?;
+    assert_eq!(session.login_start.username, "Alex");
+    Ok(())
+}
+
+#[tokio::test]
+async fn shutdown_disconnects_players_held_at_login() -> Result<()> {
+    let proxy = TestProxy::start_with(queued(Duration::from_secs(60))).await?;
+    let (_steve, _steve_session) = proxy.join("Steve").await?;
+    // a few of them, each one held could lose a race against the shutdown
+    let mut held = Vec::new();
+    for username in ["Alex", "Bob", "Carl", "Dave"] {
+        held.push(join_queue(&proxy, username).await?.0);
+    }
+
+    within(proxy.proxy.shutdown()).await?;
+    for client in &mut held {
+        // past the boss bar updates of the ones queued later
+        let reason = within(async {
+            loop {
+                if let PacketType::Disconnect(Disconnect { reason }) = client.recv().await? {
+                    return Ok::<_, anyhow::Error>(reason);
+                }
+            }
+        });
+        assert_eq!(reason.await?, Component::text("Proxy is shutting down"));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_without_a_world_to_hold_players_in() -> Result<()> {
+    let proxy = TestProxy::start_with(queued(Duration::from_millis(50))).await?;
+
+    let (client, refused) = within(async {
+        tokio::join!(FakeClient::login(proxy.addr, "Alex"), refuse(&proxy.backend))
+    })
+    .await;
+    refused?;
+    match client? {
+        LoginResult::Disconnected(reason) => assert_eq!(reason.to_plain(), "The server is full"),
+        LoginResult::Success(..) => panic!("logged in without a world to be held in"),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn switches_wait_on_the_current_server() -> Result<()> {
+    let proxy = TestProxy::start_with(queued(Duration::from_millis(50))).await?;
+    let (mut steve, mut session) = proxy.join("Steve").await?;
+
+    // `/send`, the API and BungeeCord `Connect` all switch through the online player
+    let online = proxy.proxy.players().find("Steve").unwrap();
+    online.connect(proxy.fallback.addr())?;
+    within(refuse(&proxy.fallback)).await?;
+    let boss_bar: BossBar = within(steve.expect()).await?;
+    assert_eq!(title(&boss_bar), "Position 1 of 1 in the queue for fallback");
+
+    // still playing on the backend meanwhile
+    steve.send(spawn()).await?;
+    assert_eq!(within(session.expect::<ChatCommand>()).await?, spawn());
+
+    let mut fallback = within(proxy.fallback.accept()).await?;
+    fallback.join(join_game(2, "minecraft:the_nether")).await?;
+    expect_switch(&mut steve, 2).await?;
+    assert!(proxy.proxy.queues().unwrap().waiting(proxy.fallback.addr()).is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn kicked_players_wait_for_a_full_fallback() -> Result<()> {
+    let proxy = TestProxy::start_with(queued(Duration::from_millis(50))).await?;
+    let (mut steve, session) = proxy.join("Steve").await?;
+
+    session.kick(Component::text("Restarting")).await?;
+    within(refuse(&proxy.fallback)).await?;
+    let boss_bar: BossBar = within(steve.expect()).await?;
+    assert_eq!(title(&boss_bar), "Position 1 of 1 in the queue for fallback");
+    // the proxy keeps the client alive in the world it was kicked from
+    let keepalive: KeepAlive = within(steve.expect()).await?;
+    steve.send(keepalive).await?;
+    steve.send(spawn()).await?;
+
+    // still full on the first try, in on the second
+    within(refuse(&proxy.fallback)).await?;
+    let mut fallback = within(proxy.fallback.accept()).await?;
+    fallback.join(join_game(2, "minecraft:the_nether")).await?;
+    // the switch is flushed with the boss bar going away
+    expect_switch(&mut steve, 2).await?;
+
+    // packets sent while waiting were dropped, the fallback gets the ones after
+    steve.send(spawn()).await?;
+    assert_eq!(within(fallback.expect::<ChatCommand>()).await?, spawn());
+    Ok(())
+}
@@ -0,0 +1,385 @@
+//! Scripted stand-ins for a Minecraft client and backend server, so proxy flows
+//! can be tested without running real servers.
+#![allow(dead_code)]
+
+use std::future::Future;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use bytes::{BufMut, BytesMut};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+
+use rower::component::Component;
+use rower::config::Config;
+use rower::haproxy;
+use rower::protocol::codec::connection::Connection;
+use rower::protocol::nbt::{Compound, Tag};
+use rower::protocol::packet::handshake::{Handshake, NextState};
+use rower::protocol::packet::login::{LoginPluginResponse, LoginStart, LoginSuccess, SetCompression};
+use rower::protocol::packet::play::JoinGame;
+use rower::protocol::packet::{Packet, PacketType, RawPacket};
+use rower::protocol::{Direction, ProtocolVersion, State};
+use rower::throttle::Limits;
+use rower::proxy::Timeouts;
+use rower::{Proxy, ProxyBuilder};
+
+/// Version spoken by the fake client and backend, the one the play registry is built for.
+pub const VERSION: ProtocolVersion = ProtocolVersion::V1_19_4;
+
+pub const COMPRESSION_THRESHOLD: i32 = 64;
+
+/// Fails the test instead of hanging when the proxy does not do what the script expects.
+pub async fn within<F: Future>(future: F) -> F::Output {
+    tokio::time::timeout(Duration::from_secs(5), future)
+        .await
+        .expect("timed out")
+}
+
+/// The config test proxies run with, reaching `backend` and `fallback`.
+pub fn config(backend: SocketAddr, fallback: SocketAddr) -> Config {
+    Config {
+        compression_threshold: COMPRESSION_THRESHOLD,
+        online: false,
+        backend_server: backend,
+        fallback_server: fallback,
+        ..Default::default()
+    }
+}
+
+/// What a test changes on the proxy, everything else keeps the builder's defaults.
+#[derive(Default)]
+pub struct Setup {
+    pub throttle: Option<Limits>,
+    pub timeouts: Option<Timeouts>,
+    /// PROXY protocol headers from clients and to backends.
+    pub proxy_protocol: bool,
+    /// Serves the admin API with this token on an ephemeral local port.
+    pub api_token: Option<&'static str>,
+}
+
+impl Setup {
+    fn apply(self, mut builder: ProxyBuilder) -> ProxyBuilder {
+        if let Some(limits) = self.throttle {
+            builder = builder.throttle(limits);
+        }
+        if let Some(timeouts) = self.timeouts {
+            builder = builder.timeouts(timeouts);
+        }
+        if self.proxy_protocol {
+            builder = builder.proxy_protocol().backend_proxy_protocol();
+        }
+        #[cfg(feature = "api")]
+        if let Some(token) = self.api_token {
+            builder = builder.api((Ipv4Addr::LOCALHOST, 0).into(), token);
+        }
+        builder
+    }
+}
+
+pub struct TestProxy {
+    pub proxy: Proxy,
+    pub addr: SocketAddr,
+    pub backend: FakeBackend,
+    pub fallback: FakeBackend,
+}
+
+impl TestProxy {
+    /// Starts the proxy on an ephemeral port of the current runtime, with
+    /// `backend` and `fallback` configured as its servers.
+    pub async fn start() -> Result<Self> {
+        Self::start_with(ProxyBuilder::new()).await
+    }
+
+    /// Same as [`TestProxy::start`] with the settings of `setup`.
+    pub async fn start_setup(setup: Setup) -> Result<Self> {
+        Self::start_with(setup.apply(ProxyBuilder::new())).await
+    }
+
+    /// Same as [`TestProxy::start`] with a builder that already has hooks or event handlers.
+    pub async fn start_with(builder: ProxyBuilder) -> Result<Self> {
+        let backend = FakeBackend::bind().await?;
+        let fallback = FakeBackend::bind().await?;
+        let config = config(backend.addr(), fallback.addr());
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let proxy = builder.config(config).listener(listener).start().await?;
+
+        Ok(Self {
+            addr: proxy.local_addr(),
+            proxy,
+            backend,
+            fallback,
+        })
+    }
+}
+
+impl TestProxy {
+    /// Logs `username` in through the proxy onto `backend` and waits until the client got the join game.
+    pub async fn join(&self, username: &str) -> Result<(FakeClient, BackendSession)> {
+        let backend = async {
+            let mut session = self.backend.accept().await?;
+            session.join(join_game(1, "minecraft:overworld")).await?;
+            Ok::<_, anyhow::Error>(session)
+        };
+        let (client, session) =
+            within(async { tokio::join!(FakeClient::expect_login(self.addr, username), backend) }).await;
+        let ((mut client, _), session) = (client?, session?);
+
+        within(client.expect::<JoinGame>()).await?;
+        Ok((client, session))
+    }
+}
+
+pub enum LoginResult {
+    Success(FakeClient, LoginSuccess),
+    Disconnected(Component),
+}
+
+/// Client side of the proxy, sends serverbound and receives clientbound packets.
+pub struct FakeClient {
+    pub conn: Connection,
+}
+
+impl FakeClient {
+    pub async fn connect(proxy: SocketAddr, state: NextState) -> Result<Self> {
+        Self::connect_with_header(proxy, state, &[]).await
+    }
+
+    /// Like [`FakeClient::connect`], sending `header` (a PROXY protocol one) before the handshake.
+    pub async fn connect_with_header(proxy: SocketAddr, state: NextState, header: &[u8]) -> Result<Self> {
+        Self::open(proxy, state, header, VERSION).await
+    }
+
+    /// Like [`FakeClient::connect`], speaking `version` instead of [`VERSION`].
+    pub async fn connect_as(proxy: SocketAddr, state: NextState, version: ProtocolVersion) -> Result<Self> {
+        Self::open(proxy, state, &[], version).await
+    }
+
+    async fn open(
+        proxy: SocketAddr,
+        state: NextState,
+        header: &[u8],
+        version: ProtocolVersion,
+    ) -> Result<Self> {
+        let mut stream = TcpStream::connect(proxy).await?;
+        stream.write_all(header).await?;
+        let mut conn = Connection::new(stream, Direction::Serverbound);
+        conn.protocol = version;
+        conn.queue_packet(Handshake {
+            protocol: version.into(),
+            server_address: proxy.ip().to_string(),
+            port: proxy.port(),
+            state,
+        })
+        .await?;
+
+        Ok(Self { conn })
+    }
+
+    /// Logs in offline as `username`, following whatever compression the proxy asks for.
+    pub async fn login(proxy: SocketAddr, username: &str) -> Result<LoginResult> {
+        Self::connect(proxy, NextState::Login).await?.log_in(username).await
+    }
+
+    /// Same as [`FakeClient::login`] on a client connected with [`NextState::Login`].
+    pub async fn log_in(self, username: &str) -> Result<LoginResult> {
+        let mut client = self;
+        client.conn.change_state(State::Login);
+        client
+            .conn
+            .send_packet(LoginStart {
+                username: username.to_owned(),
+                uuid: None,
+            })
+            .await?;
+
+        loop {
+            match client.conn.auto_read().await? {
+                PacketType::SetCompression(SetCompression { threshold }) => {
+                    if threshold > -1 {
+                        client.conn.enable_compression(threshold as u32);
+                    }
+                }
+                // like the vanilla client, which knows no login plugin channels
+                PacketType::LoginPluginRequest(request) => {
+                    client
+                        .conn
+                        .send_packet(LoginPluginResponse {
+                            message_id: request.message_id,
+                            successful: false,
+                            data: None,
+                        })
+                        .await?;
+                }
+                PacketType::LoginSuccess(success) => {
+                    client.conn.change_state(State::Play);
+                    return Ok(LoginResult::Success(client, success));
+                }
+                PacketType::Disconnect(disconnect) => {
+                    return Ok(LoginResult::Disconnected(disconnect.reason))
+                }
+                _ => bail!("unexpected packet during login"),
+            }
+        }
+    }
+
+    pub async fn expect_login(proxy: SocketAddr, username: &str) -> Result<(Self, LoginSuccess)> {
+        match Self::login(proxy, username).await? {
+            LoginResult::Success(client, success) => Ok((client, success)),
+            LoginResult::Disconnected(reason) => bail!("disconnected during login: {:?}", reason),
+        }
+    }
+
+    pub async fn send<T: Packet + 'static>(&mut self, packet: T) -> Result<()> {
+        self.conn.send_packet(packet).await
+    }
+
+    pub async fn send_raw(&mut self, id: u8, data: &[u8]) -> Result<()> {
+        self.conn.send_raw_packet(raw_packet(id, data)).await
+    }
+
+    pub async fn expect<T: Packet + 'static>(&mut self) -> Result<T> {
+        self.conn.recv_packet().await
+    }
+
+    pub async fn recv(&mut self) -> Result<PacketType> {
+        self.conn.auto_read().await
+    }
+}
+
+pub struct FakeBackend {
+    listener: TcpListener,
+}
+
+impl FakeBackend {
+    async fn bind() -> Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?,
+        })
+    }
+
+    /// The address the proxy is configured to reach this backend at.
+    pub fn addr(&self) -> SocketAddr {
+        self.listener.local_addr().expect("a bound listener")
+    }
+
+    /// Waits for the proxy to connect and reads its handshake and login start.
+    pub async fn accept(&self) -> Result<BackendSession> {
+        let (stream, _) = self.listener.accept().await?;
+        Self::session(stream).await
+    }
+
+    /// Like [`FakeBackend::accept`], for a proxy that sends a PROXY protocol header first.
+    pub async fn accept_proxied(&self) -> Result<(Option<SocketAddr>, BackendSession)> {
+        let (mut stream, _) = self.listener.accept().await?;
+        let client = haproxy::read_header(&mut stream).await?;
+        Ok((client, Self::session(stream).await?))
+    }
+
+    async fn session(stream: TcpStream) -> Result<BackendSession> {
+        let mut conn = Connection::new(stream, Direction::Clientbound);
+
+        let handshake: Handshake = conn.recv_packet().await?;
+        conn.protocol = handshake.protocol.into();
+        conn.change_state(State::Login);
+        let login_start = conn.recv_packet().await?;
+
+        Ok(BackendSession {
+            conn,
+            handshake,
+            login_start,
+        })
+    }
+}
+
+/// One proxied player as seen by the backend, sends clientbound and receives serverbound packets.
+pub struct BackendSession {
+    pub conn: Connection,
+    pub handshake: Handshake,
+    pub login_start: LoginStart,
+}
+
+impl BackendSession {
+    /// Finishes the login, compressing with `threshold` if given, and enters play.
+    pub async fn accept_login(&mut self, threshold: Option<i32>) -> Result<()> {
+        if let Some(threshold) = threshold {
+            self.conn.send_packet(SetCompression { threshold }).await?;
+            self.conn.enable_compression(threshold as u32);
+        }
+
+        self.conn
+            .send_packet(LoginSuccess {
+                uuid: self.login_start.uuid.unwrap_or_default(),
+                username: self.login_start.username.clone(),
+                properties: Vec::new(),
+            })
+            .await?;
+        self.conn.change_state(State::Play);
+        Ok(())
+    }
+
+    /// Logs in and sends the join game, like a server does once the player is in.
+    pub async fn join(&mut self, join: JoinGame) -> Result<()> {
+        self.accept_login(Some(COMPRESSION_THRESHOLD)).await?;
+        self.send(join).await
+    }
+
+    pub async fn kick(self, reason: Component) -> Result<()> {
+        self.conn.disconnect(reason).await
+    }
+
+    pub async fn send<T: Packet + 'static>(&mut self, packet: T) -> Result<()> {
+        self.conn.send_packet(packet).await
+    }
+
+    pub async fn send_raw(&mut self, id: u8, data: &[u8]) -> Result<()> {
+        self.conn.send_raw_packet(raw_packet(id, data)).await
+    }
+
+    pub async fn expect<T: Packet + 'static>(&mut self) -> Result<T> {
+        self.conn.recv_packet().await
+    }
+
+    pub async fn recv_raw(&mut self) -> Result<RawPacket> {
+        let mut packet = self.conn.recv_raw_packet().await?;
+        packet.decompress()?;
+        Ok(packet)
+    }
+}
+
+pub fn raw_packet(id: u8, data: &[u8]) -> RawPacket {
+    let mut buffer = BytesMut::with_capacity(data.len() + 1);
+    buffer.put_u8(id);
+    buffer.put_slice(data);
+    RawPacket::from_buffer(buffer)
+}
+
+/// A join game with a registry big enough to be sent compressed.
+pub fn join_game(entity_id: i32, dimension: &str) -> JoinGame {
+    let registry = Compound::new(
+        String::new(),
+        vec![("minecraft:padding".to_owned(), Tag::ByteArray(vec![7; 1024]))],
+    );
+
+    JoinGame {
+        entity_id,
+        is_hardcore: false,
+        gamemode: 0,
+        previous_gamemode: 0,
+        dimensions_names: vec![dimension.to_owned()],
+        registry,
+        dimension_type: "minecraft:overworld".to_owned(),
+        dimension_name: dimension.to_owned(),
+        hashed_seed: 0,
+        max_players: 20,
+        view_distance: 10,
+        simulation_distance: 10,
+        reduced_debug_info: false,
+        respawn_screen: true,
+        is_debug: false,
+        is_flat: false,
+        last_death: None,
+    }
+}
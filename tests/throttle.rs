@@ -0,0 +1,166 @@
+mod support;
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use rower::component::Component;
+use rower::protocol::packet::handshake::NextState;
+use rower::protocol::packet::login::{LoginPluginRequest, LoginPluginResponse, LoginStart};
+use rower::protocol::packet::status::{Ping, StatusRequest};
+use rower::protocol::packet::PacketType;
+use rower::protocol::State;
+use rower::throttle::{Limits, Verification};
+
+use support::{within, FakeClient, LoginResult, Setup, TestProxy};
+
+async fn ping(proxy: SocketAddr) -> Result<()> {
+    let mut client = FakeClient::connect(proxy, NextState::Status).await?;
+    client.conn.change_state(State::Status);
+    client.send(StatusRequest).await?;
+    within(client.conn.recv_raw_packet()).await?;
+    client.send(Ping(1)).await?;
+    within(client.expect::<Ping>()).await?;
+    Ok(())
+}
+
+async fn denied(proxy: SocketAddr, username: &str) -> Result<Component> {
+    match within(FakeClient::login(proxy, username)).await? {
+        LoginResult::Disconnected(reason) => Ok(reason),
+        LoginResult::Success(..) => panic!("{} was let in", username),
+    }
+}
+
+/// Starts a login and returns the verification request, or nothing when the proxy goes on to the backend.
+async fn login_start(proxy: &TestProxy, username: &str) -> Result<(FakeClient, Option<LoginPluginRequest>)> {
+    let mut client = FakeClient::connect(proxy.addr, NextState::Login).await?;
+    client.conn.change_state(State::Login);
+    client
+        .send(LoginStart {
+            username: username.to_owned(),
+            uuid: None,
+        })
+        .await?;
+    let request = within(async {
+        tokio::select! {
+            packet = client.conn.auto_read() => match packet? {
+                PacketType::LoginPluginRequest(request) => Ok(Some(request)),
+                _ => panic!("expected a verification request"),
+            },
+            session = proxy.backend.accept() => session.map(|_| None),
+        }
+    })
+    .await?;
+    Ok((client, request))
+}
+
+#[tokio::test]
+async fn connections_per_ip() -> Result<()> {
+    let proxy = TestProxy::start_setup(Setup {
+        throttle: Some(Limits {
+            connections_per_ip: 2,
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+    .await?;
+
+    ping(proxy.addr).await?;
+    ping(proxy.addr).await?;
+    // the third connection in the window is closed right away
+    assert!(ping(proxy.addr).await.is_err());
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn login_limit_and_required_ping() -> Result<()> {
+    let proxy = TestProxy::start_setup(Setup {
+        throttle: Some(Limits {
+            window: Duration::from_secs(60),
+            logins: 2,
+            require_ping: true,
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+    .await?;
+
+    assert_eq!(
+        denied(proxy.addr, "Steve").await?,
+        Component::text("Add the server to your server list and refresh it before joining")
+    );
+    ping(proxy.addr).await?;
+    proxy.join("Steve").await?;
+
+    assert_eq!(
+        denied(proxy.addr, "Alex").await?,
+        Component::text("Too many players are joining, try again in a moment")
+    );
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn verification_limbo() -> Result<()> {
+    let proxy = TestProxy::start_setup(Setup {
+        throttle: Some(Limits {
+            verification: Verification::On,
+            verification_delay: Duration::from_millis(300),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+    .await?;
+
+    // a bot that does not answer like the vanilla client
+    let (mut bot, request) = login_start(&proxy, "Bot").await?;
+    let request = request.expect("no verification request");
+    bot.send(LoginPluginResponse {
+        message_id: request.message_id + 1,
+        successful: false,
+        data: None,
+    })
+    .await?;
+    match within(bot.recv()).await? {
+        PacketType::Disconnect(disconnect) => {
+            assert_eq!(disconnect.reason, Component::text("Verification failed, try again"))
+        }
+        _ => panic!("expected the bot to be disconnected"),
+    }
+
+    // a real client is held for the delay before it reaches the backend
+    let started = Instant::now();
+    proxy.join("Steve").await?;
+    assert!(started.elapsed() >= Duration::from_millis(300));
+
+    // and is not asked again
+    let (_client, request) = login_start(&proxy, "Alex").await?;
+    assert!(request.is_none());
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn login_limit_turns_verification_on() -> Result<()> {
+    let proxy = TestProxy::start_setup(Setup {
+        throttle: Some(Limits {
+            window: Duration::from_millis(500),
+            logins: 1,
+            verification: Verification::Auto,
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+    .await?;
+
+    let (_client, request) = login_start(&proxy, "Steve").await?;
+    assert!(request.is_none());
+    denied(proxy.addr, "Alex").await?;
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let (_client, request) = login_start(&proxy, "Alex").await?;
+    assert!(request.is_some());
+
+    proxy.proxy.shutdown().await
+}
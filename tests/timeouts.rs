@@ -0,0 +1,76 @@
+mod support;
+
+use std::time::Duration;
+
+use anyhow::Result;
+use rower::component::Component;
+use rower::protocol::packet::handshake::NextState;
+use rower::protocol::packet::login::Disconnect;
+use rower::protocol::packet::play::KeepAlive;
+use rower::protocol::State;
+use rower::proxy::Timeouts;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+use support::{within, FakeClient, Setup, TestProxy};
+
+const SHORT: Timeouts = Timeouts {
+    handshake: Some(Duration::from_millis(200)),
+    status: Some(Duration::from_millis(200)),
+    login: Some(Duration::from_millis(300)),
+    keepalive: Some(Duration::from_millis(300)),
+};
+
+#[tokio::test]
+async fn silent_connections_are_closed() -> Result<()> {
+    let setup = Setup { timeouts: Some(SHORT), ..Default::default() };
+    let proxy = TestProxy::start_setup(setup).await?;
+
+    // a socket that never sends its handshake
+    let mut socket = TcpStream::connect(proxy.addr).await?;
+    assert_eq!(within(socket.read(&mut [0; 16])).await?, 0);
+
+    // a login that stops after the handshake
+    let mut client = FakeClient::connect(proxy.addr, NextState::Login).await?;
+    client.conn.change_state(State::Login);
+    assert!(within(client.conn.recv_raw_packet()).await.is_err());
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn frozen_backend() -> Result<()> {
+    let setup = Setup { timeouts: Some(SHORT), ..Default::default() };
+    let proxy = TestProxy::start_setup(setup).await?;
+    let (mut client, _session) = proxy.join("Steve").await?;
+
+    let kick: Disconnect = within(client.expect()).await?;
+    assert_eq!(kick.reason, Component::text("The server stopped responding"));
+
+    proxy.proxy.shutdown().await
+}
+
+#[tokio::test]
+async fn unanswered_keepalive() -> Result<()> {
+    let setup = Setup { timeouts: Some(SHORT), ..Default::default() };
+    let proxy = TestProxy::start_setup(setup).await?;
+    let (mut client, mut session) = proxy.join("Steve").await?;
+
+    // answered keepalives keep the player in game past the timeout
+    for id in 0..3 {
+        session.send(KeepAlive(id)).await?;
+        let keepalive: KeepAlive = within(client.expect()).await?;
+        client.send(keepalive).await?;
+        assert_eq!(within(session.expect::<KeepAlive>()).await?, KeepAlive(id));
+        tokio::time::sleep(Duration::from_millis(150)).await;
+    }
+
+    session.send(KeepAlive(3)).await?;
+    within(client.expect::<KeepAlive>()).await?;
+    let kick: Disconnect = within(client.expect()).await?;
+    assert_eq!(kick.reason, Component::text("Timed out"));
+    // and the backend connection is closed with it
+    assert!(within(session.recv_raw()).await.is_err());
+
+    proxy.proxy.shutdown().await
+}